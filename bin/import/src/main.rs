@@ -1,22 +1,43 @@
-use csv_entry_storage::entry_csv::EntryCSV;
+mod options;
+
+use csv_entry_storage::export::{self, ExportLayout};
+use csv_entry_storage::mapping::ColumnMapping;
+use csv_entry_storage::row::RowErrors;
+use csv_entry_storage::scan::CSVEntryScan;
 use csv_entry_storage::CSVEntryStorage;
+use csv_entry_storage::CSVEntryStorageError;
 use csv_entry_storage::PostalCodeCsvStorage;
+use memory_entry_storage::SnapshotWriter;
+use memory_index_storage::extended::MemoryIndexStoragePostal;
+use memory_index_storage::snapshot;
+use memory_index_storage::MemoryIndexStorage;
+use options::{Command, Options};
+use sled_db_entry_storage::extended::SledIndexStoragePostal;
+use sled_db_entry_storage::index::SledIndexStorage;
+use sled_db_entry_storage::schema::WITHOUT_INFORMATION_SCORE_VERSION;
 use sled_db_entry_storage::SledEntriesStorage;
+use sqlite_storage::extended::SqliteIndexStoragePostal;
+use sqlite_storage::index::SqliteIndexStorage;
+use sqlite_storage::SqliteStorage;
 
+use domain::business::diff::EntriesDiffer;
+use domain::business::domain::EntryDomain;
+use domain::business::error::EntryDomainError;
+use domain::business::rebuild::{is_shrunk, RebuiltIndexes};
+use domain::business::traits::EntryDomainTrait;
 use domain::core::entry::*;
-use domain::storage::traits::EntryStorageTrait;
+use domain::core::hierarchy::Hierarchy;
+use domain::storage::error::StorageError;
+use domain::storage::traits::{EntryStorageTrait, IndexStorageTrait};
 use serde::de::DeserializeOwned;
 use std::boxed::Box;
-use std::collections::{HashMap, HashSet};
-use std::fs;
-use std::fs::File;
-use std::fs::OpenOptions;
-use std::io::prelude::*;
-use std::io::BufReader;
-use std::io::{self};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
 use std::path::Path;
-use std::sync::{Arc, Mutex};
+use std::process;
 use std::time::Instant;
+use structopt::StructOpt;
 use thiserror::Error;
 
 //Define the possible errors
@@ -29,79 +50,787 @@ pub enum ImportError {
         #[from]
         source: std::io::Error,
     },
+    #[error("CSV error: {source}")]
+    Csv {
+        #[from]
+        source: CSVEntryStorageError,
+    },
+    #[error("Storage error: {source}")]
+    Storage {
+        #[from]
+        source: StorageError,
+    },
+    #[error("Serde Json error: {source}")]
+    SerdeJson {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("Thread pool error: {0}")]
+    ThreadPool(String),
+    #[error("{0} consistency errors between the entries and the indexes")]
+    Inconsistent(usize),
+    #[error("{0} entries have no territory, the migrate command stores it from the indexes")]
+    WithoutTerritory(usize),
+    #[error(
+        "{0} rebuilt indexes are empty or much smaller than the current ones, --force writes them"
+    )]
+    ShrunkIndexes(usize),
+    #[error("Domain error: {source}")]
+    Domain {
+        #[from]
+        source: EntryDomainError,
+    },
+}
+
+impl ImportError {
+    //1 for a failed import, 2 for an inconsistent database, 3 for an unreadable input. The input
+    //files are read by the CSV storages, the Io errors come from the outputs and the database.
+    fn get_exit_code(&self) -> i32 {
+        match self {
+            ImportError::Inconsistent(_) => 2,
+            ImportError::Csv { .. } => 3,
+            _ => 1,
+        }
+    }
 }
 
 //Define a generic error type to simplify return.
 pub type ImportResult<T> = std::result::Result<T, ImportError>;
 
-fn main() -> ImportResult<()> {
+fn main() {
+    let options = Options::from_args();
+    if let Err(error) = run(&options) {
+        eprintln!("Import failed: {}", error);
+        process::exit(error.get_exit_code());
+    }
+}
+
+fn run(options: &Options) -> ImportResult<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(options.threads)
+        .build_global()
+        .map_err(|error| ImportError::ThreadPool(error.to_string()))?;
+
+    match &options.command {
+        Command::Postal => import_postal(options),
+        Command::Entries { dry_run: true } | Command::All { dry_run: true } => {
+            let storage = get_entries_csv(options)?;
+            diff_entries(options, &storage, &scan_entries_csv(&storage)?)
+        }
+        Command::Entries { dry_run: false } => {
+            let storage = get_entries_csv(options)?;
+            import_entries(options, &storage, &scan_entries_csv(&storage)?)
+        }
+        Command::Indexes => import_indexes(options, &scan_entries_csv(&get_entries_csv(options)?)?),
+        Command::All { dry_run: false } => {
+            import_postal(options)?;
+            let storage = get_entries_csv(options)?;
+            let scan = scan_entries_csv(&storage)?;
+            import_indexes(options, &scan)?;
+            import_entries(options, &storage, &scan)
+        }
+        Command::Upsert => upsert(options),
+        Command::Migrate => migrate(options),
+        Command::RebuildIndexes { force } => rebuild_indexes(options, *force),
+        Command::Export { path, format } => export(options, path, format),
+        Command::Verify => verify(options),
+    }
+}
+
+fn import_postal(options: &Options) -> ImportResult<()> {
     let now = Instant::now();
-    let mut storage = PostalCodeCsvStorage::new("resources/postal.csv".to_string());
-    &storage.load();
+    fs::create_dir_all(options.get_indexes_path())?;
+    let mut storage = PostalCodeCsvStorage::new(options.postal_csv.to_string())
+        .with_delimiter(options.delimiter)
+        .with_encoding(options.encoding)
+        .with_sheet(options.sheet.clone())
+        .with_error_policy(options.on_error)
+        .with_columns(get_column_mapping(options)?.postal);
+    println!("Postal >> {}", storage.get_source()?);
+    let errors = storage.load()?;
 
     let iris_codes_postal_codes = &storage.get_iris_and_geoloc_with_postal_code();
-    serialize_index_to_file("postal".to_string(), iris_codes_postal_codes)?;
+    serialize_index_to_file(options, "postal", iris_codes_postal_codes)?;
+    MemoryIndexStoragePostal::write_fst(
+        format!("{}idx_postal.json", options.get_indexes_path()),
+        iris_codes_postal_codes,
+    )?;
+
+    let backend = Backend::open(options)?;
+    backend.create_postal_index(iris_codes_postal_codes)?;
+    backend.flush()?;
+
     println!("Postal >> Lines {:?}", iris_codes_postal_codes.len());
-    println!(
-        "Duration : {} seconds and {} nanoseconds",
-        now.elapsed().as_secs(),
-        now.elapsed().subsec_nanos()
-    );
+    print_row_errors("Postal", &errors);
+    print_duration(now);
+    Ok(())
+}
+
+fn get_entries_csv(options: &Options) -> ImportResult<CSVEntryStorage> {
+    Ok(CSVEntryStorage::new(options.entries_csv.to_string())
+        .with_delimiter(options.delimiter)
+        .with_encoding(options.encoding)
+        .with_sheet(options.sheet.clone())
+        .with_error_policy(options.on_error)
+        .with_columns(get_column_mapping(options)?.entries))
+}
+
+//Without a mapping file, the columns have their default headers.
+fn get_column_mapping(options: &Options) -> ImportResult<ColumnMapping> {
+    match &options.columns {
+        Some(path) => Ok(ColumnMapping::from_file(path)?),
+        None => Ok(ColumnMapping::default()),
+    }
+}
+
+//The averages of each row depend on all the rows, they are computed by a first pass over the file.
+fn scan_entries_csv(storage: &CSVEntryStorage) -> ImportResult<CSVEntryScan> {
+    let now = Instant::now();
+    println!("CSV >> {}", storage.get_source()?);
+    let scan = storage.scan()?;
+    println!("CSV >> Lines {:?}", scan.rows);
+    print_row_errors("CSV", &scan.errors);
+    print_duration(now);
+    Ok(scan)
+}
+
+fn import_indexes(options: &Options, scan: &CSVEntryScan) -> ImportResult<()> {
     let now = Instant::now();
-    let mut storage = CSVEntryStorage::new("resources/full.csv".to_string());
-    &storage.load();
+    fs::create_dir_all(options.get_indexes_path())?;
+    let backend = Backend::open(options)?;
 
     //CREATE INDEX FOR INSEE COM
-    let insee_com = &storage.get_insee_com_with_iris();
+    let insee_com = scan.get_insee_com_with_iris();
     println!("INSEE_COM >> Lines {:?}", insee_com.len());
-    serialize_index_to_file("insee_coms".to_string(), insee_com);
+    write_index(options, &backend, "insee_coms", insee_com)?;
 
     //CREATE INDEX FOR REGIONS
-    let reg_iris = &storage.get_regions_with_iris();
+    let reg_iris = scan.get_regions_with_iris();
     println!("REG_IRIS >> Lines {:?}", reg_iris.len());
-    serialize_index_to_file("regions".to_string(), reg_iris);
+    write_index(options, &backend, "regions", reg_iris)?;
 
     //CREATE INDEX FOR DEPARTEMENTS
-    let dep_iris = &storage.get_departements_with_iris();
+    let dep_iris = scan.get_departements_with_iris();
     println!("DEP_IRIS >> Lines {:?}", dep_iris.len());
-    serialize_index_to_file("departments".to_string(), dep_iris);
+    write_index(options, &backend, "departments", dep_iris)?;
 
-    //CREATE INDEX FOR NATIONAL ENTRIES
-    let db: Box<dyn EntryStorageTrait> = Box::new(SledEntriesStorage::new("database".to_string()));
+    //CREATE INDEX FOR DEPARTMENTS BY REGION AND THE HIERARCHY
+    let hierarchy = scan.get_hierarchy();
+    let departments_by_region = hierarchy.get_departments_by_region();
+    println!("DEP_BY_REG >> Lines {:?}", departments_by_region.len());
+    write_index(
+        options,
+        &backend,
+        "departments_by_region",
+        &departments_by_region,
+    )?;
+    write_hierarchy(options, hierarchy)?;
 
-    for entry_csv in &storage.get_entries() {
-        let iris_code = entry_csv.iris_code.as_ref().unwrap();
-        db.create(iris_code.to_string(), entry_csv.clone());
+    backend.flush()?;
+    print_duration(now);
+    Ok(())
+}
+
+//Convert and write the entries while the file is read, the snapshot is written along the database.
+fn import_entries(
+    options: &Options,
+    storage: &CSVEntryStorage,
+    scan: &CSVEntryScan,
+) -> ImportResult<()> {
+    let now = Instant::now();
+    let stats = scan.get_stats();
+    let backend = Backend::open(options)?;
+    let mut snapshot = SnapshotWriter::create(options.get_entry_snapshot_path())?;
+    let mut count = 0;
+
+    //The database and the snapshot are written at the same time, each in the order of the file.
+    for batch in storage.iter_entry_batches(&stats)? {
+        let batch = batch?;
+        let (created, written) = rayon::join(
+            || backend.create_entries(&batch),
+            || write_snapshot_entries(&mut snapshot, &batch),
+        );
+        created?;
+        written?;
+        count += batch.len();
     }
 
-    let all_db_entries = db.get_all().unwrap();
-    println!("--> {:?}", all_db_entries.len());
+    snapshot.finish()?;
+    backend.flush()?;
+    println!("ENTRIES >> Lines {:?}", count);
+    print_duration(now);
+    Ok(())
+}
+
+//Report what the import of the entries would change in the database, nothing is written.
+fn diff_entries(
+    options: &Options,
+    storage: &CSVEntryStorage,
+    scan: &CSVEntryScan,
+) -> ImportResult<()> {
+    let now = Instant::now();
+    let stats = scan.get_stats();
+    let entry_storage = Backend::open_existing(options)?.into_entry_storage();
+    let mut differ = EntriesDiffer::new(&*entry_storage);
+    for batch in storage.iter_entry_batches(&stats)? {
+        differ.compare(&batch?)?;
+    }
+
+    let diff = differ.finish()?;
+    for iris_code in &diff.added {
+        println!("ADDED >> {}", iris_code);
+    }
+    for iris_code in &diff.kept {
+        println!("KEPT >> {} absent from the input", iris_code);
+    }
+    for entry in &diff.modified {
+        for field in &entry.fields {
+            println!(
+                "MODIFIED >> {} {}: {} -> {}",
+                entry.iris_code, field.field, field.before, field.after
+            );
+        }
+    }
+    println!(
+        "DIFF >> {} added, {} kept, {} modified, {} unchanged",
+        diff.added.len(),
+        diff.kept.len(),
+        diff.modified.len(),
+        diff.unchanged
+    );
+    print_duration(now);
+    Ok(())
+}
 
-    serialize_index_to_file("here".to_string(), &all_db_entries);
+fn write_snapshot_entries(snapshot: &mut SnapshotWriter, entries: &[Entry]) -> ImportResult<()> {
+    for entry in entries {
+        if let Some(iris_code) = &entry.iris_code {
+            snapshot.write(iris_code, entry)?;
+        }
+    }
+    Ok(())
+}
 
+//Replace the entries of the partial CSV and move them in the indexes, only the averages of their
+//territories and the national ones are recomputed. The JSON and binary indexes and the memory
+//snapshot are then written again from the database.
+fn upsert(options: &Options) -> ImportResult<()> {
+    let now = Instant::now();
+    let mut storage = get_entries_csv(options)?;
+    let errors = storage.load()?;
+    let entries = storage.get_upsert_entries();
+    println!("CSV >> Lines {:?}", entries.len());
+    print_row_errors("CSV", &errors);
+
+    let backend = Backend::open_existing(options)?;
+    let domain = backend.clone().into_domain();
+    let report = domain.upsert(&entries)?;
+    println!(
+        "UPSERT >> {} added, {} replaced, averages of {} regions and {} departments, {} entries refreshed",
+        report.added, report.replaced, report.regions, report.departments, report.refreshed
+    );
+
+    fs::create_dir_all(options.get_indexes_path())?;
+    for (name, index) in &[
+        ("regions", &domain.idx_regions),
+        ("departments", &domain.idx_departments),
+        ("insee_coms", &domain.idx_insee_coms),
+        ("departments_by_region", &domain.idx_departments_by_region),
+    ] {
+        let index = read_index(&***index)?;
+        serialize_index_to_file(options, name, &index)?;
+        serialize_index_to_snapshot(options, name, &index)?;
+    }
+    let mut postal: BTreeMap<String, Iris> = BTreeMap::new();
+    for key in domain.idx_cities.get_all_keys()? {
+        if let Some(iris) = domain.idx_cities.get_index(key.to_string())? {
+            postal.insert(key, iris);
+        }
+    }
+    serialize_index_to_file(options, "postal", &postal)?;
+    MemoryIndexStoragePostal::write_fst(
+        format!("{}idx_postal.json", options.get_indexes_path()),
+        &postal,
+    )?;
+
+    update_hierarchy(options, &storage.get_hierarchy())?;
+
+    let mut snapshot = SnapshotWriter::create(options.get_entry_snapshot_path())?;
+    for entry in domain.entry_datastore.iter_entries()? {
+        let entry = entry?;
+        if let Some(iris_code) = &entry.iris_code {
+            snapshot.write(iris_code, &entry)?;
+        }
+    }
+    snapshot.finish()?;
+
+    backend.flush()?;
+    print_duration(now);
+    Ok(())
+}
+
+//Replace the IRIS codes of the extract in the hierarchy written by the last import of the indexes.
+fn update_hierarchy(options: &Options, extract: &Hierarchy) -> ImportResult<()> {
+    let mut hierarchy = match read_hierarchy(options)? {
+        Some(hierarchy) => hierarchy,
+        None => {
+            println!("HIERARCHY >> No idx_hierarchy.json, not updated");
+            return Ok(());
+        }
+    };
+    hierarchy.remove(&extract.get_iris_codes());
+    hierarchy.merge(extract);
+    write_hierarchy(options, &hierarchy)
+}
+
+//Hierarchy written by the last import of the indexes, if any.
+fn read_hierarchy(options: &Options) -> ImportResult<Option<Hierarchy>> {
+    let path = format!("{}idx_hierarchy.json", options.get_indexes_path());
+    if !Path::new(&path).exists() {
+        return Ok(None);
+    }
+    let regions = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    Ok(Some(Hierarchy::from_regions(regions)))
+}
+
+//Write the hierarchy as JSON and as a binary snapshot, the IRIS codes attached to another parent
+//than the one of their row are reported.
+fn write_hierarchy(options: &Options, hierarchy: &Hierarchy) -> ImportResult<()> {
+    let conflicts = hierarchy.get_conflicts();
+    for conflict in conflicts.iter().take(PRINTED_ROW_ERRORS) {
+        eprintln!(
+            "{}: {} kept in {}, not in {}",
+            conflict.iris_code, conflict.territory, conflict.parent, conflict.ignored_parent
+        );
+    }
+    if conflicts.len() > PRINTED_ROW_ERRORS {
+        eprintln!("... {} more", conflicts.len() - PRINTED_ROW_ERRORS);
+    }
+    println!(
+        "HIERARCHY >> Regions {:?}, conflicts {:?}",
+        hierarchy.get_regions().len(),
+        conflicts.len()
+    );
+    serialize_index_to_file(options, "hierarchy", hierarchy.get_regions())?;
+    serialize_index_to_snapshot(options, "hierarchy", hierarchy.get_regions())
+}
+
+fn read_index(index: &dyn IndexStorageTrait) -> ImportResult<BTreeMap<String, Vec<String>>> {
+    let mut values = BTreeMap::new();
+    for key in index.get_all_keys()? {
+        if let Some(index_values) = index.get_index(key.to_string())? {
+            values.insert(key, index_values);
+        }
+    }
+    Ok(values)
+}
+
+//Upgrade in place the entries of the sled database up to the last schema version without the
+//information score, the SQLite table is upgraded when it is opened. The entries imported without
+//their territory get it from the indexes of the database and the EPCI of the hierarchy. The
+//information score is only brought by a new import of the entries.
+fn migrate(options: &Options) -> ImportResult<()> {
+    let now = Instant::now();
+    let backend = Backend::open_existing(options)?;
+    if let Backend::Sled(db) = &backend {
+        let migrated = db.migrate()?;
+        println!(
+            "MIGRATE >> {} entries upgraded to the schema version {}",
+            migrated, WITHOUT_INFORMATION_SCORE_VERSION
+        );
+    }
+
+    let epcis = match read_hierarchy(options)? {
+        Some(hierarchy) => hierarchy.get_epcis(),
+        None => {
+            println!("MIGRATE >> No idx_hierarchy.json, the EPCI of the territories is left empty");
+            HashMap::new()
+        }
+    };
+    let filled = backend.clone().into_domain().fill_territories(&epcis)?;
+    println!("MIGRATE >> {} entries given their territory", filled);
+    let outdated = backend
+        .clone()
+        .into_entry_storage()
+        .count_outdated_entries()?;
+    if outdated > 0 {
+        println!(
+            "MIGRATE >> {} entries without the information score, import the entries again",
+            outdated
+        );
+    }
+    backend.flush()?;
+    print_duration(now);
+
+    Ok(())
+}
+
+//Write again the territory indexes and the hierarchy from the territories stored in the entries,
+//when their files or their copy in the database are lost. The postal codes index is not rebuilt.
+fn rebuild_indexes(options: &Options, force: bool) -> ImportResult<()> {
+    let now = Instant::now();
+    let backend = Backend::open_existing(options)?;
+    let indexes = RebuiltIndexes::from_storage(&*backend.clone().into_entry_storage())?;
+    println!("ENTRIES >> Lines {:?}", indexes.entries);
+
+    //The indexes would leave out these entries, nothing is written.
+    if !indexes.without_territory.is_empty() {
+        for iris_code in indexes.without_territory.iter().take(PRINTED_ROW_ERRORS) {
+            eprintln!("{}: no territory", iris_code);
+        }
+        if indexes.without_territory.len() > PRINTED_ROW_ERRORS {
+            eprintln!(
+                "... {} more",
+                indexes.without_territory.len() - PRINTED_ROW_ERRORS
+            );
+        }
+        return Err(ImportError::WithoutTerritory(
+            indexes.without_territory.len(),
+        ));
+    }
+
+    //Empty or truncated indexes must not replace good ones.
+    let domain = backend.clone().into_domain();
+    let mut shrunk = 0;
+    for (name, size) in indexes.get_sizes() {
+        let current = match name {
+            "hierarchy" => read_hierarchy(options)
+                .ok()
+                .flatten()
+                .map(|hierarchy| hierarchy.get_iris_codes().len()),
+            _ => get_current_index_size(options, &domain, name),
+        };
+        if is_shrunk(size, current) {
+            eprintln!(
+                "{}: {} values rebuilt, {} in the current index",
+                name,
+                size,
+                current.unwrap_or(0)
+            );
+            shrunk += 1;
+        }
+    }
+    if shrunk > 0 && !force {
+        return Err(ImportError::ShrunkIndexes(shrunk));
+    }
+
+    fs::create_dir_all(options.get_indexes_path())?;
+    println!("INSEE_COM >> Lines {:?}", indexes.insee_coms.len());
+    write_index(options, &backend, "insee_coms", &indexes.insee_coms)?;
+    println!("REG_IRIS >> Lines {:?}", indexes.regions.len());
+    write_index(options, &backend, "regions", &indexes.regions)?;
+    println!("DEP_IRIS >> Lines {:?}", indexes.departments.len());
+    write_index(options, &backend, "departments", &indexes.departments)?;
+
+    let departments_by_region = indexes.hierarchy.get_departments_by_region();
+    println!("DEP_BY_REG >> Lines {:?}", departments_by_region.len());
+    write_index(
+        options,
+        &backend,
+        "departments_by_region",
+        &departments_by_region,
+    )?;
+    write_hierarchy(options, &indexes.hierarchy)?;
+
+    backend.flush()?;
+    print_duration(now);
+    Ok(())
+}
+
+//Number of values of the largest copy of an index, in the database or in its JSON file. None when
+//both are lost or empty.
+fn get_current_index_size(options: &Options, domain: &EntryDomain, name: &str) -> Option<usize> {
+    let stored: &dyn IndexStorageTrait = match name {
+        "regions" => &*domain.idx_regions,
+        "departments" => &*domain.idx_departments,
+        "insee_coms" => &*domain.idx_insee_coms,
+        _ => &*domain.idx_departments_by_region,
+    };
+    let mut sizes = Vec::new();
+    if let Ok(values) = stored.get_all_values() {
+        sizes.push(values.len());
+    }
+    let path = format!("{}idx_{}.json", options.get_indexes_path(), name);
+    if let Ok(values) = MemoryIndexStorage::new(path).and_then(|file| file.get_all_values()) {
+        sizes.push(values.len());
+    }
+    sizes.into_iter().max().filter(|size| *size > 0)
+}
+
+//Write all the imported entries, flattened with their territory keys, to a Parquet or CSV file.
+fn export(options: &Options, path: &Option<String>, format: &str) -> ImportResult<()> {
+    let now = Instant::now();
+    let path = path
+        .clone()
+        .unwrap_or_else(|| options.get_export_path(format));
+    let domain = Backend::open_existing(options)?.into_domain();
+    let entries = domain.iter_flat_entries()?;
+    let count = match format {
+        "parquet" => parquet_storage::write_entries(File::create(&path)?, entries)?,
+        _ => {
+            let layout = match format {
+                "csv" => ExportLayout::Source,
+                _ => ExportLayout::Flat,
+            };
+            export::write_entries(
+                BufWriter::new(File::create(&path)?),
+                entries.map(|entry| entry.map_err(ImportError::from)),
+                layout,
+                options.delimiter.unwrap_or_else(|| layout.get_delimiter()),
+                &get_column_mapping(options)?.entries,
+            )?
+        }
+    };
+    println!("EXPORT >> {} entries written to {}", count, path);
+    print_duration(now);
+
+    Ok(())
+}
+
+//Check the JSON indexes against the imported entries, report the orphans and the missing links.
+fn verify(options: &Options) -> ImportResult<()> {
+    let now = Instant::now();
+    let index = |name: &str| -> ImportResult<Box<MemoryIndexStorage>> {
+        let path = format!("{}idx_{}.json", options.get_indexes_path(), name);
+        Ok(Box::new(MemoryIndexStorage::new(path)?))
+    };
+    let domain = EntryDomain::new(
+        index("regions")?,
+        index("departments")?,
+        Box::new(MemoryIndexStoragePostal::new(format!(
+            "{}idx_postal.json",
+            options.get_indexes_path()
+        ))?),
+        index("insee_coms")?,
+        index("departments_by_region")?,
+        Backend::open_existing(options)?.into_entry_storage(),
+    );
+
+    let report = domain.verify()?;
+    for link in &report.missing_links {
+        println!(
+            "MISSING >> idx_{} [{}] references {}",
+            link.index, link.key, link.value
+        );
+    }
+    for orphan in &report.orphans {
+        println!("ORPHAN >> {} is not in idx_{}", orphan.value, orphan.index);
+    }
+    println!(
+        "VERIFY >> {} entries, {} missing links, {} orphans",
+        report.entries,
+        report.missing_links.len(),
+        report.orphans.len()
+    );
+    print_duration(now);
+
+    match report.is_consistent() {
+        true => Ok(()),
+        false => Err(ImportError::Inconsistent(
+            report.missing_links.len() + report.orphans.len(),
+        )),
+    }
+}
+
+//Database written by the import, the entries and the indexes in the same storage.
+#[derive(Clone)]
+enum Backend {
+    Sled(SledEntriesStorage),
+    Sqlite(SqliteStorage),
+}
+
+impl Backend {
+    fn open(options: &Options) -> ImportResult<Self> {
+        Ok(match options.backend.as_str() {
+            "sqlite" => Backend::Sqlite(SqliteStorage::new(options.get_sqlite_db_path())?),
+            _ => Backend::Sled(SledEntriesStorage::open(options.get_database_path())?),
+        })
+    }
+
+    //Database of a previous import, it is not created when it does not exist.
+    fn open_existing(options: &Options) -> ImportResult<Self> {
+        let path = match options.backend.as_str() {
+            "sqlite" => options.get_sqlite_db_path(),
+            _ => options.get_database_path(),
+        };
+        if !Path::new(&path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no database at {}", path),
+            )
+            .into());
+        }
+        Backend::open(options)
+    }
+
+    fn create_index(&self, name: &str, index: &BTreeMap<String, Vec<String>>) -> ImportResult<()> {
+        match self {
+            Backend::Sqlite(sqlite) => sqlite.create_index(name, index)?,
+            Backend::Sled(db) => SledIndexStorage::new(db, name.to_string()).create_index(index)?,
+        }
+        Ok(())
+    }
+
+    fn create_postal_index(&self, index: &BTreeMap<String, Iris>) -> ImportResult<()> {
+        match self {
+            Backend::Sqlite(sqlite) => sqlite.create_postal_index(index)?,
+            Backend::Sled(db) => SledIndexStoragePostal::new(db).create_index(index)?,
+        }
+        Ok(())
+    }
+
+    fn create_entries(&self, entries: &[Entry]) -> ImportResult<()> {
+        match self {
+            Backend::Sqlite(sqlite) => sqlite.create_entries(entries)?,
+            Backend::Sled(db) => {
+                for entry in entries {
+                    let iris_code = match &entry.iris_code {
+                        Some(iris_code) => iris_code,
+                        None => return Err(StorageError::CreationImpossible.into()),
+                    };
+                    db.create(iris_code.to_string(), entry.clone())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> ImportResult<()> {
+        if let Backend::Sled(db) = self {
+            db.flush()?;
+        }
+        Ok(())
+    }
+
+    fn into_entry_storage(self) -> Box<dyn EntryStorageTrait> {
+        match self {
+            Backend::Sqlite(sqlite) => Box::new(sqlite),
+            Backend::Sled(db) => Box::new(db),
+        }
+    }
+
+    //Domain on the entries and indexes written by a previous import.
+    fn into_domain(self) -> EntryDomain {
+        match self {
+            Backend::Sqlite(sqlite) => {
+                let index =
+                    |name: &str| Box::new(SqliteIndexStorage::new(&sqlite, name.to_string()));
+                EntryDomain::new(
+                    index("regions"),
+                    index("departments"),
+                    Box::new(SqliteIndexStoragePostal::new(&sqlite)),
+                    index("insee_coms"),
+                    index("departments_by_region"),
+                    Box::new(sqlite),
+                )
+            }
+            Backend::Sled(db) => {
+                let index = |name: &str| Box::new(SledIndexStorage::new(&db, name.to_string()));
+                EntryDomain::new(
+                    index("regions"),
+                    index("departments"),
+                    Box::new(SledIndexStoragePostal::new(&db)),
+                    index("insee_coms"),
+                    index("departments_by_region"),
+                    Box::new(db.clone()),
+                )
+            }
+        }
+    }
+}
+
+//Only the first errors are printed, all of them are counted.
+const PRINTED_ROW_ERRORS: usize = 20;
+
+fn print_row_errors(name: &str, errors: &RowErrors) {
+    if errors.errors.is_empty() {
+        return;
+    }
+    for error in errors.errors.iter().take(PRINTED_ROW_ERRORS) {
+        eprintln!("{}", error);
+    }
+    if errors.errors.len() > PRINTED_ROW_ERRORS {
+        eprintln!("... {} more", errors.errors.len() - PRINTED_ROW_ERRORS);
+    }
+    println!(
+        "{} >> Invalid values {:?}, skipped rows {:?}",
+        name,
+        errors.errors.len(),
+        errors.skipped
+    );
+}
+
+fn print_duration(now: Instant) {
     println!(
         "Duration : {} seconds and {} nanoseconds",
         now.elapsed().as_secs(),
         now.elapsed().subsec_nanos()
     );
+}
+
+//Write the index as JSON, as a binary snapshot and in the database.
+fn write_index(
+    options: &Options,
+    backend: &Backend,
+    name: &str,
+    index: &BTreeMap<String, Vec<String>>,
+) -> ImportResult<()> {
+    serialize_index_to_file(options, name, index)?;
+    serialize_index_to_snapshot(options, name, index)?;
+    backend.create_index(name, index)
+}
+
+fn serialize_index_to_snapshot<T: serde::Serialize>(
+    options: &Options,
+    name: &str,
+    value: &T,
+) -> ImportResult<()> {
+    let path = format!("{}idx_{}.bin", options.get_indexes_path(), name);
+    snapshot::write_snapshot(&path, value)?;
 
     Ok(())
 }
 
 fn serialize_index_to_file<T: DeserializeOwned + serde::Serialize>(
-    name: String,
+    options: &Options,
+    name: &str,
     value: &T,
 ) -> ImportResult<()> {
-    let path = format!("resources/indexes/idx_{}.json", name);
-    let path = Path::new(&path);
+    let path = format!("{}idx_{}.json", options.get_indexes_path(), name);
+    let file = File::create(path)?;
+    serde_json::to_writer(file, &value)?;
 
-    fs::remove_file(path);
-    let file = match path.exists() {
-        true => OpenOptions::new().write(true).open(path)?,
-        false => File::create(path)?,
-    };
+    Ok(())
+}
 
-    let result = serde_json::to_writer(file, &value);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn commands_on_a_missing_database_fail_with_exit_code_1() {
+        let directory = std::env::temp_dir().join(format!("import-no-db-{}", process::id()));
+        let database = directory.join("database").to_string_lossy().to_string();
+        let indexes = directory.join("indexes").to_string_lossy().to_string();
+
+        for command in &["export", "verify", "migrate"] {
+            let options = Options::from_iter(&[
+                "import",
+                "--backend",
+                "sled",
+                "--database",
+                &database,
+                "--indexes",
+                &indexes,
+                command,
+            ]);
+            let error = run(&options).unwrap_err();
+            assert_eq!(error.get_exit_code(), 1, "{}: {}", command, error);
+            assert!(
+                !Path::new(&database).exists(),
+                "{} created the database",
+                command
+            );
+        }
+    }
 }
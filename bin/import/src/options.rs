@@ -0,0 +1,223 @@
+use csv_entry_storage::row::ErrorPolicy;
+use csv_entry_storage::source::parse_encoding;
+use encoding_rs::Encoding;
+use std::env;
+use structopt::StructOpt;
+
+pub const RESOURCES_INDEXES_PATH: &str = "resources/indexes/";
+pub const DATASETS_PATH: &str = "datasets/";
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "import",
+    about = "Feed the indexes and the database from the CSV files."
+)]
+pub struct Options {
+    #[structopt(
+        long,
+        global = true,
+        default_value = "resources/postal.csv",
+        help = "Postal codes CSV file"
+    )]
+    pub postal_csv: String,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "resources/full.csv",
+        help = "Entries CSV file"
+    )]
+    pub entries_csv: String,
+    #[structopt(
+        long,
+        global = true,
+        parse(try_from_str = parse_delimiter),
+        help = "Field delimiter of the CSV files, a single character or `tab`, detected from the header line by default"
+    )]
+    pub delimiter: Option<u8>,
+    #[structopt(
+        long,
+        global = true,
+        parse(try_from_str = parse_encoding),
+        help = "Encoding of the CSV files (utf-8, windows-1252, latin1, ...), detected from the BOM or the content by default"
+    )]
+    pub encoding: Option<&'static Encoding>,
+    #[structopt(
+        long,
+        global = true,
+        help = "Sheet read when an input file is a workbook (.xlsx, .xls, .ods), the first one by default"
+    )]
+    pub sheet: Option<String>,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "fail",
+        possible_values = &["fail", "skip", "null"],
+        help = "Invalid rows: stop the import, leave them out or import them without the invalid values"
+    )]
+    pub on_error: ErrorPolicy,
+    #[structopt(
+        long,
+        global = true,
+        env = "IMPORT_COLUMNS",
+        help = "TOML or JSON file mapping the expected column headers to the headers of the CSV files"
+    )]
+    pub columns: Option<String>,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "1",
+        help = "Worker threads of the import, 0 for one per CPU"
+    )]
+    pub threads: usize,
+    #[structopt(
+        long,
+        global = true,
+        env = "IMPORT_BACKEND",
+        default_value = "sled",
+        possible_values = &["sled", "sqlite"],
+        help = "Database written by the import"
+    )]
+    pub backend: String,
+    #[structopt(
+        long,
+        global = true,
+        env = "IMPORT_DATASET",
+        help = "Write all the outputs in datasets/<name>/, to be activated on a running API"
+    )]
+    pub dataset: Option<String>,
+    #[structopt(long, global = true, help = "Sled database directory")]
+    pub database: Option<String>,
+    #[structopt(long, global = true, help = "SQLite database file")]
+    pub sqlite_db: Option<String>,
+    #[structopt(
+        long,
+        global = true,
+        help = "Output directory of the JSON and binary indexes"
+    )]
+    pub indexes: Option<String>,
+    #[structopt(
+        long,
+        global = true,
+        help = "Output file of the snapshot of the memory entries"
+    )]
+    pub entry_snapshot: Option<String>,
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    #[structopt(about = "Import the postal codes index")]
+    Postal,
+    #[structopt(about = "Import the entries in the database and the memory snapshot")]
+    Entries {
+        #[structopt(
+            long,
+            help = "Compare the converted entries with the database instead of writing anything"
+        )]
+        dry_run: bool,
+    },
+    #[structopt(about = "Import the territory indexes and the hierarchy")]
+    Indexes,
+    #[structopt(about = "Import the postal codes, the indexes and the entries")]
+    All {
+        #[structopt(
+            long,
+            help = "Compare the converted entries with the database instead of writing anything"
+        )]
+        dry_run: bool,
+    },
+    #[structopt(
+        about = "Merge a partial entries CSV, like a department extract, into the imported database"
+    )]
+    Upsert,
+    #[structopt(
+        about = "Upgrade in place the entries to the current schema version and store their territory"
+    )]
+    Migrate,
+    #[structopt(
+        about = "Rebuild the territory indexes and the hierarchy from the territories of the entries"
+    )]
+    RebuildIndexes {
+        #[structopt(
+            long,
+            help = "Write the indexes even when they are empty or much smaller than the current ones"
+        )]
+        force: bool,
+    },
+    #[structopt(
+        about = "Export the entries, flattened with their territory keys, to Parquet or CSV"
+    )]
+    Export {
+        #[structopt(help = "Output file, entries.parquet or entries.csv by default")]
+        path: Option<String>,
+        #[structopt(
+            long,
+            default_value = "parquet",
+            possible_values = &["parquet", "csv", "flat-csv"],
+            help = "Parquet, CSV in the layout of the entries CSV, or CSV with the Parquet columns"
+        )]
+        format: String,
+    },
+    #[structopt(about = "Check the JSON indexes against the imported entries")]
+    Verify,
+}
+
+impl Options {
+    //The explicit paths win over the dataset directory.
+    fn get_path(&self, path: &Option<String>, dataset_path: &str, default: String) -> String {
+        match (path, &self.dataset) {
+            (Some(path), _) => path.to_string(),
+            (None, Some(dataset)) => format!("{}{}/{}", DATASETS_PATH, dataset, dataset_path),
+            (None, None) => default,
+        }
+    }
+
+    pub fn get_database_path(&self) -> String {
+        self.get_path(&self.database, "database", "database".to_string())
+    }
+
+    pub fn get_sqlite_db_path(&self) -> String {
+        self.get_path(
+            &self.sqlite_db,
+            "database.sqlite",
+            env::var("SQLITE_DB_PATH").unwrap_or("database.sqlite".to_string()),
+        )
+    }
+
+    pub fn get_indexes_path(&self) -> String {
+        let path = self.get_path(
+            &self.indexes,
+            "indexes/",
+            RESOURCES_INDEXES_PATH.to_string(),
+        );
+        match path.ends_with('/') {
+            true => path,
+            false => format!("{}/", path),
+        }
+    }
+
+    pub fn get_entry_snapshot_path(&self) -> String {
+        self.get_path(
+            &self.entry_snapshot,
+            "entries.cbor",
+            "resources/entries.cbor".to_string(),
+        )
+    }
+
+    pub fn get_export_path(&self, format: &str) -> String {
+        let file = match format {
+            "parquet" => "entries.parquet",
+            _ => "entries.csv",
+        };
+        self.get_path(&None, file, file.to_string())
+    }
+}
+
+fn parse_delimiter(value: &str) -> Result<u8, String> {
+    match value {
+        "tab" | "\\t" => Ok(b'\t'),
+        _ if value.len() == 1 && value.is_ascii() => Ok(value.as_bytes()[0]),
+        _ => Err(format!("'{}' is not a single ASCII character", value)),
+    }
+}
@@ -1,4 +1,13 @@
 use std::env;
+use thiserror::Error;
+
+//Variable of the configuration whose value cannot be read.
+#[derive(Error, Debug)]
+#[error("{name} is not valid: '{value}'")]
+pub struct ConfigurationError {
+    pub name: String,
+    pub value: String,
+}
 
 pub struct Configuration {}
 
@@ -52,7 +61,61 @@ impl Configuration {
         env::var("SLED_DB_PATH").expect("SLED_DB_PATH not found.")
     }
 
+    pub fn get_entry_storage() -> String {
+        env::var("ENTRY_STORAGE").unwrap_or("sled".to_string())
+    }
+
+    pub fn get_entry_snapshot_path() -> String {
+        env::var("ENTRY_SNAPSHOT_PATH").expect("ENTRY_SNAPSHOT_PATH not found.")
+    }
+
+    pub fn get_sqlite_db_path() -> String {
+        env::var("SQLITE_DB_PATH").expect("SQLITE_DB_PATH not found.")
+    }
+
+    pub fn get_index_storage() -> String {
+        env::var("INDEX_STORAGE").unwrap_or("json".to_string())
+    }
+
     pub fn get_index_path() -> String {
         env::var("INDEX_PATH").expect("INDEX_PATH not found.")
     }
+
+    //Read once at startup: the API does not start without a token for the write requests.
+    pub fn get_bearer_token() -> String {
+        let token = env::var("BEARER_TOKEN").expect("BEARER_TOKEN not found.");
+        if token.trim().is_empty() {
+            panic!("BEARER_TOKEN is empty.");
+        }
+        token
+    }
+
+    //Dataset of `DATASETS_PATH` loaded at startup, the configured paths are used otherwise.
+    pub fn get_dataset() -> Option<String> {
+        env::var("DATASET").ok()
+    }
+
+    pub fn get_datasets_path() -> String {
+        env::var("DATASETS_PATH").unwrap_or("./datasets/".to_string())
+    }
+
+    //Number of cached reads for each storage, 0 disables the caches.
+    pub fn get_cache_capacity() -> Result<usize, ConfigurationError> {
+        Configuration::parse_var("CACHE_CAPACITY", 0)
+    }
+
+    //Lifetime of the cached reads, in seconds.
+    pub fn get_cache_ttl() -> Result<u64, ConfigurationError> {
+        Configuration::parse_var("CACHE_TTL", 300)
+    }
+
+    fn parse_var<T: std::str::FromStr>(name: &str, default: T) -> Result<T, ConfigurationError> {
+        match env::var(name) {
+            Ok(value) => value.trim().parse::<T>().map_err(|_| ConfigurationError {
+                name: name.to_string(),
+                value,
+            }),
+            Err(_) => Ok(default),
+        }
+    }
 }
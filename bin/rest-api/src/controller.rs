@@ -1,7 +1,21 @@
-use crate::state::AppState;
-use actix_web::web::Data;
+use crate::state::{AppState, Dataset};
+use actix_web::error::BlockingError;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::{Bytes, Data};
 use actix_web::{web, HttpRequest, HttpResponse};
+use domain::business::error::EntryDomainError;
+use domain::core::entry::{CityNameSearch, Entry};
+use domain::storage::error::StorageError;
+use futures::channel::mpsc::channel;
+use futures::executor::block_on;
+use futures::SinkExt;
+use serde_json::Value;
+use std::io::Error;
 use std::sync::{Arc, Mutex};
+use std::thread;
+
+//Number of NDJSON lines buffered between the storage and the client.
+const NDJSON_BUFFER_SIZE: usize = 64;
 
 #[derive(Deserialize)]
 pub struct SearchQuery {
@@ -9,6 +23,47 @@ pub struct SearchQuery {
     q: Option<String>,
 }
 
+//One of the queries on the commune names: `prefix`, `start` and `end`, `regex` or `fuzzy` with
+//an optional `distance` (1 by default).
+#[derive(Deserialize)]
+pub struct CityNameQuery {
+    prefix: Option<String>,
+    start: Option<String>,
+    end: Option<String>,
+    regex: Option<String>,
+    fuzzy: Option<String>,
+    distance: Option<u32>,
+}
+
+impl CityNameQuery {
+    fn get_search(&self) -> Option<CityNameSearch> {
+        match self {
+            CityNameQuery {
+                prefix: Some(prefix),
+                ..
+            } => Some(CityNameSearch::Prefix(prefix.to_string())),
+            CityNameQuery {
+                start: Some(start),
+                end: Some(end),
+                ..
+            } => Some(CityNameSearch::Range(start.to_string(), end.to_string())),
+            CityNameQuery {
+                regex: Some(pattern),
+                ..
+            } => Some(CityNameSearch::Regex(pattern.to_string())),
+            CityNameQuery {
+                fuzzy: Some(query),
+                distance,
+                ..
+            } => Some(CityNameSearch::Fuzzy(
+                query.to_string(),
+                distance.unwrap_or(1),
+            )),
+            _ => None,
+        }
+    }
+}
+
 pub fn healthcheck(_req: HttpRequest) -> HttpResponse {
     HttpResponse::Ok().body("Everything's fine.")
 }
@@ -22,12 +77,69 @@ pub struct PageParam {
     page: i32,
 }
 
+//Stream all the entries as NDJSON, one entry per line.
 pub fn entries_get_all(wrap_state: Data<Arc<Mutex<AppState>>>, _req: HttpRequest) -> HttpResponse {
-    let state = wrap_state.lock().unwrap();
-    let domain = state.get_domain();
+    let domain = wrap_state.lock().unwrap().get_domain().clone();
+    let (mut sender, receiver) = channel::<Result<Bytes, Error>>(NDJSON_BUFFER_SIZE);
+
+    thread::spawn(move || {
+        let entries = match domain.iter_all() {
+            Ok(entries) => entries,
+            Err(error) => {
+                let _ = block_on(sender.send(Err(Error::other(error.to_string()))));
+                return;
+            }
+        };
+
+        for entry in entries {
+            let line = match entry {
+                Ok(entry) => match serde_json::to_vec(&entry) {
+                    Ok(mut line) => {
+                        line.push(b'\n');
+                        Ok(Bytes::from(line))
+                    }
+                    Err(error) => Err(Error::other(error.to_string())),
+                },
+                Err(error) => Err(Error::other(error.to_string())),
+            };
+            let failed = line.is_err();
+
+            //Stop when the client is gone or after an error.
+            if block_on(sender.send(line)).is_err() || failed {
+                return;
+            }
+        }
+    });
 
-    match domain.get_all() {
-        Ok(entries) => HttpResponse::Ok().json(entries),
+    HttpResponse::Ok()
+        .content_type("application/x-ndjson")
+        .streaming(receiver)
+}
+
+//Export all the entries, flattened with their territory keys, as a Parquet file. The file is
+//written on the blocking thread pool, the workers keep serving the other requests.
+pub async fn entries_get_parquet(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    _req: HttpRequest,
+) -> HttpResponse {
+    let domain = wrap_state.lock().unwrap().get_domain().clone();
+    let written = web::block(move || {
+        let mut file: Vec<u8> = Vec::new();
+        domain
+            .iter_flat_entries()
+            .and_then(|entries| parquet_storage::write_entries(&mut file, entries))
+            .map(|_| file)
+    })
+    .await;
+
+    match written {
+        Ok(file) => HttpResponse::Ok()
+            .content_type("application/vnd.apache.parquet")
+            .header(
+                "Content-Disposition",
+                "attachment; filename=\"entries.parquet\"",
+            )
+            .body(file),
         Err(_) => HttpResponse::InternalServerError().body("Error with backend."),
     }
 }
@@ -90,6 +202,32 @@ pub fn search_cities(
     }
 }
 
+pub fn search_city_names(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    _req: HttpRequest,
+    query: web::Query<CityNameQuery>,
+) -> HttpResponse {
+    let search = match query.get_search() {
+        Some(search) => search,
+        None => {
+            return HttpResponse::BadRequest()
+                .body("Cannot search without 'prefix', 'start' and 'end', 'regex' or 'fuzzy'")
+        }
+    };
+
+    let state = wrap_state.lock().unwrap();
+    match state.get_domain().search_city_names(search) {
+        Ok(names) => HttpResponse::Ok().json(names),
+        Err(EntryDomainError::Storage {
+            source: StorageError::InvalidQuery(error),
+        }) => HttpResponse::BadRequest().body(error),
+        Err(EntryDomainError::Storage {
+            source: StorageError::NotImplemented,
+        }) => HttpResponse::NotImplemented().body("Query not supported by the index backend."),
+        Err(_) => HttpResponse::InternalServerError().body("Error with backend."),
+    }
+}
+
 pub fn get_national_index(
     wrap_state: Data<Arc<Mutex<AppState>>>,
     _req: HttpRequest,
@@ -224,3 +362,205 @@ pub fn get_city_districts_index(
         None => HttpResponse::BadRequest().body("No region was given."),
     }
 }
+
+//Token of the write requests, read once at startup.
+#[derive(Clone)]
+pub struct BearerToken {
+    header: String,
+}
+
+impl BearerToken {
+    pub fn new(token: String) -> Self {
+        BearerToken {
+            header: format!("Bearer {}", token),
+        }
+    }
+
+    //Check the `Authorization: Bearer <token>` header of the write requests.
+    fn is_valid(&self, req: &HttpRequest) -> bool {
+        match req.headers().get(AUTHORIZATION) {
+            Some(header) => constant_time_eq(header.as_bytes(), self.header.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+//Compare every byte whatever the first difference, the time does not tell how much of the token
+//was guessed.
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    left.iter()
+        .zip(right)
+        .fold(0, |difference, (left, right)| difference | (left ^ right))
+        == 0
+}
+
+fn write_error_response(error: EntryDomainError) -> HttpResponse {
+    match error {
+        EntryDomainError::NotFoundError => HttpResponse::NotFound().body("No district was found."),
+        EntryDomainError::OutdatedEntries(_) => HttpResponse::Conflict().body(error.to_string()),
+        _ => HttpResponse::InternalServerError().body("Error with backend."),
+    }
+}
+
+pub fn update_district(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+    entry: web::Json<Entry>,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    //The domain serializes its writes, the state is not locked during the refresh.
+    let domain = wrap_state.lock().unwrap().get_domain().clone();
+
+    match req.match_info().get("iriscode") {
+        Some(iriscode) => match domain.update_district(iriscode.to_string(), entry.into_inner()) {
+            Ok(entry) => HttpResponse::Ok().json(entry),
+            Err(error) => write_error_response(error),
+        },
+        None => HttpResponse::BadRequest().body("No district was given."),
+    }
+}
+
+pub fn patch_district(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+    patch: web::Json<Value>,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let domain = wrap_state.lock().unwrap().get_domain().clone();
+
+    match req.match_info().get("iriscode") {
+        Some(iriscode) => match domain.patch_district(iriscode.to_string(), patch.into_inner()) {
+            Ok(entry) => HttpResponse::Ok().json(entry),
+            Err(error) => write_error_response(error),
+        },
+        None => HttpResponse::BadRequest().body("No district was given."),
+    }
+}
+
+pub fn delete_district(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let domain = wrap_state.lock().unwrap().get_domain().clone();
+
+    match req.match_info().get("iriscode") {
+        Some(iriscode) => match domain.delete_district(iriscode.to_string()) {
+            Ok(entry) => HttpResponse::Ok().json(entry),
+            Err(error) => write_error_response(error),
+        },
+        None => HttpResponse::BadRequest().body("No district was given."),
+    }
+}
+
+pub fn get_dataset(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let state = wrap_state.lock().unwrap();
+
+    HttpResponse::Ok().json(state.get_dataset_status())
+}
+
+//Load and validate a dataset without blocking the other requests, then switch to it.
+pub async fn activate_dataset(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let name = match req.match_info().get("name") {
+        Some(name) => name,
+        None => return HttpResponse::BadRequest().body("No dataset was given."),
+    };
+
+    {
+        let mut state = wrap_state.lock().unwrap();
+        let status = state.get_dataset_status();
+        if status.active == name {
+            return HttpResponse::BadRequest().body("The dataset is already active.");
+        }
+        //The previous dataset is still open, switch back to it.
+        if status.previous.as_deref() == Some(name) {
+            return match state.rollback() {
+                Ok(_) => HttpResponse::Ok().json(state.get_dataset_status()),
+                Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+            };
+        }
+    }
+
+    //The dataset is opened and validated on the thread pool, not on the worker.
+    let loaded_name = name.to_string();
+    match web::block(move || Dataset::load(&loaded_name)).await {
+        Ok(dataset) => {
+            let mut state = wrap_state.lock().unwrap();
+            state.activate(dataset);
+            HttpResponse::Ok().json(state.get_dataset_status())
+        }
+        Err(BlockingError::Error(error)) => HttpResponse::BadRequest().body(error.to_string()),
+        Err(BlockingError::Canceled) => {
+            HttpResponse::InternalServerError().body("Error with backend.")
+        }
+    }
+}
+
+pub fn rollback_dataset(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let mut state = wrap_state.lock().unwrap();
+
+    match state.rollback() {
+        Ok(_) => HttpResponse::Ok().json(state.get_dataset_status()),
+        Err(error) => HttpResponse::BadRequest().body(error.to_string()),
+    }
+}
+
+pub fn get_cache_stats(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let state = wrap_state.lock().unwrap();
+
+    HttpResponse::Ok().json(state.get_cache_stats())
+}
+
+pub fn clear_caches(
+    wrap_state: Data<Arc<Mutex<AppState>>>,
+    bearer_token: Data<BearerToken>,
+    req: HttpRequest,
+) -> HttpResponse {
+    if !bearer_token.is_valid(&req) {
+        return HttpResponse::Unauthorized().body("Invalid bearer token.");
+    }
+    let state = wrap_state.lock().unwrap();
+    state.clear_caches();
+
+    HttpResponse::Ok().json(state.get_cache_stats())
+}
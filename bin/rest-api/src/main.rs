@@ -26,11 +26,16 @@ async fn main() -> io::Result<()> {
     println!("http://{}", addr);
 
     //Logger service initialization
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "warn,actix_web=info");
     env_logger::init();
 
+    //Token of the write requests.
+    let bearer_token = BearerToken::new(Configuration::get_bearer_token());
+
     //Define a global state for all the Actix-Worker
-    let app_state = Arc::new(Mutex::new(AppState::new()));
+    let app_state = Arc::new(Mutex::new(
+        AppState::load().expect("cannot load the dataset."),
+    ));
 
     // let cors = Cors::new().supports_credentials();
 
@@ -51,6 +56,7 @@ async fn main() -> io::Result<()> {
                     .header("Cache-Control", "public, max-age=604800, immutable"),
             )
             .data(app_state.clone())
+            .data(bearer_token.clone())
             .service(
                 web::scope("/api")
                     .route("/_", web::get().to(healthcheck))
@@ -58,6 +64,7 @@ async fn main() -> io::Result<()> {
                     .route("/departments", web::get().to(get_departments))
                     .route("/cities", web::get().to(get_cities))
                     .route("/cities/search", web::get().to(search_cities))
+                    .route("/cities/names", web::get().to(search_city_names))
                     .route("/index/national", web::get().to(get_national_index))
                     .route(
                         "/index/regional/{region}",
@@ -85,7 +92,25 @@ async fn main() -> io::Result<()> {
                         "/index/districts/{iriscode}",
                         web::get().to(get_district_index),
                     )
-                    .route("/index", web::get().to(entries_get_all)),
+                    .route(
+                        "/index/districts/{iriscode}",
+                        web::put().to(update_district),
+                    )
+                    .route(
+                        "/index/districts/{iriscode}",
+                        web::patch().to(patch_district),
+                    )
+                    .route(
+                        "/index/districts/{iriscode}",
+                        web::delete().to(delete_district),
+                    )
+                    .route("/index", web::get().to(entries_get_all))
+                    .route("/index/parquet", web::get().to(entries_get_parquet))
+                    .route("/admin/dataset", web::get().to(get_dataset))
+                    .route("/admin/dataset/rollback", web::post().to(rollback_dataset))
+                    .route("/admin/dataset/{name}", web::post().to(activate_dataset))
+                    .route("/admin/cache", web::get().to(get_cache_stats))
+                    .route("/admin/cache", web::delete().to(clear_caches)),
             )
             .service(web::scope("/").configure(get_static_files_configuration))
     })
@@ -1,48 +1,470 @@
-use crate::configuration::Configuration;
+use crate::configuration::{Configuration, ConfigurationError};
+use cached_storage::cache::{CacheStats, CacheTrait};
+use cached_storage::index::CachedIndexStorage;
+use cached_storage::CachedEntryStorage;
 use domain::business::domain::EntryDomain;
+use domain::business::error::EntryDomainError;
 use domain::business::traits::EntryDomainTrait;
+use domain::storage::error::{StorageError, StorageResult};
+use domain::storage::traits::{
+    EntryStorageTrait, HierarchyStorageTrait, IndexStoragePostalTrait, IndexStorageTrait,
+};
+use memory_entry_storage::MemoryEntryStorage;
 use memory_index_storage::extended::MemoryIndexStoragePostal;
+use memory_index_storage::hierarchy::MemoryHierarchyStorage;
 use memory_index_storage::MemoryIndexStorage;
+use sled_db_entry_storage::extended::SledIndexStoragePostal;
+use sled_db_entry_storage::index::SledIndexStorage;
 use sled_db_entry_storage::SledEntriesStorage;
+use sqlite_storage::extended::SqliteIndexStoragePostal;
+use sqlite_storage::index::SqliteIndexStorage;
+use sqlite_storage::SqliteStorage;
 use std::boxed::Box;
+use std::collections::BTreeMap;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+//Name of the dataset loaded from the configured paths.
+const DEFAULT_DATASET_NAME: &str = "default";
+
+//Define the possible errors
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Storage error: {source}")]
+    Storage {
+        #[from]
+        source: StorageError,
+    },
+    #[error("Domain error: {source}")]
+    Domain {
+        #[from]
+        source: EntryDomainError,
+    },
+    #[error("Configuration error: {source}")]
+    Configuration {
+        #[from]
+        source: ConfigurationError,
+    },
+    #[error("Invalid dataset: {0}")]
+    InvalidDataset(String),
+    #[error("No previous dataset to roll back to")]
+    NoPreviousDataset,
+}
+
+//Define a generic error type to simplify return.
+pub type StateResult<T> = std::result::Result<T, StateError>;
 
 pub struct AppState {
-    entry_domain: Box<dyn EntryDomainTrait + Send>,
+    active: Dataset,
+    previous: Option<Dataset>,
 }
 
 impl AppState {
-    pub fn new() -> Self {
-        AppState {
-            entry_domain: Box::new(EntryDomain::new(
-                Box::new(
-                    MemoryIndexStorage::new(Configuration::get_index_path() + "idx_regions.json")
-                        .unwrap(),
-                ),
-                Box::new(
-                    MemoryIndexStorage::new(
-                        Configuration::get_index_path() + "idx_departments.json",
-                    )
-                    .unwrap(),
-                ),
-                Box::new(
-                    MemoryIndexStoragePostal::new(
-                        Configuration::get_index_path() + "idx_postal.json",
-                    )
-                    .unwrap(),
-                ),
-                Box::new(
-                    MemoryIndexStorage::new(
-                        Configuration::get_index_path() + "idx_insee_coms.json",
-                    )
-                    .unwrap(),
-                ),
-                Box::new(MemoryIndexStorage::new(Configuration::get_index_path() + "idx_departments_by_region.json").unwrap()),
-                Box::new(SledEntriesStorage::new(Configuration::get_sled_db_path())),
+    //Load the configured dataset, the API does not start without it.
+    pub fn load() -> StateResult<Self> {
+        let dataset = match Configuration::get_dataset() {
+            Some(name) => Dataset::load(&name),
+            None => Dataset::from_paths(
+                DEFAULT_DATASET_NAME.to_string(),
+                DatasetPaths::Configuration,
+            ),
+        };
+
+        Ok(AppState {
+            active: dataset?,
+            previous: None,
+        })
+    }
+
+    pub fn get_domain(&self) -> &Arc<dyn EntryDomainTrait> {
+        &self.active.domain
+    }
+
+    pub fn get_dataset_status(&self) -> DatasetStatus {
+        DatasetStatus {
+            active: self.active.name.to_string(),
+            previous: self
+                .previous
+                .as_ref()
+                .map(|dataset| dataset.name.to_string()),
+        }
+    }
+
+    pub fn get_cache_stats(&self) -> BTreeMap<String, CacheStats> {
+        self.active
+            .caches
+            .iter()
+            .map(|(name, cache)| (name.to_string(), cache.get_stats()))
+            .collect()
+    }
+
+    pub fn clear_caches(&self) {
+        for (_, cache) in &self.active.caches {
+            cache.clear();
+        }
+    }
+
+    //Switch to a loaded dataset, the active one is kept for a rollback.
+    //The requests in progress keep their own reference on the previous domain.
+    pub fn activate(&mut self, dataset: Dataset) {
+        let previous = mem::replace(&mut self.active, dataset);
+        self.previous = Some(previous);
+    }
+
+    //Switch back to the previous dataset, the active one becomes the previous.
+    pub fn rollback(&mut self) -> StateResult<()> {
+        match self.previous.take() {
+            Some(previous) => {
+                self.activate(previous);
+                Ok(())
+            }
+            None => Err(StateError::NoPreviousDataset),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DatasetStatus {
+    pub active: String,
+    pub previous: Option<String>,
+}
+
+//Domain built on the storages of a dataset.
+pub struct Dataset {
+    name: String,
+    domain: Arc<dyn EntryDomainTrait>,
+    caches: Vec<(String, Arc<dyn CacheTrait>)>,
+}
+
+impl Dataset {
+    //Load and validate the dataset written by the import in `{DATASETS_PATH}{name}/`.
+    pub fn load(name: &str) -> StateResult<Self> {
+        let is_valid_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid_name {
+            return Err(StateError::InvalidDataset(format!(
+                "'{}' is not a dataset name",
+                name
+            )));
+        }
+
+        let path = format!("{}{}", Configuration::get_datasets_path(), name);
+        if !Path::new(&path).is_dir() {
+            return Err(StateError::InvalidDataset(format!("{} not found", path)));
+        }
+
+        let dataset = Dataset::from_paths(name.to_string(), DatasetPaths::Directory(path))?;
+        dataset.validate()?;
+        Ok(dataset)
+    }
+
+    fn from_paths(name: String, paths: DatasetPaths) -> StateResult<Self> {
+        let mut storages = StorageFactory::new(paths);
+        let domain = Arc::new(
+            EntryDomain::new(
+                storages.get_index_storage("regions")?,
+                storages.get_index_storage("departments")?,
+                storages.get_postal_index_storage()?,
+                storages.get_index_storage("insee_coms")?,
+                storages.get_index_storage("departments_by_region")?,
+                storages.get_entry_storage()?,
+            )
+            .with_hierarchy(storages.get_hierarchy_storage()),
+        );
+
+        Ok(Dataset {
+            name,
+            domain,
+            caches: storages.caches,
+        })
+    }
+
+    //Check that the national, regional and departmental pages can be served.
+    fn validate(&self) -> StateResult<()> {
+        let domain = &self.domain;
+        let check = || -> StateResult<()> {
+            domain.get_national_index()?;
+
+            let regions = domain.get_regions()?;
+            if regions.is_empty() {
+                return Err(StateError::InvalidDataset("no region".to_string()));
+            }
+            for region in regions {
+                domain.get_regional_index(region)?;
+            }
+
+            let departments = domain.get_departments()?;
+            if departments.is_empty() {
+                return Err(StateError::InvalidDataset("no department".to_string()));
+            }
+            for department in departments {
+                domain.get_departmental_index(department)?;
+            }
+
+            if domain.get_cities()?.is_empty() {
+                return Err(StateError::InvalidDataset("no city".to_string()));
+            }
+            Ok(())
+        };
+
+        //The domain panics on some incomplete data.
+        match panic::catch_unwind(AssertUnwindSafe(check)) {
+            Ok(result) => result,
+            Err(_) => Err(StateError::InvalidDataset(format!(
+                "{} is incomplete",
+                self.name
+            ))),
+        }
+    }
+}
+
+//Files of a dataset, for each storage backend.
+enum DatasetPaths {
+    Configuration,
+    //Same layout as the import output.
+    Directory(String),
+}
+
+impl DatasetPaths {
+    fn get_sled_db_path(&self) -> String {
+        match self {
+            DatasetPaths::Configuration => Configuration::get_sled_db_path(),
+            DatasetPaths::Directory(path) => format!("{}/database", path),
+        }
+    }
+
+    fn get_sqlite_db_path(&self) -> String {
+        match self {
+            DatasetPaths::Configuration => Configuration::get_sqlite_db_path(),
+            DatasetPaths::Directory(path) => format!("{}/database.sqlite", path),
+        }
+    }
+
+    fn get_index_path(&self) -> String {
+        match self {
+            DatasetPaths::Configuration => Configuration::get_index_path(),
+            DatasetPaths::Directory(path) => format!("{}/indexes/", path),
+        }
+    }
+
+    fn get_entry_snapshot_path(&self) -> String {
+        match self {
+            DatasetPaths::Configuration => Configuration::get_entry_snapshot_path(),
+            DatasetPaths::Directory(path) => format!("{}/entries.cbor", path),
+        }
+    }
+}
+
+//Build the configured storages, sharing the sled database between entries and indexes.
+struct StorageFactory {
+    paths: DatasetPaths,
+    sled: Option<SledEntriesStorage>,
+    caches: Vec<(String, Arc<dyn CacheTrait>)>,
+}
+
+impl StorageFactory {
+    fn new(paths: DatasetPaths) -> Self {
+        StorageFactory {
+            paths,
+            sled: None,
+            caches: Vec::new(),
+        }
+    }
+
+    fn get_sled_storage(&mut self) -> StorageResult<SledEntriesStorage> {
+        if self.sled.is_none() {
+            self.sled = Some(SledEntriesStorage::open(self.paths.get_sled_db_path())?);
+        }
+        Ok(self.sled.clone().unwrap())
+    }
+
+    fn get_sqlite_storage(&self) -> StorageResult<SqliteStorage> {
+        SqliteStorage::new(self.paths.get_sqlite_db_path())
+    }
+
+    fn get_index_storage(&mut self, name: &str) -> StateResult<Box<dyn IndexStorageTrait>> {
+        let storage: Box<dyn IndexStorageTrait> = match Configuration::get_index_storage().as_str()
+        {
+            "sqlite" => Box::new(SqliteIndexStorage::new(
+                &self.get_sqlite_storage()?,
+                name.to_string(),
             )),
+            "sled" => Box::new(SledIndexStorage::open(
+                &self.get_sled_storage()?,
+                name.to_string(),
+            )?),
+            //The JSON indexes are already in memory, they are not cached.
+            _ => {
+                return Ok(Box::new(MemoryIndexStorage::new(format!(
+                    "{}idx_{}.json",
+                    self.paths.get_index_path(),
+                    name
+                ))?))
+            }
+        };
+
+        match Configuration::get_cache_capacity()? {
+            0 => Ok(storage),
+            capacity => {
+                let cached = CachedIndexStorage::new(storage, capacity, get_cache_ttl()?);
+                self.caches
+                    .push((format!("idx_{}", name), cached.get_cache()));
+                Ok(Box::new(cached))
+            }
+        }
+    }
+
+    fn get_postal_index_storage(&mut self) -> StorageResult<Box<dyn IndexStoragePostalTrait>> {
+        Ok(match Configuration::get_index_storage().as_str() {
+            "sqlite" => Box::new(SqliteIndexStoragePostal::new(&self.get_sqlite_storage()?)),
+            "sled" => Box::new(SledIndexStoragePostal::open(&self.get_sled_storage()?)?),
+            _ => Box::new(MemoryIndexStoragePostal::new(format!(
+                "{}idx_postal.json",
+                self.paths.get_index_path()
+            ))?),
+        })
+    }
+
+    //The hierarchy is only written as files, whatever the index storage.
+    fn get_hierarchy_storage(&self) -> Box<dyn HierarchyStorageTrait> {
+        Box::new(MemoryHierarchyStorage::new(format!(
+            "{}idx_hierarchy.json",
+            self.paths.get_index_path()
+        )))
+    }
+
+    fn get_entry_storage(&mut self) -> StateResult<Box<dyn EntryStorageTrait>> {
+        let storage: Box<dyn EntryStorageTrait> = match Configuration::get_entry_storage().as_str()
+        {
+            //The memory entries are already decoded, they are not cached.
+            "memory" => {
+                return Ok(Box::new(MemoryEntryStorage::new(
+                    self.paths.get_entry_snapshot_path(),
+                )?))
+            }
+            "sqlite" => Box::new(self.get_sqlite_storage()?),
+            _ => Box::new(self.get_sled_storage()?),
+        };
+
+        match Configuration::get_cache_capacity()? {
+            0 => Ok(storage),
+            capacity => {
+                let cached = CachedEntryStorage::new(storage, capacity, get_cache_ttl()?);
+                self.caches
+                    .push(("entries".to_string(), cached.get_cache()));
+                Ok(Box::new(cached))
+            }
+        }
+    }
+}
+
+fn get_cache_ttl() -> StateResult<Duration> {
+    Ok(Duration::from_secs(Configuration::get_cache_ttl()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cached_storage::cache::LruTtlCache;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn get_directory(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("rest-api-state-{}-{}", process::id(), name))
+    }
+
+    //Dataset on an empty sled database, with one cache.
+    fn get_dataset(name: &str) -> Dataset {
+        let storage =
+            SledEntriesStorage::open(get_directory(name).to_string_lossy().to_string()).unwrap();
+        let index = |name: &str| Box::new(SledIndexStorage::new(&storage, name.to_string()));
+        let domain = EntryDomain::new(
+            index("regions"),
+            index("departments"),
+            Box::new(SledIndexStoragePostal::new(&storage)),
+            index("insee_coms"),
+            index("departments_by_region"),
+            Box::new(storage.clone()),
+        );
+        let cache: LruTtlCache<String, u32> = LruTtlCache::new(10, Duration::from_secs(60));
+        Dataset {
+            name: name.to_string(),
+            domain: Arc::new(domain),
+            caches: vec![(format!("{}_cache", name), Arc::new(cache))],
+        }
+    }
+
+    fn get_status(state: &AppState) -> (String, Option<String>) {
+        let status = state.get_dataset_status();
+        (status.active, status.previous)
+    }
+
+    #[test]
+    fn activate_swaps_the_datasets_and_rollback_switches_back() {
+        let mut state = AppState {
+            active: get_dataset("first"),
+            previous: None,
+        };
+        let first_domain = state.get_domain().clone();
+        assert!(matches!(
+            state.rollback(),
+            Err(StateError::NoPreviousDataset)
+        ));
+
+        state.activate(get_dataset("second"));
+        assert_eq!(
+            get_status(&state),
+            ("second".to_string(), Some("first".to_string()))
+        );
+        assert!(!Arc::ptr_eq(state.get_domain(), &first_domain));
+        let caches: Vec<String> = state.get_cache_stats().keys().cloned().collect();
+        assert_eq!(caches, vec!["second_cache".to_string()]);
+
+        state.rollback().unwrap();
+        assert_eq!(
+            get_status(&state),
+            ("first".to_string(), Some("second".to_string()))
+        );
+        assert!(Arc::ptr_eq(state.get_domain(), &first_domain));
+
+        //The rolled back dataset can be activated again by a rollback.
+        state.rollback().unwrap();
+        assert_eq!(
+            get_status(&state),
+            ("second".to_string(), Some("first".to_string()))
+        );
+
+        //Activating a third dataset closes the previous one.
+        state.activate(get_dataset("third"));
+        assert_eq!(
+            get_status(&state),
+            ("third".to_string(), Some("second".to_string()))
+        );
+
+        drop(state);
+        drop(first_domain);
+        for name in &["first", "second", "third"] {
+            fs::remove_dir_all(get_directory(name)).unwrap();
         }
     }
 
-    pub fn get_domain(&self) -> &Box<dyn EntryDomainTrait + Send> {
-        &self.entry_domain
+    #[test]
+    fn load_refuses_invalid_and_missing_datasets() {
+        assert!(matches!(
+            Dataset::load("../other"),
+            Err(StateError::InvalidDataset(_))
+        ));
+        assert!(matches!(
+            Dataset::load("missing-dataset"),
+            Err(StateError::InvalidDataset(_))
+        ));
     }
 }
@@ -1,3 +1,8 @@
+pub mod aggregates;
+pub mod consistency;
+pub mod diff;
 pub mod domain;
 pub mod error;
+pub mod rebuild;
 pub mod traits;
+pub mod upsert;
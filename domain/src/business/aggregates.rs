@@ -0,0 +1,350 @@
+use crate::core::entry::Entry;
+use std::collections::HashMap;
+
+//Number of averaged scores: the global score, then the score of each axis.
+pub const SCORES_COUNT: usize = 5;
+
+//Averages of a territory, in the order of `get_scores`.
+pub type Averages = [Option<f64>; SCORES_COUNT];
+
+//Sum of the scores of a group of entries, shared by the import and the refresh after a write.
+//A missing score is left out of the sum and of the count of its average.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct ScoresSum {
+    sums: [f64; SCORES_COUNT],
+    counts: [u32; SCORES_COUNT],
+}
+
+impl ScoresSum {
+    pub fn add(&mut self, scores: &Averages) {
+        for ((sum, count), score) in self.sums.iter_mut().zip(&mut self.counts).zip(scores) {
+            if let Some(score) = score {
+                *sum += score;
+                *count += 1;
+            }
+        }
+    }
+
+    //Take back the scores of an entry added before, when it is replaced or deleted.
+    pub fn remove(&mut self, scores: &Averages) {
+        for ((sum, count), score) in self.sums.iter_mut().zip(&mut self.counts).zip(scores) {
+            if let Some(score) = score {
+                *sum -= score;
+                *count -= 1;
+            }
+        }
+    }
+
+    //The averages are rounded to f32 like the import always did: the sums of the import follow
+    //the rows of the file and the refresh the order of the storage, the rounding hides the
+    //difference of the last bits.
+    pub fn get_averages(&self) -> Averages {
+        let mut averages = [None; SCORES_COUNT];
+        for ((average, sum), count) in averages.iter_mut().zip(&self.sums).zip(&self.counts) {
+            if *count > 0 {
+                *average = Some((sum / *count as f64) as f32 as f64);
+            }
+        }
+        averages
+    }
+}
+
+//Averaged scores of an entry: the global score, then the score of each axis. The information
+//access averages come from the ACCES A L'INFORMATION score, not from the global score of the axis.
+pub fn get_scores(entry: &Entry) -> Averages {
+    [
+        entry.global,
+        entry
+            .information_access
+            .as_ref()
+            .and_then(|axis| axis.information_score),
+        entry
+            .numeric_interfaces_access
+            .as_ref()
+            .and_then(|axis| axis.global),
+        entry
+            .administrative_competencies
+            .as_ref()
+            .and_then(|axis| axis.global),
+        entry
+            .numeric_competencies
+            .as_ref()
+            .and_then(|axis| axis.global),
+    ]
+}
+
+//Regional, departmental and national averages stored in each entry. Only the territories given
+//to `new` are refreshed, the averages of the other ones are left as they are stored. An axis
+//without a national average, when no entry has its score like in a database written before the
+//information score, keeps its stored averages too.
+pub struct Aggregates {
+    national: Averages,
+    regions: HashMap<String, Averages>,
+    departments: HashMap<String, Averages>,
+    region_of: HashMap<String, String>,
+    department_of: HashMap<String, String>,
+}
+
+impl Aggregates {
+    //Compute the averages of the territories of `region_of` and `department_of`, which give the
+    //territory of each of their IRIS codes, from their entries.
+    pub fn new(
+        entries: &[Entry],
+        national: Averages,
+        region_of: HashMap<String, String>,
+        department_of: HashMap<String, String>,
+    ) -> Self {
+        let mut regions: HashMap<String, ScoresSum> = HashMap::new();
+        let mut departments: HashMap<String, ScoresSum> = HashMap::new();
+
+        for entry in entries {
+            let iris_code = match &entry.iris_code {
+                Some(iris_code) => iris_code,
+                None => continue,
+            };
+            let scores = get_scores(entry);
+            if let Some(region) = region_of.get(iris_code) {
+                regions.entry(region.to_string()).or_default().add(&scores);
+            }
+            if let Some(department) = department_of.get(iris_code) {
+                departments
+                    .entry(department.to_string())
+                    .or_default()
+                    .add(&scores);
+            }
+        }
+
+        Aggregates {
+            national,
+            regions: regions
+                .into_iter()
+                .map(|(region, sum)| (region, sum.get_averages()))
+                .collect(),
+            departments: departments
+                .into_iter()
+                .map(|(department, sum)| (department, sum.get_averages()))
+                .collect(),
+            region_of,
+            department_of,
+        }
+    }
+
+    //Entry with the current averages, `None` when they are already up to date.
+    pub fn apply(&self, entry: &Entry) -> Option<Entry> {
+        let iris_code = entry.iris_code.clone().unwrap_or_default();
+        let region = self
+            .region_of
+            .get(&iris_code)
+            .and_then(|region| self.regions.get(region));
+        let department = self
+            .department_of
+            .get(&iris_code)
+            .and_then(|department| self.departments.get(department));
+
+        let mut updated = entry.clone();
+        let mut changed = false;
+        {
+            let mut set_averages =
+                |position: usize,
+                 global_region: &mut Option<f64>,
+                 global_dept: &mut Option<f64>,
+                 global_national: &mut Option<f64>| {
+                    if self.national[position].is_none() {
+                        return;
+                    }
+                    if let Some(region) = region {
+                        changed |= set_average(global_region, region[position]);
+                    }
+                    if let Some(department) = department {
+                        changed |= set_average(global_dept, department[position]);
+                    }
+                    changed |= set_average(global_national, self.national[position]);
+                };
+
+            set_averages(
+                0,
+                &mut updated.global_region,
+                &mut updated.global_dept,
+                &mut updated.global_national,
+            );
+            if let Some(axis) = updated.information_access.as_mut() {
+                set_averages(
+                    1,
+                    &mut axis.global_region,
+                    &mut axis.global_dept,
+                    &mut axis.global_national,
+                );
+            }
+            if let Some(axis) = updated.numeric_interfaces_access.as_mut() {
+                set_averages(
+                    2,
+                    &mut axis.global_region,
+                    &mut axis.global_dept,
+                    &mut axis.global_national,
+                );
+            }
+            if let Some(axis) = updated.administrative_competencies.as_mut() {
+                set_averages(
+                    3,
+                    &mut axis.global_region,
+                    &mut axis.global_dept,
+                    &mut axis.global_national,
+                );
+            }
+            if let Some(axis) = updated.numeric_competencies.as_mut() {
+                set_averages(
+                    4,
+                    &mut axis.global_region,
+                    &mut axis.global_dept,
+                    &mut axis.global_national,
+                );
+            }
+        }
+
+        match changed {
+            true => Some(updated),
+            false => None,
+        }
+    }
+}
+
+//A missing average, when none of the entries of the territory has the score, is stored as None
+//like the import.
+fn set_average(target: &mut Option<f64>, average: Option<f64>) -> bool {
+    if *target == average {
+        return false;
+    }
+    *target = average;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::entry::InformationAccess;
+
+    fn get_entry(iris_code: &str, global: Option<f64>, information_score: Option<f64>) -> Entry {
+        Entry::new(
+            global,
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            Some(
+                InformationAccess::new(Some(500.0), None, None, None, None, None, None, None)
+                    .with_information_score(information_score),
+            ),
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn get_territories(territories: &[(&str, &str)]) -> HashMap<String, String> {
+        territories
+            .iter()
+            .map(|(iris_code, territory)| (iris_code.to_string(), territory.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn missing_scores_are_left_out_of_the_sum() {
+        let mut sum = ScoresSum::default();
+        assert_eq!(sum.get_averages(), [None; SCORES_COUNT]);
+        sum.add(&[Some(10.0), None, None, None, None]);
+        sum.add(&[Some(20.0), Some(4.0), None, None, None]);
+        assert_eq!(
+            sum.get_averages(),
+            [Some(15.0), Some(4.0), None, None, None]
+        );
+
+        sum.remove(&[Some(20.0), Some(4.0), None, None, None]);
+        assert_eq!(sum.get_averages(), [Some(10.0), None, None, None, None]);
+    }
+
+    #[test]
+    fn information_access_averages_the_information_score() {
+        let scores = get_scores(&get_entry("1", Some(100.0), Some(80.0)));
+        assert_eq!(scores[1], Some(80.0));
+    }
+
+    #[test]
+    fn apply_sets_the_averages_of_the_territories() {
+        let entries = vec![
+            get_entry("1", Some(100.0), Some(80.0)),
+            get_entry("2", Some(120.0), None),
+            get_entry("3", Some(90.0), Some(60.0)),
+        ];
+        let aggregates = Aggregates::new(
+            &entries,
+            [Some(99.0), Some(70.0), None, None, None],
+            get_territories(&[("1", "R1"), ("2", "R1"), ("3", "R2")]),
+            get_territories(&[("1", "D1"), ("2", "D2")]),
+        );
+
+        let updated = aggregates.apply(&entries[0]).unwrap();
+        assert_eq!(updated.global_region, Some(110.0));
+        assert_eq!(updated.global_dept, Some(100.0));
+        assert_eq!(updated.global_national, Some(99.0));
+        let axis = updated.information_access.unwrap();
+        assert_eq!(axis.global_region, Some(80.0));
+        assert_eq!(axis.global_dept, Some(80.0));
+        assert_eq!(axis.global_national, Some(70.0));
+
+        //None of the entries of the department D2 has an information score.
+        let updated = aggregates.apply(&entries[1]).unwrap();
+        assert_eq!(updated.global_dept, Some(120.0));
+        assert_eq!(updated.information_access.unwrap().global_dept, None);
+
+        //The department of the IRIS code 3 is not refreshed.
+        let updated = aggregates.apply(&entries[2]).unwrap();
+        assert_eq!(updated.global_region, Some(90.0));
+        assert_eq!(updated.global_dept, None);
+    }
+
+    #[test]
+    fn apply_leaves_up_to_date_entries() {
+        let entries = vec![get_entry("1", Some(100.0), Some(80.0))];
+        let aggregates = Aggregates::new(
+            &entries,
+            [Some(100.0), Some(80.0), None, None, None],
+            get_territories(&[("1", "R1")]),
+            get_territories(&[("1", "D1")]),
+        );
+        let updated = aggregates.apply(&entries[0]).unwrap();
+        assert!(aggregates.apply(&updated).is_none());
+    }
+
+    #[test]
+    fn apply_keeps_the_averages_of_a_score_no_entry_has() {
+        //Entries stored before the information score, with the information access averages of
+        //the import of that time.
+        let entries: Vec<Entry> = vec![
+            get_entry("1", Some(100.0), None),
+            get_entry("2", Some(120.0), None),
+        ]
+        .into_iter()
+        .map(|mut entry| {
+            let axis = entry.information_access.as_mut().unwrap();
+            axis.global_region = Some(450.0);
+            axis.global_dept = Some(400.0);
+            axis.global_national = Some(500.0);
+            entry
+        })
+        .collect();
+        let aggregates = Aggregates::new(
+            &entries,
+            [Some(110.0), None, None, None, None],
+            get_territories(&[("1", "R1"), ("2", "R1")]),
+            get_territories(&[("1", "D1"), ("2", "D1")]),
+        );
+
+        let updated = aggregates.apply(&entries[0]).unwrap();
+        assert_eq!(updated.global_region, Some(110.0));
+        let axis = updated.information_access.unwrap();
+        assert_eq!(axis.global_region, Some(450.0));
+        assert_eq!(axis.global_dept, Some(400.0));
+        assert_eq!(axis.global_national, Some(500.0));
+    }
+}
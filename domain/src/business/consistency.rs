@@ -0,0 +1,215 @@
+use crate::business::domain::EntryDomain;
+use crate::business::error::*;
+use crate::storage::traits::IndexStorageTrait;
+use std::collections::HashSet;
+
+//Reference of an index to a value that does not exist.
+#[derive(Debug, Serialize)]
+pub struct MissingLink {
+    pub index: String,
+    pub key: String,
+    pub value: String,
+}
+
+//Stored value that no index references.
+#[derive(Debug, Serialize)]
+pub struct Orphan {
+    pub index: String,
+    pub value: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConsistencyReport {
+    pub entries: usize,
+    pub missing_links: Vec<MissingLink>,
+    pub orphans: Vec<Orphan>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing_links.is_empty() && self.orphans.is_empty()
+    }
+
+    fn add_missing_link(&mut self, index: &str, key: &str, value: &str) {
+        self.missing_links.push(MissingLink {
+            index: index.to_string(),
+            key: key.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    fn add_orphan(&mut self, index: &str, value: &str) {
+        self.orphans.push(Orphan {
+            index: index.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    //Check that each value of the index exists, return the referenced values.
+    fn check_index(
+        &mut self,
+        name: &str,
+        index: &dyn IndexStorageTrait,
+        existing: &HashSet<String>,
+    ) -> EntryDomainResult<HashSet<String>> {
+        let mut referenced: HashSet<String> = HashSet::new();
+        for key in index.get_all_keys()? {
+            for value in index.get_index(key.to_string())?.unwrap_or(Vec::new()) {
+                if !existing.contains(&value) {
+                    self.add_missing_link(name, &key, &value);
+                }
+                referenced.insert(value);
+            }
+        }
+        Ok(referenced)
+    }
+
+    fn check_orphans(
+        &mut self,
+        name: &str,
+        existing: &HashSet<String>,
+        referenced: &HashSet<String>,
+    ) {
+        let mut orphans: Vec<&String> = existing.difference(referenced).collect();
+        orphans.sort();
+        for orphan in orphans {
+            self.add_orphan(name, orphan);
+        }
+    }
+}
+
+impl EntryDomain {
+    //Check the links between the entries and the indexes, the domain unwraps them at request time.
+    pub fn verify(&self) -> EntryDomainResult<ConsistencyReport> {
+        let mut report = ConsistencyReport::default();
+
+        let mut iris_codes: HashSet<String> = HashSet::new();
+        for entry in self.entry_datastore.iter_entries()? {
+            if let Some(iris_code) = entry?.iris_code {
+                iris_codes.insert(iris_code);
+            }
+        }
+        report.entries = iris_codes.len();
+
+        //Every IRIS code of the territories is an entry, every entry is in each territory index.
+        let territory_indexes: [(&str, &dyn IndexStorageTrait); 3] = [
+            ("regions", &*self.idx_regions),
+            ("departments", &*self.idx_departments),
+            ("insee_coms", &*self.idx_insee_coms),
+        ];
+        for (name, index) in territory_indexes.iter() {
+            let referenced = report.check_index(name, *index, &iris_codes)?;
+            report.check_orphans(name, &iris_codes, &referenced);
+        }
+
+        //Every commune of the postal index is an INSEE code.
+        let insee_coms: HashSet<String> = self.idx_insee_coms.get_all_keys()?.into_iter().collect();
+        for city in self.idx_cities.get_all_keys()? {
+            let insee_com = self
+                .idx_cities
+                .get_index(city.to_string())?
+                .and_then(|iris| iris.code)
+                .unwrap_or_default();
+            if !insee_coms.contains(&insee_com) {
+                report.add_missing_link("postal", &city, &insee_com);
+            }
+        }
+
+        //Every department of the regions exists, every department is in a region.
+        let departments: HashSet<String> =
+            self.idx_departments.get_all_keys()?.into_iter().collect();
+        let referenced = report.check_index(
+            "departments_by_region",
+            &*self.idx_departments_by_region,
+            &departments,
+        )?;
+        report.check_orphans("departments_by_region", &departments, &referenced);
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::entry::Entry;
+    use crate::storage::testing::*;
+
+    fn get_entry(iris_code: &str) -> Entry {
+        Entry::new(
+            Some(100.0),
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn get_domain(
+        regions: &[(&str, &[&str])],
+        cities: &[(&str, &str)],
+        departments_by_region: &[(&str, &[&str])],
+    ) -> EntryDomain {
+        let entries: Vec<Entry> = ["1", "2", "3"].iter().map(|code| get_entry(code)).collect();
+        EntryDomain::new(
+            Box::new(TestIndexStorage::new(regions)),
+            Box::new(TestIndexStorage::new(&[("D1", &["1", "2", "3"])])),
+            Box::new(TestIndexStoragePostal::new(cities)),
+            Box::new(TestIndexStorage::new(&[("62041", &["1", "2", "3"])])),
+            Box::new(TestIndexStorage::new(departments_by_region)),
+            Box::new(TestEntryStorage::new(&entries)),
+        )
+    }
+
+    #[test]
+    fn consistent_datasets_have_no_missing_link_nor_orphan() {
+        let domain = get_domain(
+            &[("R1", &["1", "2", "3"])],
+            &[("ARRAS", "62041")],
+            &[("R1", &["D1"])],
+        );
+        let report = domain.verify().unwrap();
+
+        assert_eq!(report.entries, 3);
+        assert!(report.is_consistent());
+    }
+
+    #[test]
+    fn orphans_and_missing_links_are_reported() {
+        //The entry 3 is in no region, the entry 4 of R2 and the department D9 do not exist and
+        //LENS is in no INSEE commune.
+        let domain = get_domain(
+            &[("R1", &["1", "2"]), ("R2", &["4"])],
+            &[("ARRAS", "62041"), ("LENS", "62498")],
+            &[("R1", &["D1", "D9"])],
+        );
+        let report = domain.verify().unwrap();
+
+        assert_eq!(report.entries, 3);
+        assert!(!report.is_consistent());
+        let missing_links: Vec<(&str, &str, &str)> = report
+            .missing_links
+            .iter()
+            .map(|link| (link.index.as_str(), link.key.as_str(), link.value.as_str()))
+            .collect();
+        assert_eq!(
+            missing_links,
+            vec![
+                ("regions", "R2", "4"),
+                ("postal", "LENS", "62498"),
+                ("departments_by_region", "R1", "D9"),
+            ]
+        );
+        let orphans: Vec<(&str, &str)> = report
+            .orphans
+            .iter()
+            .map(|orphan| (orphan.index.as_str(), orphan.value.as_str()))
+            .collect();
+        assert_eq!(orphans, vec![("regions", "3")]);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::business::error::*;
+use crate::core::entry::{Entry, FieldDiff};
+use crate::storage::error::StorageError;
+use crate::storage::traits::EntryStorageTrait;
+use std::collections::HashSet;
+
+//Stored entry with other values in the compared entries.
+#[derive(Debug, Serialize)]
+pub struct ModifiedEntry {
+    pub iris_code: String,
+    pub fields: Vec<FieldDiff>,
+}
+
+//Changes an import of the compared entries would make to the stored ones. The import never
+//deletes an entry: the stored entries absent from the compared ones are kept as they are.
+#[derive(Debug, Default, Serialize)]
+pub struct EntriesDiff {
+    pub added: Vec<String>,
+    pub kept: Vec<String>,
+    pub modified: Vec<ModifiedEntry>,
+    pub unchanged: usize,
+}
+
+//Compare new entries, batch by batch, with the stored ones. Nothing is written.
+pub struct EntriesDiffer<'a> {
+    storage: &'a dyn EntryStorageTrait,
+    compared: HashSet<String>,
+    diff: EntriesDiff,
+}
+
+impl<'a> EntriesDiffer<'a> {
+    pub fn new(storage: &'a dyn EntryStorageTrait) -> Self {
+        EntriesDiffer {
+            storage,
+            compared: HashSet::new(),
+            diff: EntriesDiff::default(),
+        }
+    }
+
+    pub fn compare(&mut self, entries: &[Entry]) -> EntryDomainResult<()> {
+        let iris_codes: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| entry.iris_code.clone())
+            .collect();
+        let stored_entries = self.storage.get_entries(&iris_codes)?;
+
+        for entry in entries {
+            let iris_code = match &entry.iris_code {
+                Some(iris_code) => iris_code,
+                None => continue,
+            };
+            self.compared.insert(iris_code.to_string());
+            let stored_entry = match stored_entries.get(iris_code) {
+                Some(stored_entry) => stored_entry,
+                None => {
+                    self.diff.added.push(iris_code.to_string());
+                    continue;
+                }
+            };
+
+            let fields = stored_entry.diff(entry).map_err(StorageError::from)?;
+            match fields.is_empty() {
+                true => self.diff.unchanged += 1,
+                false => self.diff.modified.push(ModifiedEntry {
+                    iris_code: iris_code.to_string(),
+                    fields,
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    //The stored entries which were not compared would be kept.
+    pub fn finish(mut self) -> EntryDomainResult<EntriesDiff> {
+        for entry in self.storage.iter_entries()? {
+            if let Some(iris_code) = entry?.iris_code {
+                if !self.compared.contains(&iris_code) {
+                    self.diff.kept.push(iris_code);
+                }
+            }
+        }
+        Ok(self.diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::TestEntryStorage;
+
+    fn get_entry(iris_code: &str, global: Option<f64>) -> Entry {
+        Entry::new(
+            global,
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn stored_entries_absent_from_the_input_are_kept() {
+        let storage = TestEntryStorage::new(&[
+            get_entry("1", Some(100.0)),
+            get_entry("2", Some(120.0)),
+            get_entry("3", Some(90.0)),
+        ]);
+        let mut differ = EntriesDiffer::new(&storage);
+        differ
+            .compare(&[get_entry("1", Some(100.0)), get_entry("4", Some(80.0))])
+            .unwrap();
+        differ.compare(&[get_entry("2", Some(110.0))]).unwrap();
+
+        let diff = differ.finish().unwrap();
+        assert_eq!(diff.added, vec!["4".to_string()]);
+        assert_eq!(diff.kept, vec!["3".to_string()]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].iris_code, "2");
+        assert_eq!(diff.unchanged, 1);
+        //Nothing is written.
+        assert_eq!(storage.get_stored().len(), 3);
+    }
+}
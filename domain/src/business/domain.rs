@@ -1,9 +1,18 @@
+use crate::business::aggregates::{get_scores, Aggregates, Averages, ScoresSum};
 use crate::business::error::*;
-use crate::business::traits::EntryDomainTrait;
+use crate::business::traits::{EntryDomainIterator, EntryDomainTrait, FlatEntryDomainIterator};
 use crate::core::entry::*;
-use crate::storage::traits::{EntryStorageTrait, IndexStoragePostalTrait, IndexStorageTrait};
+use crate::core::flat_entry::FlatEntry;
+use crate::storage::traits::{
+    EntryStorageTrait, HierarchyStorageTrait, IndexStoragePostalTrait, IndexStorageTrait,
+};
+use serde_json::Value;
 use std::boxed::Box;
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+//Entries rewritten at once when the national averages change.
+const REFRESH_BATCH_SIZE: usize = 1000;
 
 pub struct EntryDomain {
     pub idx_regions: Box<dyn IndexStorageTrait>,
@@ -12,6 +21,9 @@ pub struct EntryDomain {
     pub idx_departments_by_region: Box<dyn IndexStorageTrait>,
     pub idx_insee_coms: Box<dyn IndexStorageTrait>,
     pub entry_datastore: Box<dyn EntryStorageTrait>,
+    pub idx_hierarchy: Option<Box<dyn HierarchyStorageTrait>>,
+    //Sum of the scores of all the entries, computed on the first write then kept up to date.
+    national_sum: Mutex<Option<ScoresSum>>,
 }
 
 impl EntryDomain {
@@ -30,13 +42,273 @@ impl EntryDomain {
             idx_insee_coms,
             idx_departments_by_region,
             entry_datastore,
+            idx_hierarchy: None,
+            national_sum: Mutex::new(None),
+        }
+    }
+
+    //Remove the deleted IRIS codes from the hierarchy written by the import too.
+    pub fn with_hierarchy(mut self, idx_hierarchy: Box<dyn HierarchyStorageTrait>) -> Self {
+        self.idx_hierarchy = Some(idx_hierarchy);
+        self
+    }
+
+    //Run a write with the national sum of the scores. The writes are serialized so the sum
+    //follows the stored entries, it is computed again after a write failing on the storages.
+    //Nothing is written while some entries lack the information score.
+    pub(crate) fn write<T>(
+        &self,
+        write: impl FnOnce(&mut ScoresSum) -> EntryDomainResult<T>,
+    ) -> EntryDomainResult<T> {
+        let mut national_sum = match self.national_sum.lock() {
+            Ok(national_sum) => national_sum,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if national_sum.is_none() {
+            let outdated = self.entry_datastore.count_outdated_entries()?;
+            if outdated > 0 {
+                return Err(EntryDomainError::OutdatedEntries(outdated));
+            }
+            let mut sum = ScoresSum::default();
+            for entry in self.entry_datastore.iter_entries()? {
+                sum.add(&get_scores(&entry?));
+            }
+            *national_sum = Some(sum);
+        }
+
+        let result = write(national_sum.get_or_insert_with(ScoresSum::default));
+        if let Err(error) = &result {
+            if !matches!(error, EntryDomainError::NotFoundError) {
+                *national_sum = None;
+            }
+        }
+        result
+    }
+
+    //Recompute the averages of the regions and departments touched by a write and the national
+    //ones, `removed` and `added` are the scores of the entries it replaced and stored. Only the
+    //entries of these territories are read, unless the national averages changed: they are
+    //stored in every entry. Return the number of entries rewritten.
+    pub(crate) fn refresh_territories(
+        &self,
+        national_sum: &mut ScoresSum,
+        removed: &[Averages],
+        added: &[Averages],
+        regions: &HashSet<String>,
+        departments: &HashSet<String>,
+    ) -> EntryDomainResult<usize> {
+        let previous_national = national_sum.get_averages();
+        //A write keeping the scores leaves the sum as it is, without rounding errors.
+        if removed != added {
+            for scores in removed {
+                national_sum.remove(scores);
+            }
+            for scores in added {
+                national_sum.add(scores);
+            }
+        }
+        let national = national_sum.get_averages();
+
+        let region_of = get_territories_of(&*self.idx_regions, regions)?;
+        let department_of = get_territories_of(&*self.idx_departments, departments)?;
+        let iris_codes: Vec<String> = region_of
+            .keys()
+            .chain(department_of.keys())
+            .cloned()
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        let entries: Vec<Entry> = self
+            .entry_datastore
+            .get_entries(&iris_codes)?
+            .into_values()
+            .collect();
+        let aggregates = Aggregates::new(&entries, national, region_of, department_of);
+
+        if national == previous_national {
+            let updated_entries: Vec<Entry> = entries
+                .iter()
+                .filter_map(|entry| aggregates.apply(entry))
+                .collect();
+            self.entry_datastore.update_entries(&updated_entries)?;
+            return Ok(updated_entries.len());
+        }
+
+        let mut refreshed = 0;
+        let mut updated_entries: Vec<Entry> = Vec::with_capacity(REFRESH_BATCH_SIZE);
+        for entry in self.entry_datastore.iter_entries()? {
+            if let Some(updated_entry) = aggregates.apply(&entry?) {
+                updated_entries.push(updated_entry);
+            }
+            if updated_entries.len() == REFRESH_BATCH_SIZE {
+                self.entry_datastore.update_entries(&updated_entries)?;
+                refreshed += updated_entries.len();
+                updated_entries.clear();
+            }
+        }
+        self.entry_datastore.update_entries(&updated_entries)?;
+        Ok(refreshed + updated_entries.len())
+    }
+
+    //Regions and departments of an IRIS code in the indexes.
+    fn find_territories(
+        &self,
+        iris_code: &str,
+    ) -> EntryDomainResult<(HashSet<String>, HashSet<String>)> {
+        Ok((
+            self.idx_regions
+                .find_keys(iris_code.to_string())?
+                .into_iter()
+                .collect(),
+            self.idx_departments
+                .find_keys(iris_code.to_string())?
+                .into_iter()
+                .collect(),
+        ))
+    }
+
+    //Remove a deleted IRIS code from the territory and postal indexes and from the hierarchy.
+    fn remove_from_indexes(&self, iris_code: &str) -> EntryDomainResult<()> {
+        remove_from_index(&*self.idx_regions, iris_code)?;
+        for department in remove_from_index(&*self.idx_departments, iris_code)? {
+            remove_from_index(&*self.idx_departments_by_region, &department)?;
+        }
+        for insee_com in remove_from_index(&*self.idx_insee_coms, iris_code)? {
+            for city in self.idx_cities.find_keys(insee_com)? {
+                self.idx_cities.delete_index(city)?;
+            }
+        }
+
+        if let Some(idx_hierarchy) = &self.idx_hierarchy {
+            if let Some(mut hierarchy) = idx_hierarchy.get_hierarchy()? {
+                hierarchy.remove(&[iris_code.to_string()].iter().cloned().collect());
+                idx_hierarchy.set_hierarchy(&hierarchy)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+//Territory of each IRIS code of the index.
+pub(crate) fn get_territories(
+    index: &dyn IndexStorageTrait,
+) -> EntryDomainResult<HashMap<String, String>> {
+    let mut territories: HashMap<String, String> = HashMap::new();
+    for key in index.get_all_keys()? {
+        for iris_code in index.get_index(key.to_string())?.unwrap_or(Vec::new()) {
+            territories.insert(iris_code, key.to_string());
+        }
+    }
+    Ok(territories)
+}
+
+//Name of each INSEE commune code of the postal index, from its "postal code - name" keys.
+fn get_commune_names(
+    index: &dyn IndexStoragePostalTrait,
+) -> EntryDomainResult<HashMap<String, String>> {
+    let mut communes: HashMap<String, String> = HashMap::new();
+    for key in index.get_all_keys()? {
+        if let Some(insee_com) = index.get_index(key.to_string())?.and_then(|iris| iris.code) {
+            let name = key.split_once(" - ").map_or(key.as_str(), |(_, name)| name);
+            communes.insert(insee_com, name.to_string());
+        }
+    }
+    Ok(communes)
+}
+
+//Territory of each IRIS code of the given keys of the index.
+fn get_territories_of(
+    index: &dyn IndexStorageTrait,
+    keys: &HashSet<String>,
+) -> EntryDomainResult<HashMap<String, String>> {
+    let mut territories: HashMap<String, String> = HashMap::new();
+    for key in keys {
+        for iris_code in index.get_index(key.to_string())?.unwrap_or(Vec::new()) {
+            territories.insert(iris_code, key.to_string());
+        }
+    }
+    Ok(territories)
+}
+
+//Remove the value from the index, return the keys left without any value.
+pub(crate) fn remove_from_index(
+    index: &dyn IndexStorageTrait,
+    value: &str,
+) -> EntryDomainResult<Vec<String>> {
+    move_in_index(index, "", value)
+}
+
+//Remove the value from the other keys of the index and add it to `key`, "" to only remove it.
+//Return the keys left without any value.
+pub(crate) fn move_in_index(
+    index: &dyn IndexStorageTrait,
+    key: &str,
+    value: &str,
+) -> EntryDomainResult<Vec<String>> {
+    let mut emptied_keys = Vec::new();
+    for previous_key in index.find_keys(value.to_string())? {
+        if previous_key == key {
+            continue;
+        }
+        let values: Vec<String> = index
+            .get_index(previous_key.to_string())?
+            .unwrap_or(Vec::new())
+            .into_iter()
+            .filter(|stored_value| stored_value != value)
+            .collect();
+
+        match values.is_empty() {
+            true => {
+                index.delete_index(previous_key.to_string())?;
+                emptied_keys.push(previous_key);
+            }
+            false => index.update_index(previous_key, values)?,
+        }
+    }
+
+    if !key.is_empty() {
+        let mut values = index.get_index(key.to_string())?.unwrap_or(Vec::new());
+        if !values.iter().any(|stored_value| stored_value == value) {
+            values.push(value.to_string());
+            index.update_index(key.to_string(), values)?;
         }
     }
+    Ok(emptied_keys)
 }
 
 impl EntryDomainTrait for EntryDomain {
     fn get_all(&self) -> EntryDomainResult<Vec<Entry>> {
-        Ok(Vec::new())
+        self.iter_all()?.collect()
+    }
+
+    fn iter_all(&self) -> EntryDomainResult<EntryDomainIterator<'_>> {
+        let entries = self.entry_datastore.iter_entries()?;
+        Ok(Box::new(
+            entries.map(|entry| entry.map_err(EntryDomainError::from)),
+        ))
+    }
+
+    fn iter_flat_entries(&self) -> EntryDomainResult<FlatEntryDomainIterator<'_>> {
+        let regions = get_territories(&*self.idx_regions)?;
+        let departments = get_territories(&*self.idx_departments)?;
+        let insee_coms = get_territories(&*self.idx_insee_coms)?;
+        let communes = get_commune_names(&*self.idx_cities)?;
+        let entries = self.iter_all()?;
+        Ok(Box::new(entries.map(move |entry| {
+            let entry = entry?;
+            let iris_code = entry.iris_code.clone().unwrap_or_default();
+            let insee_com = insee_coms.get(&iris_code).cloned();
+            let commune = insee_com
+                .as_ref()
+                .and_then(|insee_com| communes.get(insee_com).cloned());
+            Ok(FlatEntry::new(
+                &entry,
+                regions.get(&iris_code).cloned(),
+                departments.get(&iris_code).cloned(),
+                insee_com,
+            )
+            .with_commune(commune))
+        })))
     }
 
     fn get_regions(&self) -> EntryDomainResult<Vec<String>> {
@@ -62,36 +334,46 @@ impl EntryDomainTrait for EntryDomain {
             .search_on_key(query, Some(department))
             .unwrap();
 
+        //get the insee_code and the districts codes of each city
+        let mut cities_districts: Vec<(String, String, Vec<String>)> = Vec::new();
         for city in cities.iter() {
-            //get the insee_code
             let iris = self.idx_cities.get_index(city.to_string())?;
-            let mut districts: Vec<District> = Vec::new();
-
-            let iris_code = match iris {
+            let (iris_code, districts) = match iris {
                 Some(iris) => {
                     let iris_code = iris.code.unwrap_or("".to_string());
-                    for district in self
+                    let districts = self
                         .idx_insee_coms
                         .get_index(iris_code.to_string())?
-                        .unwrap_or(Vec::new())
-                    {
-                        let entry = self.entry_datastore.get_entry(district.to_string())?;
-                        let district_design = match entry {
-                            Some(entry) => entry.iris_code_designation.unwrap_or("".to_string()),
-                            None => "".to_string(),
-                        };
-                        let district = District::new(district.to_string(), district_design);
-                        districts.push(district);
-                    }
-
-                    iris_code
+                        .unwrap_or(Vec::new());
+                    (iris_code, districts)
                 }
-                None => "".to_string(),
+                None => ("".to_string(), Vec::new()),
             };
+            cities_districts.push((city.to_string(), iris_code, districts));
+        }
+
+        //get the districts entries at once
+        let all_districts: Vec<String> = cities_districts
+            .iter()
+            .flat_map(|(_, _, districts)| districts.iter().cloned())
+            .collect();
+        let entries = self.entry_datastore.get_entries(&all_districts)?;
+
+        for (city, iris_code, city_districts) in cities_districts {
+            let mut districts: Vec<District> = Vec::new();
+            for district in city_districts {
+                let district_design = match entries.get(&district) {
+                    Some(entry) => entry
+                        .iris_code_designation
+                        .clone()
+                        .unwrap_or("".to_string()),
+                    None => "".to_string(),
+                };
+                districts.push(District::new(district, district_design));
+            }
 
-            //get the districts
             results.insert(
-                city.to_string(),
+                city,
                 CityDetail {
                     code_insee: Some(iris_code),
                     districts: Some(districts),
@@ -101,6 +383,18 @@ impl EntryDomainTrait for EntryDomain {
         Ok(results)
     }
 
+    fn search_city_names(&self, search: CityNameSearch) -> EntryDomainResult<Vec<String>> {
+        let names = match search {
+            CityNameSearch::Prefix(prefix) => self.idx_cities.search_prefix(prefix)?,
+            CityNameSearch::Range(start, end) => self.idx_cities.search_range(start, end)?,
+            CityNameSearch::Regex(pattern) => self.idx_cities.search_regex(pattern)?,
+            CityNameSearch::Fuzzy(query, distance) => {
+                self.idx_cities.search_fuzzy(query, distance)?
+            }
+        };
+        Ok(names)
+    }
+
     fn get_national_index(&self) -> EntryDomainResult<Entry> {
         match self.entry_datastore.get_national_entry().unwrap() {
             Some(national_entry) => Ok(national_entry),
@@ -212,7 +506,7 @@ impl EntryDomainTrait for EntryDomain {
         &self,
         code_insee: String,
     ) -> EntryDomainResult<HashMap<String, Entry>> {
-        let iris_codes_res = match self.idx_insee_coms.get_index(code_insee) {
+        let iris_codes_res = match self.idx_insee_coms.get_index(code_insee.to_string()) {
             Ok(optional_code) => match optional_code {
                 Some(codes) => codes.clone(),
                 None => Vec::new(),
@@ -220,19 +514,19 @@ impl EntryDomainTrait for EntryDomain {
             Err(_) => Vec::new(),
         };
 
-        let mut districts: HashMap<String, Entry> = HashMap::new();
-
-        for iris_code in iris_codes_res {
-            districts.insert(
-                iris_code.to_string(),
-                self.entry_datastore
-                    .get_entry(iris_code.to_string())
-                    .unwrap()
-                    .unwrap(),
+        let entries = self.entry_datastore.get_entries(&iris_codes_res)?;
+        let missing: Vec<&String> = iris_codes_res
+            .iter()
+            .filter(|iris_code| !entries.contains_key(*iris_code))
+            .collect();
+        if !missing.is_empty() {
+            log::warn!(
+                "IRIS codes of the commune {} without entry: {:?}",
+                code_insee,
+                missing
             );
         }
-
-        Ok(districts)
+        Ok(entries)
     }
 
     fn get_city_index(&self, code_insee: String) -> EntryDomainResult<Entry> {
@@ -244,19 +538,11 @@ impl EntryDomainTrait for EntryDomain {
             Err(_) => Vec::new(),
         };
 
-        let mut city_entries: Vec<Entry> = Vec::new();
-        for iris_code in iris_codes.iter() {
-            match self
-                .entry_datastore
-                .get_entry(iris_code.to_string())
-                .unwrap()
-            {
-                Some(neighbor_entry) => {
-                    city_entries.push(neighbor_entry);
-                }
-                None => {}
-            };
-        }
+        let mut neighbor_entries = self.entry_datastore.get_entries(&iris_codes)?;
+        let city_entries: Vec<Entry> = iris_codes
+            .iter()
+            .filter_map(|iris_code| neighbor_entries.remove(iris_code))
+            .collect();
 
         let num_of_neighbors = city_entries.len() as f64;
         let sum_of_global: f64 = city_entries.iter().map(|entry| entry.global.unwrap()).sum();
@@ -403,4 +689,273 @@ impl EntryDomainTrait for EntryDomain {
             None => Err(EntryDomainError::NotFoundError),
         }
     }
+
+    //The territory of the stored entry is kept, like its place in the indexes.
+    fn update_district(&self, iriscode: String, entry: Entry) -> EntryDomainResult<Entry> {
+        self.write(|national_sum| {
+            let mut entry = entry;
+            entry.iris_code = Some(iriscode.to_string());
+            let previous_entry = match self.entry_datastore.get_entry(iriscode.to_string())? {
+                Some(stored_entry) => stored_entry,
+                None => return Err(EntryDomainError::NotFoundError),
+            };
+            entry.territory = previous_entry.territory.clone();
+            let scores = get_scores(&entry);
+            if self
+                .entry_datastore
+                .update(iriscode.to_string(), entry)?
+                .is_none()
+            {
+                return Err(EntryDomainError::NotFoundError);
+            }
+
+            let (regions, departments) = self.find_territories(&iriscode)?;
+            self.refresh_territories(
+                national_sum,
+                &[get_scores(&previous_entry)],
+                &[scores],
+                &regions,
+                &departments,
+            )?;
+            self.get_district_index(iriscode)
+        })
+    }
+
+    fn patch_district(&self, iriscode: String, patch: Value) -> EntryDomainResult<Entry> {
+        self.write(|national_sum| {
+            let previous_entry = match self.entry_datastore.get_entry(iriscode.to_string())? {
+                Some(stored_entry) => stored_entry,
+                None => return Err(EntryDomainError::NotFoundError),
+            };
+            let entry = match self.entry_datastore.patch(iriscode.to_string(), &patch)? {
+                Some(entry) => entry,
+                None => return Err(EntryDomainError::NotFoundError),
+            };
+
+            let (regions, departments) = self.find_territories(&iriscode)?;
+            self.refresh_territories(
+                national_sum,
+                &[get_scores(&previous_entry)],
+                &[get_scores(&entry)],
+                &regions,
+                &departments,
+            )?;
+            self.get_district_index(iriscode)
+        })
+    }
+
+    fn delete_district(&self, iriscode: String) -> EntryDomainResult<Entry> {
+        self.write(|national_sum| {
+            if self
+                .entry_datastore
+                .get_entry(iriscode.to_string())?
+                .is_none()
+            {
+                return Err(EntryDomainError::NotFoundError);
+            }
+
+            //The territories are read before the IRIS code leaves the indexes, which is done
+            //before the entry is deleted: a failure can leave an entry out of the indexes, never
+            //an index pointing to a deleted entry.
+            let (regions, departments) = self.find_territories(&iriscode)?;
+            self.remove_from_indexes(&iriscode)?;
+            let deleted_entry = match self.entry_datastore.delete(iriscode.to_string())? {
+                Some(entry) => entry,
+                None => return Err(EntryDomainError::NotFoundError),
+            };
+            self.refresh_territories(
+                national_sum,
+                &[get_scores(&deleted_entry)],
+                &[],
+                &regions,
+                &departments,
+            )?;
+            Ok(deleted_entry)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hierarchy::Hierarchy;
+    use crate::storage::testing::*;
+    use serde_json::json;
+
+    fn get_entry(iris_code: &str, global: f64, information_score: f64) -> Entry {
+        Entry::new(
+            Some(global),
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            Some(
+                InformationAccess::new(Some(500.0), None, None, None, None, None, None, None)
+                    .with_information_score(Some(information_score)),
+            ),
+            None,
+            None,
+            None,
+        )
+    }
+
+    const REGIONS: &[(&str, &[&str])] = &[("R1", &["1", "2"]), ("R2", &["3", "4"])];
+    const DEPARTMENTS: &[(&str, &[&str])] = &[("D1", &["1"]), ("D2", &["2"]), ("D3", &["3", "4"])];
+
+    //Domain on the entries, stored with their current averages like after an import.
+    fn get_domain(entries: Vec<Entry>) -> (EntryDomain, TestEntryStorage) {
+        let mut national = ScoresSum::default();
+        for entry in &entries {
+            national.add(&get_scores(entry));
+        }
+        let all_territories = |territories: &[(&str, &[&str])]| {
+            territories
+                .iter()
+                .flat_map(|(territory, iris_codes)| {
+                    iris_codes
+                        .iter()
+                        .map(move |iris_code| (iris_code.to_string(), territory.to_string()))
+                })
+                .collect::<HashMap<String, String>>()
+        };
+        let aggregates = Aggregates::new(
+            &entries,
+            national.get_averages(),
+            all_territories(REGIONS),
+            all_territories(DEPARTMENTS),
+        );
+        let entries: Vec<Entry> = entries
+            .iter()
+            .map(|entry| aggregates.apply(entry).unwrap_or_else(|| entry.clone()))
+            .collect();
+
+        let storage = TestEntryStorage::new(&entries);
+        let domain = EntryDomain::new(
+            Box::new(TestIndexStorage::new(REGIONS)),
+            Box::new(TestIndexStorage::new(DEPARTMENTS)),
+            Box::new(TestIndexStoragePostal::default()),
+            Box::new(TestIndexStorage::default()),
+            Box::new(TestIndexStorage::default()),
+            Box::new(storage.clone()),
+        );
+        (domain, storage)
+    }
+
+    fn get_entries() -> Vec<Entry> {
+        vec![
+            get_entry("1", 100.0, 80.0),
+            get_entry("2", 120.0, 60.0),
+            get_entry("3", 90.0, 70.0),
+            get_entry("4", 110.0, 50.0),
+        ]
+    }
+
+    #[test]
+    fn noop_patch_leaves_every_entry_unchanged() {
+        let (domain, storage) = get_domain(get_entries());
+        let stored = storage.get_stored();
+
+        domain
+            .patch_district("1".to_string(), json!({"global": 100.0}))
+            .unwrap();
+        domain.patch_district("3".to_string(), json!({})).unwrap();
+        assert_eq!(storage.get_stored(), stored);
+    }
+
+    #[test]
+    fn patch_refreshes_the_territories_of_the_district() {
+        let (domain, storage) = get_domain(get_entries());
+        let stored = storage.get_stored();
+
+        let patched = domain
+            .patch_district("1".to_string(), json!({"global": 140.0}))
+            .unwrap();
+        assert_eq!(patched.global_region, Some(130.0));
+        assert_eq!(patched.global_dept, Some(140.0));
+        assert_eq!(patched.global_national, Some(115.0));
+
+        let other = domain.get_district_index("3".to_string()).unwrap();
+        assert_eq!(other.global_region, Some(100.0));
+        assert_eq!(other.global_national, Some(115.0));
+        assert_ne!(storage.get_stored()["3"], stored["3"]);
+    }
+
+    #[test]
+    fn delete_takes_the_district_out_of_the_averages() {
+        let (domain, _) = get_domain(get_entries());
+
+        domain.delete_district("4".to_string()).unwrap();
+        let entry = domain.get_district_index("3".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(90.0));
+        assert_eq!(entry.global_dept, Some(90.0));
+        assert_eq!(entry.global_national, Some((310.0_f64 / 3.0) as f32 as f64));
+        assert_eq!(
+            entry.information_access.unwrap().global_national,
+            Some(70.0)
+        );
+    }
+
+    #[test]
+    fn delete_removes_the_district_from_the_indexes_and_the_hierarchy() {
+        let mut hierarchy = Hierarchy::default();
+        hierarchy.add("R2", "D3", "", "C3", "3");
+        hierarchy.add("R2", "D3", "", "C3", "4");
+        let idx_hierarchy = TestHierarchyStorage::new(hierarchy);
+        let (domain, _) = get_domain(get_entries());
+        let domain = domain.with_hierarchy(Box::new(idx_hierarchy.clone()));
+
+        domain.delete_district("4".to_string()).unwrap();
+        assert!(domain
+            .idx_regions
+            .find_keys("4".to_string())
+            .unwrap()
+            .is_empty());
+        assert!(domain
+            .idx_departments
+            .find_keys("4".to_string())
+            .unwrap()
+            .is_empty());
+        let hierarchy = idx_hierarchy.get_hierarchy().unwrap().unwrap();
+        assert_eq!(
+            hierarchy.get_iris_codes(),
+            ["3".to_string()].iter().cloned().collect()
+        );
+
+        assert!(matches!(
+            domain.delete_district("4".to_string()),
+            Err(EntryDomainError::NotFoundError)
+        ));
+    }
+
+    #[test]
+    fn writes_keep_the_information_averages_of_a_database_without_information_score() {
+        //Entries written before the information score, with the averages of their import.
+        let entries = get_entries()
+            .into_iter()
+            .map(|mut entry| {
+                let axis = entry.information_access.as_mut().unwrap();
+                axis.information_score = None;
+                axis.global_region = Some(450.0);
+                axis.global_dept = Some(400.0);
+                axis.global_national = Some(500.0);
+                entry
+            })
+            .collect();
+        let (domain, _) = get_domain(entries);
+
+        domain
+            .patch_district("1".to_string(), json!({"global": 140.0}))
+            .unwrap();
+        domain.delete_district("4".to_string()).unwrap();
+        for iris_code in &["1", "2", "3"] {
+            let entry = domain.get_district_index(iris_code.to_string()).unwrap();
+            let axis = entry.information_access.unwrap();
+            assert_eq!(axis.global_region, Some(450.0));
+            assert_eq!(axis.global_dept, Some(400.0));
+            assert_eq!(axis.global_national, Some(500.0));
+        }
+        let entry = domain.get_district_index("2".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(130.0));
+    }
 }
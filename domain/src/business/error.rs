@@ -11,6 +11,8 @@ pub enum EntryDomainError {
     StorageError,
     #[error("Not found error")]
     NotFoundError,
+    #[error("{0} entries were stored before the information score, import the entries again")]
+    OutdatedEntries(usize),
     #[error("Storage error: {source}")]
     Storage {
         #[from]
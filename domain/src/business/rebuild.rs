@@ -0,0 +1,192 @@
+use crate::business::domain::{get_territories, EntryDomain};
+use crate::business::error::*;
+use crate::core::entry::{Entry, Territory};
+use crate::core::hierarchy::Hierarchy;
+use crate::storage::traits::EntryStorageTrait;
+use std::collections::{BTreeMap, HashMap};
+
+//A rebuilt index with less than this share of the values of the current one is not written.
+const MIN_REBUILT_SHARE: f64 = 0.5;
+
+//Territory indexes and hierarchy rebuilt from the territories stored in the entries.
+#[derive(Debug, Default)]
+pub struct RebuiltIndexes {
+    pub entries: usize,
+    pub regions: BTreeMap<String, Vec<String>>,
+    pub departments: BTreeMap<String, Vec<String>>,
+    pub insee_coms: BTreeMap<String, Vec<String>>,
+    pub hierarchy: Hierarchy,
+    //IRIS codes of the entries stored without their territory, they are in no index.
+    pub without_territory: Vec<String>,
+}
+
+impl RebuiltIndexes {
+    //The IRIS codes are indexed in the order of the storage, the unassigned keys are left out
+    //like in the import.
+    pub fn from_storage(storage: &dyn EntryStorageTrait) -> EntryDomainResult<Self> {
+        let mut indexes = RebuiltIndexes::default();
+        for entry in storage.iter_entries()? {
+            let entry = entry?;
+            let iris_code = match entry.iris_code {
+                Some(iris_code) => iris_code,
+                None => continue,
+            };
+            indexes.entries += 1;
+            match entry.territory {
+                Some(territory) => indexes.add(&iris_code, &territory),
+                None => indexes.without_territory.push(iris_code),
+            }
+        }
+        Ok(indexes)
+    }
+
+    //Number of values of each rebuilt index by name, the IRIS codes for the hierarchy.
+    pub fn get_sizes(&self) -> Vec<(&'static str, usize)> {
+        let count = |index: &BTreeMap<String, Vec<String>>| index.values().map(Vec::len).sum();
+        vec![
+            ("insee_coms", count(&self.insee_coms)),
+            ("regions", count(&self.regions)),
+            ("departments", count(&self.departments)),
+            (
+                "departments_by_region",
+                count(&self.hierarchy.get_departments_by_region()),
+            ),
+            ("hierarchy", self.hierarchy.get_iris_codes().len()),
+        ]
+    }
+
+    fn add(&mut self, iris_code: &str, territory: &Territory) {
+        add_to_index(&mut self.regions, &territory.region, iris_code);
+        add_to_index(&mut self.departments, &territory.department, iris_code);
+        add_to_index(&mut self.insee_coms, &territory.insee_com, iris_code);
+        self.hierarchy.add(
+            &territory.region,
+            &territory.department,
+            &territory.epci,
+            &territory.insee_com,
+            iris_code,
+        );
+    }
+}
+
+//A rebuilt index replacing the current one (None when it is lost) by an empty or a much smaller
+//one comes from entries without their territory, it would not be written.
+pub fn is_shrunk(rebuilt: usize, current: Option<usize>) -> bool {
+    match current {
+        _ if rebuilt == 0 => true,
+        Some(current) => (rebuilt as f64) < current as f64 * MIN_REBUILT_SHARE,
+        None => false,
+    }
+}
+
+fn add_to_index(index: &mut BTreeMap<String, Vec<String>>, key: &str, iris_code: &str) {
+    if !key.is_empty() {
+        index
+            .entry(key.to_string())
+            .or_default()
+            .push(iris_code.to_string());
+    }
+}
+
+impl EntryDomain {
+    //Store the territory of the entries which have none, from the territory indexes and the EPCI
+    //of each IRIS code. Return the number of entries updated.
+    pub fn fill_territories(&self, epcis: &HashMap<String, String>) -> EntryDomainResult<usize> {
+        let regions = get_territories(&*self.idx_regions)?;
+        let departments = get_territories(&*self.idx_departments)?;
+        let insee_coms = get_territories(&*self.idx_insee_coms)?;
+        let key = |keys: &HashMap<String, String>, iris_code: &str| {
+            keys.get(iris_code).cloned().unwrap_or_default()
+        };
+
+        let mut updated_entries: Vec<Entry> = Vec::new();
+        for entry in self.entry_datastore.iter_entries()? {
+            let entry = entry?;
+            let iris_code = match (&entry.iris_code, &entry.territory) {
+                (Some(iris_code), None) => iris_code.to_string(),
+                _ => continue,
+            };
+            updated_entries.push(entry.with_territory(Territory {
+                region: key(&regions, &iris_code),
+                department: key(&departments, &iris_code),
+                epci: key(epcis, &iris_code),
+                insee_com: key(&insee_coms, &iris_code),
+            }));
+        }
+
+        self.entry_datastore.update_entries(&updated_entries)?;
+        Ok(updated_entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::hierarchy::UNASSIGNED_EPCI;
+    use crate::storage::testing::TestEntryStorage;
+
+    fn get_entry(iris_code: &str, territory: Option<[&str; 4]>) -> Entry {
+        let entry = Entry::new(
+            Some(100.0),
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        );
+        match territory {
+            Some([region, department, epci, insee_com]) => entry.with_territory(Territory {
+                region: region.to_string(),
+                department: department.to_string(),
+                epci: epci.to_string(),
+                insee_com: insee_com.to_string(),
+            }),
+            None => entry,
+        }
+    }
+
+    #[test]
+    fn rebuilds_the_indexes_from_the_territories() {
+        let storage = TestEntryStorage::new(&[
+            get_entry("1", Some(["R1", "D1", "E1", "C1"])),
+            get_entry("2", Some(["R1", "D2", "", "C2"])),
+            get_entry("3", Some(["R2", "D3", "E3", ""])),
+            get_entry("4", None),
+        ]);
+        let indexes = RebuiltIndexes::from_storage(&storage).unwrap();
+
+        assert_eq!(indexes.entries, 4);
+        assert_eq!(indexes.without_territory, vec!["4"]);
+        assert_eq!(indexes.regions["R1"], vec!["1", "2"]);
+        assert_eq!(indexes.departments["D3"], vec!["3"]);
+        assert!(!indexes.insee_coms.contains_key(""));
+        assert_eq!(
+            indexes.hierarchy.get_regions()["R1"]["D2"][UNASSIGNED_EPCI]["C2"],
+            vec!["2"]
+        );
+        assert_eq!(
+            indexes.get_sizes(),
+            vec![
+                ("insee_coms", 2),
+                ("regions", 3),
+                ("departments", 3),
+                ("departments_by_region", 2),
+                ("hierarchy", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn refuses_empty_and_much_smaller_indexes() {
+        assert!(is_shrunk(0, None));
+        assert!(is_shrunk(0, Some(10)));
+        assert!(is_shrunk(4, Some(10)));
+        assert!(!is_shrunk(5, Some(10)));
+        assert!(!is_shrunk(12, Some(10)));
+        assert!(!is_shrunk(3, None));
+    }
+}
@@ -1,9 +1,19 @@
 use crate::business::error::EntryDomainResult;
 use crate::core::entry::*;
+use crate::core::flat_entry::FlatEntry;
+use serde_json::Value;
 use std::collections::{HashMap, BTreeMap};
 
+//Lazy iteration over all the entries of the domain.
+pub type EntryDomainIterator<'a> = Box<dyn Iterator<Item = EntryDomainResult<Entry>> + 'a>;
+
+//Lazy iteration over all the entries, flattened with their territory keys.
+pub type FlatEntryDomainIterator<'a> = Box<dyn Iterator<Item = EntryDomainResult<FlatEntry>> + 'a>;
+
 pub trait EntryDomainTrait: Sync + Send {
     fn get_all(&self) -> EntryDomainResult<Vec<Entry>>;
+    fn iter_all(&self) -> EntryDomainResult<EntryDomainIterator<'_>>;
+    fn iter_flat_entries(&self) -> EntryDomainResult<FlatEntryDomainIterator<'_>>;
     fn get_regions(&self) -> EntryDomainResult<Vec<String>>;
     fn get_departments(&self) -> EntryDomainResult<Vec<String>>;
     fn get_cities(&self) -> EntryDomainResult<Vec<String>>;
@@ -12,6 +22,7 @@ pub trait EntryDomainTrait: Sync + Send {
         department: String,
         query: String,
     ) -> EntryDomainResult<BTreeMap<String, CityDetail>>;
+    fn search_city_names(&self, search: CityNameSearch) -> EntryDomainResult<Vec<String>>;
     fn get_national_index(&self) -> EntryDomainResult<Entry>;
     fn get_regional_index(&self, region: String) -> EntryDomainResult<Entry>;
     fn get_in_regional_index(&self, region: String) -> EntryDomainResult<HashMap<String, Entry>>;
@@ -21,4 +32,7 @@ pub trait EntryDomainTrait: Sync + Send {
     fn get_city_districts_index(&self, code_insee: String) -> EntryDomainResult<HashMap<String, Entry>>;
     fn get_all_regions_index(&self) -> EntryDomainResult<HashMap<String, Entry>>;
     fn get_district_index(&self, iriscode: String) -> EntryDomainResult<Entry>;
+    fn update_district(&self, iriscode: String, entry: Entry) -> EntryDomainResult<Entry>;
+    fn patch_district(&self, iriscode: String, patch: Value) -> EntryDomainResult<Entry>;
+    fn delete_district(&self, iriscode: String) -> EntryDomainResult<Entry>;
 }
@@ -0,0 +1,244 @@
+use crate::business::aggregates::get_scores;
+use crate::business::domain::{get_territories, move_in_index, remove_from_index, EntryDomain};
+use crate::business::error::*;
+use crate::core::entry::Entry;
+use crate::storage::error::StorageError;
+use std::collections::HashSet;
+
+//Entry of a partial import, with the keys of its territories in the indexes ("" when unassigned).
+pub struct UpsertEntry {
+    pub entry: Entry,
+    pub region: String,
+    pub department: String,
+    pub insee_com: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct UpsertReport {
+    pub added: usize,
+    pub replaced: usize,
+    //Entries rewritten with the new averages.
+    pub refreshed: usize,
+    pub regions: usize,
+    pub departments: usize,
+}
+
+impl EntryDomain {
+    //Insert or replace the entries and move them to their territories in the indexes.
+    //Only the averages of the territories they leave or join are recomputed, with the national ones.
+    pub fn upsert(&self, entries: &[UpsertEntry]) -> EntryDomainResult<UpsertReport> {
+        self.write(|national_sum| {
+            let mut report = UpsertReport::default();
+            let previous_regions = get_territories(&*self.idx_regions)?;
+            let previous_departments = get_territories(&*self.idx_departments)?;
+            let mut regions: HashSet<String> = HashSet::new();
+            let mut departments: HashSet<String> = HashSet::new();
+            let mut removed_scores = Vec::new();
+            let mut added_scores = Vec::with_capacity(entries.len());
+
+            for upsert_entry in entries {
+                let iris_code = match &upsert_entry.entry.iris_code {
+                    Some(iris_code) => iris_code,
+                    None => return Err(StorageError::CreationImpossible.into()),
+                };
+                regions.extend(previous_regions.get(iris_code).cloned());
+                departments.extend(previous_departments.get(iris_code).cloned());
+                regions.insert(upsert_entry.region.to_string());
+                departments.insert(upsert_entry.department.to_string());
+
+                added_scores.push(get_scores(&upsert_entry.entry));
+                match self
+                    .entry_datastore
+                    .update(iris_code.to_string(), upsert_entry.entry.clone())?
+                {
+                    Some(previous_entry) => {
+                        removed_scores.push(get_scores(&previous_entry));
+                        report.replaced += 1;
+                    }
+                    None => {
+                        self.entry_datastore
+                            .create(iris_code.to_string(), upsert_entry.entry.clone())?;
+                        report.added += 1;
+                    }
+                }
+                self.move_in_indexes(iris_code, upsert_entry)?;
+            }
+
+            regions.remove("");
+            departments.remove("");
+            report.regions = regions.len();
+            report.departments = departments.len();
+            report.refreshed = self.refresh_territories(
+                national_sum,
+                &removed_scores,
+                &added_scores,
+                &regions,
+                &departments,
+            )?;
+            Ok(report)
+        })
+    }
+
+    fn move_in_indexes(
+        &self,
+        iris_code: &str,
+        upsert_entry: &UpsertEntry,
+    ) -> EntryDomainResult<()> {
+        move_in_index(&*self.idx_regions, &upsert_entry.region, iris_code)?;
+        for department in
+            move_in_index(&*self.idx_departments, &upsert_entry.department, iris_code)?
+        {
+            remove_from_index(&*self.idx_departments_by_region, &department)?;
+        }
+        if !upsert_entry.region.is_empty() && !upsert_entry.department.is_empty() {
+            move_in_index(
+                &*self.idx_departments_by_region,
+                &upsert_entry.region,
+                &upsert_entry.department,
+            )?;
+        }
+        for insee_com in move_in_index(&*self.idx_insee_coms, &upsert_entry.insee_com, iris_code)? {
+            for city in self.idx_cities.find_keys(insee_com)? {
+                self.idx_cities.delete_index(city)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::business::traits::EntryDomainTrait;
+    use crate::storage::testing::*;
+    use crate::storage::traits::IndexStorageTrait;
+
+    fn get_upsert_entry(
+        iris_code: &str,
+        global: f64,
+        region: &str,
+        department: &str,
+        insee_com: &str,
+    ) -> UpsertEntry {
+        UpsertEntry {
+            entry: Entry::new(
+                Some(global),
+                None,
+                None,
+                None,
+                Some(iris_code.to_string()),
+                None,
+                None,
+                None,
+                None,
+                None,
+            ),
+            region: region.to_string(),
+            department: department.to_string(),
+            insee_com: insee_com.to_string(),
+        }
+    }
+
+    //Domain on empty storages, filled by a first upsert of three IRIS codes.
+    fn get_domain() -> EntryDomain {
+        let domain = EntryDomain::new(
+            Box::new(TestIndexStorage::default()),
+            Box::new(TestIndexStorage::default()),
+            Box::new(TestIndexStoragePostal::default()),
+            Box::new(TestIndexStorage::default()),
+            Box::new(TestIndexStorage::default()),
+            Box::new(TestEntryStorage::default()),
+        );
+        domain
+            .upsert(&[
+                get_upsert_entry("1", 100.0, "R1", "D1", "C1"),
+                get_upsert_entry("2", 120.0, "R1", "D2", "C2"),
+                get_upsert_entry("3", 90.0, "R2", "D3", "C3"),
+            ])
+            .unwrap();
+        domain
+    }
+
+    fn get_index(index: &dyn IndexStorageTrait, key: &str) -> Option<Vec<String>> {
+        index.get_index(key.to_string()).unwrap()
+    }
+
+    #[test]
+    fn upsert_adds_the_new_entries_to_their_territories() {
+        let domain = get_domain();
+        let report = domain
+            .upsert(&[get_upsert_entry("4", 110.0, "R2", "D3", "C3")])
+            .unwrap();
+        assert_eq!((report.added, report.replaced), (1, 0));
+        assert_eq!((report.regions, report.departments), (1, 1));
+
+        assert_eq!(
+            get_index(&*domain.idx_regions, "R2"),
+            Some(vec!["3".to_string(), "4".to_string()])
+        );
+        assert_eq!(
+            get_index(&*domain.idx_insee_coms, "C3"),
+            Some(vec!["3".to_string(), "4".to_string()])
+        );
+        let entry = domain.get_district_index("4".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(100.0));
+        assert_eq!(entry.global_dept, Some(100.0));
+        assert_eq!(entry.global_national, Some(105.0));
+    }
+
+    #[test]
+    fn upsert_moves_the_replaced_entries_to_their_new_territories() {
+        let domain = get_domain();
+        let report = domain
+            .upsert(&[get_upsert_entry("1", 140.0, "R2", "D3", "C3")])
+            .unwrap();
+        assert_eq!((report.added, report.replaced), (0, 1));
+        //The territories the IRIS code leaves are refreshed too.
+        assert_eq!((report.regions, report.departments), (2, 2));
+
+        assert_eq!(
+            get_index(&*domain.idx_regions, "R1"),
+            Some(vec!["2".to_string()])
+        );
+        assert_eq!(get_index(&*domain.idx_departments, "D1"), None);
+        assert_eq!(get_index(&*domain.idx_insee_coms, "C1"), None);
+        assert_eq!(
+            get_index(&*domain.idx_departments_by_region, "R1"),
+            Some(vec!["D2".to_string()])
+        );
+
+        let entry = domain.get_district_index("1".to_string()).unwrap();
+        assert_eq!(entry.global, Some(140.0));
+        assert_eq!(entry.global_region, Some(115.0));
+        assert_eq!(entry.global_dept, Some(115.0));
+        let entry = domain.get_district_index("2".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(120.0));
+        assert_eq!(entry.global_national, Some((350.0_f64 / 3.0) as f32 as f64));
+    }
+
+    #[test]
+    fn upsert_refreshes_only_the_entries_whose_averages_change() {
+        let domain = get_domain();
+        let entry = domain.get_district_index("1".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(110.0));
+        assert_eq!(entry.global_dept, Some(100.0));
+        assert_eq!(entry.global_national, Some((310.0_f64 / 3.0) as f32 as f64));
+
+        //Same scores and territories: only the replaced entry gets its averages back.
+        let report = domain
+            .upsert(&[get_upsert_entry("2", 120.0, "R1", "D2", "C2")])
+            .unwrap();
+        assert_eq!(report.refreshed, 1);
+        let entry = domain.get_district_index("2".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(110.0));
+
+        //The national averages change, every entry is rewritten.
+        let report = domain
+            .upsert(&[get_upsert_entry("2", 150.0, "R1", "D2", "C2")])
+            .unwrap();
+        assert_eq!(report.refreshed, 3);
+        let entry = domain.get_district_index("3".to_string()).unwrap();
+        assert_eq!(entry.global_region, Some(90.0));
+        assert_eq!(entry.global_national, Some((340.0_f64 / 3.0) as f32 as f64));
+    }
+}
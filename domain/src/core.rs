@@ -1 +1,3 @@
 pub mod entry;
+pub mod flat_entry;
+pub mod hierarchy;
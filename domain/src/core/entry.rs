@@ -1,3 +1,4 @@
+use serde_json::{Map, Value};
 use std::collections::BTreeMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -12,6 +13,10 @@ pub struct Entry {
     pub numeric_interfaces_access: Option<NumericInterfacesAccess>,
     pub administrative_competencies: Option<AdministrativeCompetencies>,
     pub numeric_competencies: Option<NumericCompetencies>,
+    //Keys of the territories of the IRIS code in the indexes, the entries imported before it was
+    //stored have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub territory: Option<Territory>,
 }
 
 impl Entry {
@@ -38,8 +43,258 @@ impl Entry {
             numeric_interfaces_access,
             administrative_competencies,
             numeric_competencies,
+            territory: None,
         }
     }
+
+    pub fn with_territory(mut self, territory: Territory) -> Self {
+        self.territory = Some(territory);
+        self
+    }
+
+    /// Apply a JSON merge patch (RFC 7386) on the entry, the IRIS code and the territory cannot be
+    /// changed.
+    pub fn merge_patch(&self, patch: &Value) -> serde_json::Result<Entry> {
+        let mut value = serde_json::to_value(self)?;
+        merge_value(&mut value, patch);
+        let mut entry: Entry = serde_json::from_value(value)?;
+        entry.iris_code = self.iris_code.clone();
+        entry.territory = self.territory.clone();
+        Ok(entry)
+    }
+
+    /// Fields whose value differ in `other`, named by their path (`information_access.global`).
+    pub fn diff(&self, other: &Entry) -> serde_json::Result<Vec<FieldDiff>> {
+        let mut diffs = Vec::new();
+        diff_value(
+            "",
+            &serde_json::to_value(self)?,
+            &serde_json::to_value(other)?,
+            &mut diffs,
+        );
+        Ok(diffs)
+    }
+
+    /// Keep only the national scores of the entry.
+    pub fn to_national_entry(&self) -> Entry {
+        Entry::new(
+            self.global_national,
+            None,
+            None,
+            None,
+            None,
+            None,
+            self.information_access.as_ref().map(|axis| {
+                InformationAccess::new(
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.numeric_interfaces_access.as_ref().map(|axis| {
+                NumericInterfacesAccess::new(
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.administrative_competencies.as_ref().map(|axis| {
+                AdministrativeCompetencies::new(axis.global_national, None, None, None, None, None)
+            }),
+            self.numeric_competencies.as_ref().map(|axis| {
+                NumericCompetencies::new(axis.global_national, None, None, None, None, None)
+            }),
+        )
+    }
+
+    /// Keep the regional scores of the entry, compared to the national ones.
+    pub fn to_regional_entry(&self) -> Entry {
+        Entry::new(
+            self.global_region,
+            None,
+            None,
+            self.global_national,
+            None,
+            None,
+            self.information_access.as_ref().map(|axis| {
+                InformationAccess::new(
+                    axis.global_region,
+                    None,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.numeric_interfaces_access.as_ref().map(|axis| {
+                NumericInterfacesAccess::new(
+                    axis.global_region,
+                    None,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.administrative_competencies.as_ref().map(|axis| {
+                AdministrativeCompetencies::new(
+                    axis.global_region,
+                    None,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                )
+            }),
+            self.numeric_competencies.as_ref().map(|axis| {
+                NumericCompetencies::new(
+                    axis.global_region,
+                    None,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                )
+            }),
+        )
+    }
+
+    /// Keep the departmental scores of the entry, compared to the regional and national ones.
+    pub fn to_departmental_entry(&self) -> Entry {
+        Entry::new(
+            self.global_dept,
+            self.global_region,
+            None,
+            self.global_national,
+            None,
+            None,
+            self.information_access.as_ref().map(|axis| {
+                InformationAccess::new(
+                    axis.global_dept,
+                    axis.global_region,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.numeric_interfaces_access.as_ref().map(|axis| {
+                NumericInterfacesAccess::new(
+                    axis.global_dept,
+                    axis.global_region,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+            }),
+            self.administrative_competencies.as_ref().map(|axis| {
+                AdministrativeCompetencies::new(
+                    axis.global_dept,
+                    axis.global_region,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                )
+            }),
+            self.numeric_competencies.as_ref().map(|axis| {
+                NumericCompetencies::new(
+                    axis.global_dept,
+                    axis.global_region,
+                    None,
+                    axis.global_national,
+                    None,
+                    None,
+                )
+            }),
+        )
+    }
+}
+
+fn merge_value(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            match value {
+                Value::Null => {
+                    target.remove(key);
+                }
+                _ => merge_value(target.entry(key.as_str()).or_insert(Value::Null), value),
+            }
+        }
+    }
+}
+
+//Value of a field in two versions of an entry.
+#[derive(Debug, Serialize, Clone)]
+pub struct FieldDiff {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+fn diff_value(path: &str, before: &Value, after: &Value, diffs: &mut Vec<FieldDiff>) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            let added_keys = after.keys().filter(|key| !before.contains_key(*key));
+            for key in before.keys().chain(added_keys) {
+                let field = match path.is_empty() {
+                    true => key.to_string(),
+                    false => format!("{}.{}", path, key),
+                };
+                diff_value(
+                    &field,
+                    before.get(key).unwrap_or(&Value::Null),
+                    after.get(key).unwrap_or(&Value::Null),
+                    diffs,
+                );
+            }
+        }
+        _ if before != after => diffs.push(FieldDiff {
+            field: path.to_string(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+        _ => {}
+    }
+}
+
+//Keys of the territories of an IRIS code in the indexes and the hierarchy, "" when unassigned.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Territory {
+    pub region: String,
+    pub department: String,
+    pub epci: String,
+    pub insee_com: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -52,6 +307,10 @@ pub struct InformationAccess {
     pub single_person_percent: Option<f64>,
     pub number_of_public_service_per_citizen: Option<f64>,
     pub number_of_public_services: Option<f64>,
+    //ACCES A L'INFORMATION score, averaged into the regional, departmental and national scores of
+    //the axis. The entries imported before it was stored have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub information_score: Option<f64>,
 }
 
 impl InformationAccess {
@@ -74,8 +333,14 @@ impl InformationAccess {
             single_person_percent,
             number_of_public_service_per_citizen,
             number_of_public_services,
+            information_score: None,
         }
     }
+
+    pub fn with_information_score(mut self, information_score: Option<f64>) -> Self {
+        self.information_score = information_score;
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -213,6 +478,17 @@ impl SearchResult {
     }
 }
 
+//Query on the commune names of the postal index.
+#[derive(Debug, Clone)]
+pub enum CityNameSearch {
+    Prefix(String),
+    //Names from the first value (included) to the second one (excluded).
+    Range(String, String),
+    Regex(String),
+    //Names within a number of edits of the query.
+    Fuzzy(String, u32),
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CityDetail {
     pub code_insee: Option<String>,
@@ -233,3 +509,94 @@ impl District {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn get_entry() -> Entry {
+        Entry::new(
+            Some(101.5),
+            Some(100.0),
+            Some(99.0),
+            Some(98.0),
+            Some("751010101".to_string()),
+            Some("Saint-Germain".to_string()),
+            Some(InformationAccess::new(
+                Some(1.0),
+                Some(2.0),
+                None,
+                None,
+                Some(0.5),
+                None,
+                None,
+                None,
+            )),
+            None,
+            None,
+            None,
+        )
+        .with_territory(Territory {
+            region: "Ile-de-France".to_string(),
+            department: "75 - Paris".to_string(),
+            epci: "200054781 - Metropole du Grand Paris".to_string(),
+            insee_com: "75106".to_string(),
+        })
+    }
+
+    #[test]
+    fn merge_patch_replaces_the_given_fields() {
+        let entry = get_entry();
+        let patched = entry
+            .merge_patch(&json!({
+                "global": 120.0,
+                "information_access": {"monoparental_families_percent": 0.75}
+            }))
+            .unwrap();
+
+        assert_eq!(patched.global, Some(120.0));
+        assert_eq!(patched.global_region, entry.global_region);
+        let axis = patched.information_access.unwrap();
+        assert_eq!(axis.monoparental_families_percent, Some(0.75));
+        assert_eq!(axis.global, Some(1.0));
+    }
+
+    #[test]
+    fn merge_patch_removes_the_null_fields() {
+        let patched = get_entry()
+            .merge_patch(&json!({"global_dept": null, "information_access": null}))
+            .unwrap();
+
+        assert_eq!(patched.global_dept, None);
+        assert!(patched.information_access.is_none());
+        assert_eq!(patched.global, Some(101.5));
+    }
+
+    #[test]
+    fn merge_patch_keeps_the_iris_code_and_the_territory() {
+        let entry = get_entry();
+        let patched = entry
+            .merge_patch(&json!({
+                "iris_code": "130010101",
+                "territory": {"region": "Provence", "department": "13 - Bouches-du-Rhone"}
+            }))
+            .unwrap();
+
+        assert_eq!(patched.iris_code, entry.iris_code);
+        assert_eq!(
+            serde_json::to_value(&patched.territory).unwrap(),
+            serde_json::to_value(&entry.territory).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_merge_patch_changes_nothing() {
+        let entry = get_entry();
+        let patched = entry.merge_patch(&json!({})).unwrap();
+        assert_eq!(
+            serde_json::to_value(&patched).unwrap(),
+            serde_json::to_value(&entry).unwrap()
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use crate::core::entry::*;
+
+//Score columns of a flat entry, the axes are prefixed by their name.
+pub const FLAT_SCORE_COLUMNS: [&str; 33] = [
+    "global",
+    "global_region",
+    "global_dept",
+    "global_national",
+    "information_access_global",
+    "information_access_global_region",
+    "information_access_global_dept",
+    "information_access_global_national",
+    "monoparental_families_percent",
+    "single_person_percent",
+    "number_of_public_service_per_citizen",
+    "number_of_public_services",
+    "information_score",
+    "numeric_interfaces_access_global",
+    "numeric_interfaces_access_global_region",
+    "numeric_interfaces_access_global_dept",
+    "numeric_interfaces_access_global_national",
+    "high_speed_internet_access_percent",
+    "mobile_network_availability_percent",
+    "percent_of_poor_people",
+    "available_median_salary",
+    "administrative_competencies_global",
+    "administrative_competencies_global_region",
+    "administrative_competencies_global_dept",
+    "administrative_competencies_global_national",
+    "unemployed_percent",
+    "people_15_29_percent",
+    "numeric_competencies_global",
+    "numeric_competencies_global_region",
+    "numeric_competencies_global_dept",
+    "numeric_competencies_global_national",
+    "percent_of_65_plus_people",
+    "percent_of_people_without_grade",
+];
+
+//One row per IRIS code, with its territory keys and its scores in the order of FLAT_SCORE_COLUMNS.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FlatEntry {
+    pub iris_code: Option<String>,
+    pub iris_code_designation: Option<String>,
+    pub region: Option<String>,
+    pub department: Option<String>,
+    pub insee_com: Option<String>,
+    //EPCI of the territory of the entry, "code - name", and name of the commune from the postal
+    //codes. They are written by the CSV export only.
+    pub epci: Option<String>,
+    pub commune: Option<String>,
+    pub scores: Vec<Option<f64>>,
+}
+
+impl FlatEntry {
+    pub fn new(
+        entry: &Entry,
+        region: Option<String>,
+        department: Option<String>,
+        insee_com: Option<String>,
+    ) -> Self {
+        FlatEntry {
+            iris_code: entry.iris_code.clone(),
+            iris_code_designation: entry.iris_code_designation.clone(),
+            region,
+            department,
+            insee_com,
+            epci: entry
+                .territory
+                .as_ref()
+                .map(|territory| territory.epci.to_string()),
+            commune: None,
+            scores: get_scores(entry),
+        }
+    }
+
+    pub fn with_commune(mut self, commune: Option<String>) -> Self {
+        self.commune = commune;
+        self
+    }
+}
+
+//Scores of an entry, in the order of FLAT_SCORE_COLUMNS.
+pub fn get_scores(entry: &Entry) -> Vec<Option<f64>> {
+    let mut scores = vec![
+        entry.global,
+        entry.global_region,
+        entry.global_dept,
+        entry.global_national,
+    ];
+
+    scores.extend(match &entry.information_access {
+        Some(axis) => vec![
+            axis.global,
+            axis.global_region,
+            axis.global_dept,
+            axis.global_national,
+            axis.monoparental_families_percent,
+            axis.single_person_percent,
+            axis.number_of_public_service_per_citizen,
+            axis.number_of_public_services,
+            axis.information_score,
+        ],
+        None => vec![None; 9],
+    });
+
+    scores.extend(match &entry.numeric_interfaces_access {
+        Some(axis) => vec![
+            axis.global,
+            axis.global_region,
+            axis.global_dept,
+            axis.global_national,
+            axis.high_speed_internet_access_percent,
+            axis.mobile_network_availability_percent,
+            axis.percent_of_poor_people,
+            axis.available_median_salary,
+        ],
+        None => vec![None; 8],
+    });
+
+    scores.extend(match &entry.administrative_competencies {
+        Some(axis) => vec![
+            axis.global,
+            axis.global_region,
+            axis.global_dept,
+            axis.global_national,
+            axis.unemployed_percent.map(|value| value as f64),
+            axis._15_29_percent.map(|value| value as f64),
+        ],
+        None => vec![None; 6],
+    });
+
+    scores.extend(match &entry.numeric_competencies {
+        Some(axis) => vec![
+            axis.global,
+            axis.global_region,
+            axis.global_dept,
+            axis.global_national,
+            axis.percent_of_65_plus_people.map(|value| value as f64),
+            axis.percent_of_people_without_grade
+                .map(|value| value as f64),
+        ],
+        None => vec![None; 6],
+    });
+
+    scores
+}
+
+//Entry of the scores in the order of FLAT_SCORE_COLUMNS, an axis without any score is left out.
+pub fn scores_to_entry(
+    iris_code: Option<String>,
+    iris_code_designation: Option<String>,
+    scores: &[Option<f64>],
+) -> Entry {
+    let axis = |start: usize, end: usize| match scores[start..end].iter().all(Option::is_none) {
+        true => None,
+        false => Some(&scores[start..end]),
+    };
+    let as_f32 = |value: Option<f64>| value.map(|value| value as f32);
+
+    Entry::new(
+        scores[0],
+        scores[1],
+        scores[2],
+        scores[3],
+        iris_code,
+        iris_code_designation,
+        axis(4, 13).map(|values| {
+            InformationAccess::new(
+                values[0], values[1], values[2], values[3], values[4], values[5], values[6],
+                values[7],
+            )
+            .with_information_score(values[8])
+        }),
+        axis(13, 21).map(|values| {
+            NumericInterfacesAccess::new(
+                values[0], values[1], values[2], values[3], values[4], values[5], values[6],
+                values[7],
+            )
+        }),
+        axis(21, 27).map(|values| {
+            AdministrativeCompetencies::new(
+                values[0],
+                values[1],
+                values[2],
+                values[3],
+                as_f32(values[4]),
+                as_f32(values[5]),
+            )
+        }),
+        axis(27, 33).map(|values| {
+            NumericCompetencies::new(
+                values[0],
+                values[1],
+                values[2],
+                values[3],
+                as_f32(values[4]),
+                as_f32(values[5]),
+            )
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_round_trip_through_the_flat_columns() {
+        let entry = Entry::new(
+            Some(101.5),
+            Some(100.0),
+            Some(99.0),
+            Some(98.0),
+            Some("751010101".to_string()),
+            Some("Saint-Germain".to_string()),
+            Some(
+                InformationAccess::new(
+                    Some(1.0),
+                    Some(2.0),
+                    None,
+                    None,
+                    Some(5.0),
+                    None,
+                    None,
+                    Some(8.0),
+                )
+                .with_information_score(Some(9.5)),
+            ),
+            None,
+            Some(AdministrativeCompetencies::new(
+                Some(3.0),
+                None,
+                None,
+                None,
+                Some(0.25),
+                None,
+            )),
+            None,
+        );
+
+        let scores = get_scores(&entry);
+        assert_eq!(scores.len(), FLAT_SCORE_COLUMNS.len());
+        let rebuilt = scores_to_entry(
+            entry.iris_code.clone(),
+            entry.iris_code_designation.clone(),
+            &scores,
+        );
+        assert_eq!(
+            serde_json::to_value(&rebuilt).unwrap(),
+            serde_json::to_value(&entry).unwrap()
+        );
+    }
+}
@@ -0,0 +1,358 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+//Each level holds the next one by key, an EPCI holds the IRIS codes by commune.
+//An EPCI over several departments is found in each of them.
+pub type Epci = BTreeMap<String, Vec<String>>;
+pub type Department = BTreeMap<String, Epci>;
+pub type Region = BTreeMap<String, Department>;
+
+//EPCI key of the communes of the rows without an EPCI, they are kept in their department.
+pub const UNASSIGNED_EPCI: &str = "(unassigned)";
+
+//Territory found under another parent than the one of its first IRIS code.
+#[derive(Debug, Clone, Serialize)]
+pub struct HierarchyConflict {
+    pub territory: String,
+    pub parent: String,
+    pub ignored_parent: String,
+    pub iris_code: String,
+}
+
+//Territorial hierarchy with the keys of the indexes: region → department → EPCI → commune → IRIS codes.
+//A department stays in the region of its first IRIS code, a commune in the department and the EPCI of
+//its first one, the other rows are attached there and reported as conflicts.
+#[derive(Debug, Default, Clone)]
+pub struct Hierarchy {
+    regions: BTreeMap<String, Region>,
+    conflicts: Vec<HierarchyConflict>,
+    region_of: HashMap<String, String>,
+    parents_of: HashMap<String, (String, String)>,
+}
+
+impl Hierarchy {
+    pub fn from_regions(regions: BTreeMap<String, Region>) -> Self {
+        let mut hierarchy = Hierarchy {
+            regions,
+            ..Hierarchy::default()
+        };
+        hierarchy.index_parents();
+        hierarchy
+    }
+
+    //The rows with an unassigned region, department or commune are left out, like in the indexes.
+    //The communes without an EPCI are stored under UNASSIGNED_EPCI.
+    pub fn add(
+        &mut self,
+        region: &str,
+        department: &str,
+        epci: &str,
+        insee_com: &str,
+        iris_code: &str,
+    ) {
+        if [region, department, insee_com, iris_code]
+            .iter()
+            .any(|key| key.is_empty())
+        {
+            return;
+        }
+        let epci = match epci.is_empty() {
+            true => UNASSIGNED_EPCI,
+            false => epci,
+        };
+
+        let (department, epci) = match self.parents_of.get(insee_com).cloned() {
+            Some((kept_department, kept_epci)) => {
+                if kept_department != department || kept_epci != epci {
+                    self.add_conflict(
+                        insee_com,
+                        &format!("{} / {}", kept_department, kept_epci),
+                        &format!("{} / {}", department, epci),
+                        iris_code,
+                    );
+                    //The region of the row is not the one of the kept department.
+                    let region = self.region_of[&kept_department].to_string();
+                    return self.insert(region, kept_department, kept_epci, insee_com, iris_code);
+                }
+                (kept_department, kept_epci)
+            }
+            None => {
+                self.parents_of.insert(
+                    insee_com.to_string(),
+                    (department.to_string(), epci.to_string()),
+                );
+                (department.to_string(), epci.to_string())
+            }
+        };
+        let region = match self.region_of.get(&department).cloned() {
+            Some(kept_region) => {
+                if kept_region != region {
+                    self.add_conflict(&department, &kept_region, region, iris_code);
+                }
+                kept_region
+            }
+            None => {
+                self.region_of
+                    .insert(department.to_string(), region.to_string());
+                region.to_string()
+            }
+        };
+        self.insert(region, department, epci, insee_com, iris_code);
+    }
+
+    fn insert(
+        &mut self,
+        region: String,
+        department: String,
+        epci: String,
+        insee_com: &str,
+        iris_code: &str,
+    ) {
+        let iris_codes = self
+            .regions
+            .entry(region)
+            .or_default()
+            .entry(department)
+            .or_default()
+            .entry(epci)
+            .or_default()
+            .entry(insee_com.to_string())
+            .or_default();
+        if !iris_codes.iter().any(|stored| stored == iris_code) {
+            iris_codes.push(iris_code.to_string());
+        }
+    }
+
+    //Remove the IRIS codes and the territories left empty.
+    pub fn remove(&mut self, iris_codes: &HashSet<String>) {
+        for departments in self.regions.values_mut() {
+            for epcis in departments.values_mut() {
+                for communes in epcis.values_mut() {
+                    for commune in communes.values_mut() {
+                        commune.retain(|iris_code| !iris_codes.contains(iris_code));
+                    }
+                    communes.retain(|_, commune| !commune.is_empty());
+                }
+                epcis.retain(|_, communes| !communes.is_empty());
+            }
+            departments.retain(|_, epcis| !epcis.is_empty());
+        }
+        self.regions
+            .retain(|_, departments| !departments.is_empty());
+        self.index_parents();
+    }
+
+    //Add the IRIS codes of another hierarchy, its territories keep their parents when they are new.
+    pub fn merge(&mut self, hierarchy: &Hierarchy) {
+        for (region, departments) in &hierarchy.regions {
+            for (department, epcis) in departments {
+                for (epci, communes) in epcis {
+                    for (insee_com, iris_codes) in communes {
+                        for iris_code in iris_codes {
+                            self.add(region, department, epci, insee_com, iris_code);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn get_iris_codes(&self) -> HashSet<String> {
+        self.regions
+            .values()
+            .flat_map(|departments| departments.values())
+            .flat_map(|epcis| epcis.values())
+            .flat_map(|communes| communes.values())
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    //EPCI key of each IRIS code, the IRIS codes without an EPCI are left out.
+    pub fn get_epcis(&self) -> HashMap<String, String> {
+        let mut epcis = HashMap::new();
+        for departments in self.regions.values() {
+            for (epci, communes) in departments.values().flatten() {
+                if epci == UNASSIGNED_EPCI {
+                    continue;
+                }
+                for iris_code in communes.values().flatten() {
+                    epcis.insert(iris_code.to_string(), epci.to_string());
+                }
+            }
+        }
+        epcis
+    }
+
+    pub fn get_regions(&self) -> &BTreeMap<String, Region> {
+        &self.regions
+    }
+
+    pub fn get_conflicts(&self) -> &[HierarchyConflict] {
+        &self.conflicts
+    }
+
+    //Departments of each region, from the (region, department) pairs whatever their EPCI.
+    pub fn get_departments_by_region(&self) -> BTreeMap<String, Vec<String>> {
+        let mut departments_by_region: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (department, region) in &self.region_of {
+            departments_by_region
+                .entry(region.to_string())
+                .or_default()
+                .push(department.to_string());
+        }
+        for departments in departments_by_region.values_mut() {
+            departments.sort();
+        }
+        departments_by_region
+    }
+
+    fn add_conflict(
+        &mut self,
+        territory: &str,
+        parent: &str,
+        ignored_parent: &str,
+        iris_code: &str,
+    ) {
+        self.conflicts.push(HierarchyConflict {
+            territory: territory.to_string(),
+            parent: parent.to_string(),
+            ignored_parent: ignored_parent.to_string(),
+            iris_code: iris_code.to_string(),
+        });
+    }
+
+    fn index_parents(&mut self) {
+        self.region_of.clear();
+        self.parents_of.clear();
+        for (region, departments) in &self.regions {
+            for (department, epcis) in departments {
+                self.region_of
+                    .insert(department.to_string(), region.to_string());
+                for (epci, communes) in epcis {
+                    for insee_com in communes.keys() {
+                        self.parents_of.insert(
+                            insee_com.to_string(),
+                            (department.to_string(), epci.to_string()),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_hierarchy(rows: &[[&str; 5]]) -> Hierarchy {
+        let mut hierarchy = Hierarchy::default();
+        for [region, department, epci, insee_com, iris_code] in rows {
+            hierarchy.add(region, department, epci, insee_com, iris_code);
+        }
+        hierarchy
+    }
+
+    fn get_iris_codes(
+        hierarchy: &Hierarchy,
+        region: &str,
+        department: &str,
+        epci: &str,
+        insee_com: &str,
+    ) -> Vec<String> {
+        hierarchy.get_regions()[region][department][epci][insee_com].clone()
+    }
+
+    #[test]
+    fn add_keeps_the_communes_without_epci() {
+        let hierarchy = get_hierarchy(&[
+            ["R1", "D1", "E1", "C1", "1"],
+            ["R1", "D2", "", "C2", "2"],
+            ["R1", "D2", "", "", "3"],
+        ]);
+
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D2", UNASSIGNED_EPCI, "C2"),
+            vec!["2"]
+        );
+        assert_eq!(
+            hierarchy.get_departments_by_region()["R1"],
+            vec!["D1", "D2"]
+        );
+        assert!(!hierarchy.get_epcis().contains_key("2"));
+        assert_eq!(hierarchy.get_epcis()["1"], "E1");
+        assert!(!hierarchy.get_iris_codes().contains("3"));
+    }
+
+    #[test]
+    fn add_keeps_the_first_parents_and_reports_the_conflicts() {
+        let hierarchy = get_hierarchy(&[
+            ["R1", "D1", "E1", "C1", "1"],
+            ["R2", "D1", "E2", "C2", "2"],
+            ["R1", "D2", "E1", "C1", "3"],
+        ]);
+
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", "E2", "C2"),
+            vec!["2"]
+        );
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", "E1", "C1"),
+            vec!["1", "3"]
+        );
+        assert!(!hierarchy.get_regions().contains_key("R2"));
+
+        let conflicts = hierarchy.get_conflicts();
+        assert_eq!(conflicts.len(), 2);
+        assert_eq!(conflicts[0].territory, "D1");
+        assert_eq!(conflicts[0].ignored_parent, "R2");
+        assert_eq!(conflicts[1].territory, "C1");
+        assert_eq!(conflicts[1].parent, "D1 / E1");
+        assert_eq!(conflicts[1].ignored_parent, "D2 / E1");
+    }
+
+    #[test]
+    fn remove_drops_the_emptied_territories() {
+        let mut hierarchy = get_hierarchy(&[
+            ["R1", "D1", "E1", "C1", "1"],
+            ["R1", "D1", "E1", "C1", "2"],
+            ["R2", "D2", "E2", "C2", "3"],
+        ]);
+
+        hierarchy.remove(&["2", "3"].iter().map(|code| code.to_string()).collect());
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", "E1", "C1"),
+            vec!["1"]
+        );
+        assert!(!hierarchy.get_regions().contains_key("R2"));
+        assert!(!hierarchy.get_departments_by_region().contains_key("R2"));
+    }
+
+    #[test]
+    fn merge_moves_the_extract_to_its_parents() {
+        let mut hierarchy =
+            get_hierarchy(&[["R1", "D1", "E1", "C1", "1"], ["R1", "D1", "E1", "C2", "2"]]);
+        let extract = get_hierarchy(&[["R1", "D1", "E3", "C2", "2"], ["R1", "D1", "", "C3", "4"]]);
+
+        hierarchy.remove(&extract.get_iris_codes());
+        hierarchy.merge(&extract);
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", "E3", "C2"),
+            vec!["2"]
+        );
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", UNASSIGNED_EPCI, "C3"),
+            vec!["4"]
+        );
+        assert!(hierarchy.get_conflicts().is_empty());
+
+        //A commune of the extract already in another EPCI stays there.
+        let conflicting = get_hierarchy(&[["R1", "D1", "E4", "C1", "5"]]);
+        hierarchy.merge(&conflicting);
+        assert_eq!(
+            get_iris_codes(&hierarchy, "R1", "D1", "E1", "C1"),
+            vec!["1", "5"]
+        );
+        assert_eq!(hierarchy.get_conflicts().len(), 1);
+    }
+}
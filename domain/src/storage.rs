@@ -1,2 +1,4 @@
 pub mod error;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod traits;
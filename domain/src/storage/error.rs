@@ -19,6 +19,12 @@ pub enum StorageError {
         #[from]
         source: serde_json::Error,
     },
+    #[error("Serialization error: {0}")]
+    Serialization(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
 }
 
 //Define a generic error type to simplify return.
@@ -0,0 +1,301 @@
+//In-memory storages for the tests of the domain. The entries are kept serialized, like the
+//database backends store them, so a test can compare the stored bytes before and after a write.
+//The clones of a storage share its entries.
+use crate::core::entry::{Entry, Iris};
+use crate::core::hierarchy::Hierarchy;
+use crate::storage::error::*;
+use crate::storage::traits::{
+    EntryIterator, EntryStorageTrait, HierarchyStorageTrait, IndexStoragePostalTrait,
+    IndexStorageTrait,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+#[derive(Default, Clone)]
+pub struct TestEntryStorage {
+    entries: Arc<RwLock<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl TestEntryStorage {
+    pub fn new(entries: &[Entry]) -> Self {
+        let storage = TestEntryStorage::default();
+        for entry in entries {
+            storage
+                .create(entry.iris_code.clone().unwrap_or_default(), entry.clone())
+                .unwrap();
+        }
+        storage
+    }
+
+    //Stored bytes of every entry, by IRIS code.
+    pub fn get_stored(&self) -> BTreeMap<String, Vec<u8>> {
+        self.entries.read().unwrap().clone()
+    }
+
+    fn insert(&self, iris_code: String, entry: &Entry) -> StorageResult<Option<Vec<u8>>> {
+        let bytes = serde_json::to_vec(entry)?;
+        Ok(self.entries.write().unwrap().insert(iris_code, bytes))
+    }
+}
+
+fn decode(bytes: &[u8]) -> StorageResult<Entry> {
+    Ok(serde_json::from_slice(bytes)?)
+}
+
+impl EntryStorageTrait for TestEntryStorage {
+    fn get_all(&self) -> StorageResult<Vec<Entry>> {
+        self.iter_entries()?.collect()
+    }
+
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>> {
+        let entries: Vec<Vec<u8>> = self.entries.read().unwrap().values().cloned().collect();
+        Ok(Box::new(entries.into_iter().map(|bytes| decode(&bytes))))
+    }
+
+    fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        match self.entries.read().unwrap().get(&iris_code) {
+            Some(bytes) => Ok(Some(decode(bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>> {
+        let mut entries = HashMap::new();
+        for iris_code in iris_codes {
+            if let Some(entry) = self.get_entry(iris_code.to_string())? {
+                entries.insert(iris_code.to_string(), entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    fn get_national_entry(&self) -> StorageResult<Option<Entry>> {
+        self.iter_entries()?.next().transpose()
+    }
+
+    fn get_region_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.get_entry(iris_code)
+    }
+
+    fn get_department_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.get_entry(iris_code)
+    }
+
+    fn create(&self, iris_code: String, entry: Entry) -> StorageResult<()> {
+        self.insert(iris_code, &entry)?;
+        Ok(())
+    }
+
+    fn update(&self, iris_code: String, entry: Entry) -> StorageResult<Option<Entry>> {
+        if !self.entries.read().unwrap().contains_key(&iris_code) {
+            return Ok(None);
+        }
+        match self.insert(iris_code, &entry)? {
+            Some(bytes) => Ok(Some(decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn update_entries(&self, entries: &[Entry]) -> StorageResult<()> {
+        for entry in entries {
+            self.insert(entry.iris_code.clone().unwrap_or_default(), entry)?;
+        }
+        Ok(())
+    }
+
+    fn delete(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        match self.entries.write().unwrap().remove(&iris_code) {
+            Some(bytes) => Ok(Some(decode(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TestIndexStorage {
+    index: RwLock<BTreeMap<String, Vec<String>>>,
+}
+
+impl TestIndexStorage {
+    pub fn new(index: &[(&str, &[&str])]) -> Self {
+        TestIndexStorage {
+            index: RwLock::new(
+                index
+                    .iter()
+                    .map(|(key, values)| {
+                        (
+                            key.to_string(),
+                            values.iter().map(|value| value.to_string()).collect(),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl IndexStorageTrait for TestIndexStorage {
+    fn search_on_key(
+        &self,
+        contains: String,
+        _start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|key| key.contains(&contains.to_uppercase()))
+            .cloned()
+            .collect())
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>> {
+        Ok(self.index.read().unwrap().get(&value).cloned())
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .values()
+            .flatten()
+            .cloned()
+            .collect())
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        Ok(self.index.read().unwrap().keys().cloned().collect())
+    }
+
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, values)| values.contains(&value))
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
+
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()> {
+        self.index.write().unwrap().insert(key, values);
+        Ok(())
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        self.index.write().unwrap().remove(&key);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct TestIndexStoragePostal {
+    index: RwLock<BTreeMap<String, Iris>>,
+}
+
+impl TestIndexStoragePostal {
+    //Cities with their INSEE code, without geo location.
+    pub fn new(index: &[(&str, &str)]) -> Self {
+        TestIndexStoragePostal {
+            index: RwLock::new(
+                index
+                    .iter()
+                    .map(|(city, code)| (city.to_string(), Iris::new(Some(code.to_string()), None)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl IndexStoragePostalTrait for TestIndexStoragePostal {
+    fn search_on_key(
+        &self,
+        contains: String,
+        _start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|key| key.contains(&contains.to_uppercase()))
+            .cloned()
+            .collect())
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Iris>> {
+        Ok(self.index.read().unwrap().get(&value).cloned())
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<Iris>> {
+        Ok(self.index.read().unwrap().values().cloned().collect())
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        Ok(self.index.read().unwrap().keys().cloned().collect())
+    }
+
+    fn find_keys(&self, code: String) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, iris)| iris.code.as_deref() == Some(code.as_str()))
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        self.index.write().unwrap().remove(&key);
+        Ok(())
+    }
+
+    fn search_prefix(&self, prefix: String) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .keys()
+            .filter(|key| key.starts_with(&prefix))
+            .cloned()
+            .collect())
+    }
+
+    fn search_range(&self, start: String, end: String) -> StorageResult<Vec<String>> {
+        Ok(self
+            .index
+            .read()
+            .unwrap()
+            .range(start..end)
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct TestHierarchyStorage {
+    hierarchy: Arc<RwLock<Option<Hierarchy>>>,
+}
+
+impl TestHierarchyStorage {
+    pub fn new(hierarchy: Hierarchy) -> Self {
+        TestHierarchyStorage {
+            hierarchy: Arc::new(RwLock::new(Some(hierarchy))),
+        }
+    }
+}
+
+impl HierarchyStorageTrait for TestHierarchyStorage {
+    fn get_hierarchy(&self) -> StorageResult<Option<Hierarchy>> {
+        Ok(self.hierarchy.read().unwrap().clone())
+    }
+
+    fn set_hierarchy(&self, hierarchy: &Hierarchy) -> StorageResult<()> {
+        *self.hierarchy.write().unwrap() = Some(hierarchy.clone());
+        Ok(())
+    }
+}
@@ -1,16 +1,49 @@
 use crate::core::entry::{Entry, Iris};
+use crate::core::hierarchy::Hierarchy;
 use crate::storage::error::*;
+use serde_json::Value;
+use std::collections::HashMap;
+
+//Lazy iteration over the stored entries.
+pub type EntryIterator<'a> = Box<dyn Iterator<Item = StorageResult<Entry>> + 'a>;
 
 pub trait EntryStorageTrait: Sync + Send {
     fn get_all(&self) -> StorageResult<Vec<Entry>>;
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>>;
     fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>>;
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>>;
     fn get_national_entry(&self) -> StorageResult<Option<Entry>>;
     fn get_region_entry(&self, iris_code: String) -> StorageResult<Option<Entry>>;
     fn get_department_entry(&self, iris_code: String) -> StorageResult<Option<Entry>>;
     fn create(&self, iris_code: String, entry: Entry) -> StorageResult<()>;
+    //Replace a stored entry, return the previous one or `None` when there is nothing to update.
+    fn update(&self, iris_code: String, entry: Entry) -> StorageResult<Option<Entry>>;
+    //Write many existing entries at once (aggregates refresh).
+    fn update_entries(&self, entries: &[Entry]) -> StorageResult<()>;
+    fn delete(&self, iris_code: String) -> StorageResult<Option<Entry>>;
+
+    //Number of entries stored by an older schema without the information score, they have to be
+    //imported again before the averages are refreshed.
+    fn count_outdated_entries(&self) -> StorageResult<usize> {
+        Ok(0)
+    }
+
+    //Apply a JSON merge patch on a stored entry, return the patched entry.
+    fn patch(&self, iris_code: String, patch: &Value) -> StorageResult<Option<Entry>> {
+        let entry = match self.get_entry(iris_code.to_string())? {
+            Some(entry) => entry.merge_patch(patch)?,
+            None => return Ok(None),
+        };
+        match self.update(iris_code, entry.clone())? {
+            Some(_) => Ok(Some(entry)),
+            None => Ok(None),
+        }
+    }
 }
 
 pub trait IndexStorageTrait: Sync + Send {
+    //Keys containing the uppercased `contains`, among the keys from `start_with` to
+    //`start_with + "z"` (included) when it is given. Every backend uses these bounds.
     fn search_on_key(
         &self,
         contains: String,
@@ -19,9 +52,14 @@ pub trait IndexStorageTrait: Sync + Send {
     fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>>;
     fn get_all_values(&self) -> StorageResult<Vec<String>>;
     fn get_all_keys(&self) -> StorageResult<Vec<String>>;
+    //Keys whose values contain `value`.
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>>;
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()>;
+    fn delete_index(&self, key: String) -> StorageResult<()>;
 }
 
 pub trait IndexStoragePostalTrait: Sync + Send {
+    //Same bounds as `IndexStorageTrait::search_on_key`.
     fn search_on_key(
         &self,
         contains: String,
@@ -30,4 +68,27 @@ pub trait IndexStoragePostalTrait: Sync + Send {
     fn get_index(&self, value: String) -> StorageResult<Option<Iris>>;
     fn get_all_values(&self) -> StorageResult<Vec<Iris>>;
     fn get_all_keys(&self) -> StorageResult<Vec<String>>;
+    //Keys pointing to the INSEE code `code`.
+    fn find_keys(&self, code: String) -> StorageResult<Vec<String>>;
+    fn delete_index(&self, key: String) -> StorageResult<()>;
+    //Keys starting with `prefix`.
+    fn search_prefix(&self, prefix: String) -> StorageResult<Vec<String>>;
+    //Keys between `start` (included) and `end` (excluded).
+    fn search_range(&self, start: String, end: String) -> StorageResult<Vec<String>>;
+    //Keys matching the regular expression, anchored at the start of the key. Only the FST index
+    //runs the automaton queries.
+    fn search_regex(&self, _pattern: String) -> StorageResult<Vec<String>> {
+        Err(StorageError::NotImplemented)
+    }
+    //Keys within `distance` edits of the uppercased query.
+    fn search_fuzzy(&self, _query: String, _distance: u32) -> StorageResult<Vec<String>> {
+        Err(StorageError::NotImplemented)
+    }
+}
+
+//Territorial hierarchy written by the import of the indexes.
+pub trait HierarchyStorageTrait: Sync + Send {
+    //`None` when no hierarchy was written.
+    fn get_hierarchy(&self) -> StorageResult<Option<Hierarchy>>;
+    fn set_hierarchy(&self, hierarchy: &Hierarchy) -> StorageResult<()>;
 }
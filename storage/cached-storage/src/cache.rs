@@ -0,0 +1,206 @@
+use lru::LruCache;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub size: usize,
+    pub capacity: usize,
+}
+
+//Statistics and invalidation, shared by the caches of any key and value.
+pub trait CacheTrait: Sync + Send {
+    fn get_stats(&self) -> CacheStats;
+    fn clear(&self);
+}
+
+//Bounded LRU cache whose values expire after the TTL.
+pub struct LruTtlCache<K: Hash + Eq, V> {
+    values: Mutex<LruCache<K, (Instant, V)>>,
+    capacity: usize,
+    ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    //Incremented by each invalidation, a value loaded before it is not kept.
+    generation: AtomicU64,
+}
+
+impl<K: Hash + Eq, V: Clone> LruTtlCache<K, V> {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        LruTtlCache {
+            values: Mutex::new(LruCache::new(capacity)),
+            capacity,
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+        }
+    }
+
+    fn get_values(&self) -> MutexGuard<'_, LruCache<K, (Instant, V)>> {
+        self.values.lock().unwrap()
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        let mut values = self.get_values();
+        let (cached, expired) = match values.get(key) {
+            Some((inserted, value)) if inserted.elapsed() < self.ttl => {
+                (Some(value.clone()), false)
+            }
+            Some(_) => (None, true),
+            None => (None, false),
+        };
+        if expired {
+            values.pop(key);
+        }
+
+        match cached {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        cached
+    }
+
+    //Generation to read before loading a value from the storage, then given to `insert`.
+    pub fn get_generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    //Keep a value loaded from the storage at `generation`. It is dropped when the cache was
+    //invalidated during the load: the storage was written and the value may be stale.
+    pub fn insert(&self, generation: u64, key: K, value: V) {
+        let mut values = self.get_values();
+        if self.get_generation() == generation {
+            values.put(key, (Instant::now(), value));
+        }
+    }
+
+    //Return the cached value, or load it from the storage and keep it. The storage is read
+    //without the lock of the cache.
+    pub fn get_or_load<E, F>(&self, key: K, load: F) -> Result<V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        if let Some(value) = self.get(&key) {
+            return Ok(value);
+        }
+
+        let generation = self.get_generation();
+        let value = load()?;
+        self.insert(generation, key, value.clone());
+        Ok(value)
+    }
+
+    pub fn invalidate(&self, key: &K) {
+        let mut values = self.get_values();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        values.pop(key);
+    }
+}
+
+impl<K, V> CacheTrait for LruTtlCache<K, V>
+where
+    K: Hash + Eq + Send,
+    V: Clone + Send,
+{
+    fn get_stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            size: self.get_values().len(),
+            capacity: self.capacity,
+        }
+    }
+
+    fn clear(&self) {
+        let mut values = self.get_values();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        values.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn get_cache(capacity: usize) -> LruTtlCache<&'static str, u32> {
+        LruTtlCache::new(capacity, Duration::from_secs(60))
+    }
+
+    fn load(value: u32) -> Result<u32, ()> {
+        Ok(value)
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_value() {
+        let cache = get_cache(2);
+        cache.get_or_load("a", || load(1)).unwrap();
+        cache.get_or_load("b", || load(2)).unwrap();
+        cache.get(&"a");
+        cache.get_or_load("c", || load(3)).unwrap();
+
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"c"), Some(3));
+        assert_eq!(cache.get_stats().size, 2);
+    }
+
+    #[test]
+    fn expires_the_values_after_the_ttl() {
+        let cache = LruTtlCache::new(2, Duration::from_millis(20));
+        cache.get_or_load("a", || load(1)).unwrap();
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get_stats().size, 0);
+        assert_eq!(cache.get_or_load("a", || load(2)), Ok(2));
+    }
+
+    #[test]
+    fn counts_the_hits_and_the_misses() {
+        let cache = get_cache(2);
+        cache.get_or_load("a", || load(1)).unwrap();
+        cache.get_or_load("a", || load(1)).unwrap();
+        cache.get(&"a");
+        cache.get(&"b");
+
+        let stats = cache.get_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 2));
+        assert_eq!((stats.size, stats.capacity), (1, 2));
+    }
+
+    #[test]
+    fn a_failed_load_is_not_kept() {
+        let cache = get_cache(2);
+        assert_eq!(cache.get_or_load("a", || Err(())), Err(()));
+        assert_eq!(cache.get_stats().size, 0);
+    }
+
+    #[test]
+    fn drops_a_value_loaded_before_an_invalidation() {
+        let cache = get_cache(2);
+        let value = cache.get_or_load("a", || {
+            cache.clear();
+            load(1)
+        });
+        assert_eq!(value, Ok(1));
+        assert_eq!(cache.get(&"a"), None);
+
+        cache
+            .get_or_load("b", || {
+                cache.invalidate(&"c");
+                load(2)
+            })
+            .unwrap();
+        assert_eq!(cache.get(&"b"), None);
+
+        cache.get_or_load("a", || load(3)).unwrap();
+        assert_eq!(cache.get(&"a"), Some(3));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::cache::LruTtlCache;
+use domain::storage::error::*;
+use domain::storage::traits::IndexStorageTrait;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type IndexCache = LruTtlCache<String, Option<Vec<String>>>;
+
+//Read-through cache of the index lookups, the writes invalidate their key.
+pub struct CachedIndexStorage {
+    storage: Box<dyn IndexStorageTrait>,
+    cache: Arc<IndexCache>,
+}
+
+impl CachedIndexStorage {
+    pub fn new(storage: Box<dyn IndexStorageTrait>, capacity: usize, ttl: Duration) -> Self {
+        CachedIndexStorage {
+            storage,
+            cache: Arc::new(LruTtlCache::new(capacity, ttl)),
+        }
+    }
+
+    //Shared handle on the cache, for the statistics and the explicit invalidation.
+    pub fn get_cache(&self) -> Arc<IndexCache> {
+        self.cache.clone()
+    }
+}
+
+impl IndexStorageTrait for CachedIndexStorage {
+    fn search_on_key(
+        &self,
+        contains: String,
+        start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        self.storage.search_on_key(contains, start_with)
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>> {
+        self.cache
+            .get_or_load(value.to_string(), || self.storage.get_index(value))
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<String>> {
+        self.storage.get_all_values()
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        self.storage.get_all_keys()
+    }
+
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>> {
+        self.storage.find_keys(value)
+    }
+
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()> {
+        let result = self.storage.update_index(key.to_string(), values);
+        self.cache.invalidate(&key);
+        result
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        let result = self.storage.delete_index(key.to_string());
+        self.cache.invalidate(&key);
+        result
+    }
+}
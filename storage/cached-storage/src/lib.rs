@@ -0,0 +1,214 @@
+#[macro_use]
+extern crate serde_derive;
+
+use cache::{CacheTrait, LruTtlCache};
+use domain::core::entry::Entry;
+use domain::storage::error::*;
+use domain::storage::traits::{EntryIterator, EntryStorageTrait};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub mod cache;
+pub mod index;
+
+//Cached results of the entry storage reads.
+#[derive(Hash, PartialEq, Eq)]
+pub enum EntryCacheKey {
+    Entry(String),
+    National,
+    Region(String),
+    Department(String),
+}
+
+pub type EntryCache = LruTtlCache<EntryCacheKey, Option<Entry>>;
+
+//Read-through cache in front of any entries storage, the writes clear it.
+pub struct CachedEntryStorage {
+    storage: Box<dyn EntryStorageTrait>,
+    cache: Arc<EntryCache>,
+}
+
+impl CachedEntryStorage {
+    pub fn new(storage: Box<dyn EntryStorageTrait>, capacity: usize, ttl: Duration) -> Self {
+        CachedEntryStorage {
+            storage,
+            cache: Arc::new(LruTtlCache::new(capacity, ttl)),
+        }
+    }
+
+    //Shared handle on the cache, for the statistics and the explicit invalidation.
+    pub fn get_cache(&self) -> Arc<EntryCache> {
+        self.cache.clone()
+    }
+}
+
+impl EntryStorageTrait for CachedEntryStorage {
+    //The full scans are not cached, they would evict all the popular entries.
+    fn get_all(&self) -> StorageResult<Vec<Entry>> {
+        self.storage.get_all()
+    }
+
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>> {
+        self.storage.iter_entries()
+    }
+
+    fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.cache
+            .get_or_load(EntryCacheKey::Entry(iris_code.to_string()), || {
+                self.storage.get_entry(iris_code)
+            })
+    }
+
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>> {
+        let mut entries: HashMap<String, Entry> = HashMap::with_capacity(iris_codes.len());
+        let mut missing_codes: Vec<String> = Vec::new();
+        for iris_code in iris_codes {
+            match self.cache.get(&EntryCacheKey::Entry(iris_code.to_string())) {
+                Some(Some(entry)) => {
+                    entries.insert(iris_code.to_string(), entry);
+                }
+                Some(None) => {}
+                None => missing_codes.push(iris_code.to_string()),
+            }
+        }
+
+        if !missing_codes.is_empty() {
+            let generation = self.cache.get_generation();
+            let mut loaded_entries = self.storage.get_entries(&missing_codes)?;
+            for iris_code in missing_codes {
+                let entry = loaded_entries.remove(&iris_code);
+                self.cache.insert(
+                    generation,
+                    EntryCacheKey::Entry(iris_code.to_string()),
+                    entry.clone(),
+                );
+                if let Some(entry) = entry {
+                    entries.insert(iris_code, entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn get_national_entry(&self) -> StorageResult<Option<Entry>> {
+        self.cache.get_or_load(EntryCacheKey::National, || {
+            self.storage.get_national_entry()
+        })
+    }
+
+    fn get_region_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.cache
+            .get_or_load(EntryCacheKey::Region(iris_code.to_string()), || {
+                self.storage.get_region_entry(iris_code)
+            })
+    }
+
+    fn get_department_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.cache
+            .get_or_load(EntryCacheKey::Department(iris_code.to_string()), || {
+                self.storage.get_department_entry(iris_code)
+            })
+    }
+
+    //A single entry changes the averages projected in the regional, departmental and national entries.
+    fn create(&self, iris_code: String, entry: Entry) -> StorageResult<()> {
+        let result = self.storage.create(iris_code, entry);
+        self.cache.clear();
+        result
+    }
+
+    fn update(&self, iris_code: String, entry: Entry) -> StorageResult<Option<Entry>> {
+        let result = self.storage.update(iris_code, entry);
+        self.cache.clear();
+        result
+    }
+
+    fn update_entries(&self, entries: &[Entry]) -> StorageResult<()> {
+        let result = self.storage.update_entries(entries);
+        self.cache.clear();
+        result
+    }
+
+    fn delete(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        let result = self.storage.delete(iris_code);
+        self.cache.clear();
+        result
+    }
+
+    fn patch(&self, iris_code: String, patch: &Value) -> StorageResult<Option<Entry>> {
+        let result = self.storage.patch(iris_code, patch);
+        self.cache.clear();
+        result
+    }
+
+    fn count_outdated_entries(&self) -> StorageResult<usize> {
+        self.storage.count_outdated_entries()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::CachedIndexStorage;
+    use domain::storage::traits::IndexStorageTrait;
+    use sled_db_entry_storage::index::SledIndexStorage;
+    use sled_db_entry_storage::SledEntriesStorage;
+    use std::fs;
+
+    fn get_entry(iris_code: &str, global: f64) -> Entry {
+        Entry::new(
+            Some(global),
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn writes_clear_the_cached_reads() {
+        let directory = std::env::temp_dir().join(format!("cached-storage-{}", std::process::id()));
+        let database = SledEntriesStorage::open(directory.to_string_lossy().to_string()).unwrap();
+        database
+            .create("1".to_string(), get_entry("1", 100.0))
+            .unwrap();
+
+        let entries =
+            CachedEntryStorage::new(Box::new(database.clone()), 10, Duration::from_secs(60));
+        let cache = entries.get_cache();
+        entries.get_entry("1".to_string()).unwrap();
+        entries.get_national_entry().unwrap();
+        assert_eq!(cache.get_stats().size, 2);
+
+        entries
+            .update("1".to_string(), get_entry("1", 120.0))
+            .unwrap();
+        assert_eq!(cache.get_stats().size, 0);
+        let entry = entries.get_entry("1".to_string()).unwrap().unwrap();
+        assert_eq!(entry.global, Some(120.0));
+
+        let index = CachedIndexStorage::new(
+            Box::new(SledIndexStorage::new(&database, "regions".to_string())),
+            10,
+            Duration::from_secs(60),
+        );
+        assert_eq!(index.get_index("R1".to_string()).unwrap(), None);
+        index
+            .update_index("R1".to_string(), vec!["1".to_string()])
+            .unwrap();
+        assert_eq!(
+            index.get_index("R1".to_string()).unwrap(),
+            Some(vec!["1".to_string()])
+        );
+
+        drop(database);
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
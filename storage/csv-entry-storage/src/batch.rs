@@ -0,0 +1,53 @@
+use crate::entry_csv::EntryCSV;
+use crate::row::{read_row, CSVRow};
+use crate::source::RecordSource;
+use crate::CSVEntryStorageResult;
+use csv::StringRecord;
+use rayon::prelude::*;
+
+//Rows read together, then deserialized on the thread pool.
+pub const CSV_BATCH_SIZE: usize = 4096;
+
+//Batches of rows in the order of the file.
+pub struct CSVEntryBatches {
+    records: Box<dyn RecordSource>,
+    headers: StringRecord,
+    path: String,
+}
+
+impl CSVEntryBatches {
+    //The headers are the ones expected by the rows, in the order of the source ones.
+    pub fn new(records: Box<dyn RecordSource>, headers: StringRecord, path: &str) -> Self {
+        CSVEntryBatches {
+            records,
+            headers,
+            path: path.to_string(),
+        }
+    }
+}
+
+impl Iterator for CSVEntryBatches {
+    type Item = CSVEntryStorageResult<Vec<CSVRow<EntryCSV>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut records = Vec::with_capacity(CSV_BATCH_SIZE);
+        while records.len() < CSV_BATCH_SIZE {
+            match self.records.next_record() {
+                Ok(Some(record)) => records.push(record),
+                Ok(None) => break,
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        if records.is_empty() {
+            return None;
+        }
+
+        let headers = &self.headers;
+        let source_headers = self.records.get_headers();
+        let path = &self.path;
+        Some(Ok(records
+            .into_par_iter()
+            .map(|record| read_row(path, headers, source_headers, record))
+            .collect()))
+    }
+}
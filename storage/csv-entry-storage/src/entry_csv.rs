@@ -1,6 +1,9 @@
+use crate::row::{CheckedRow, InvalidCell};
 use crate::AvgStat;
+use domain::business::aggregates::Averages;
 use domain::core::entry::*;
 use std::collections::BTreeMap;
+use std::num::ParseFloatError;
 
 impl EntryCSV {
     fn concat_name(&self, code: String, name: String) -> String {
@@ -10,32 +13,42 @@ impl EntryCSV {
         }
     }
 
+    //Department key of the indexes, "" when the department has no name.
+    pub fn get_department(&self) -> String {
+        self.concat_name(self.dep.to_string(), self.nom_dep.to_string())
+    }
+
+    //EPCI key of the hierarchy, "" when the EPCI has no name.
+    pub fn get_epci(&self) -> String {
+        self.concat_name(self.epci.to_string(), self.libepci.to_string())
+    }
+
     pub fn to_entry(
         &self,
         nationalStats: &AvgStat,
         regionsStats: &BTreeMap<String, AvgStat>,
         departmentsStats: &BTreeMap<String, AvgStat>,
     ) -> Entry {
-        let regionStat = regionsStats.get(&self.nom_reg).unwrap();
-        let departmentStat = departmentsStats
-            .get(&self.concat_name(self.dep.to_string(), self.nom_dep.to_string()))
-            .unwrap();
+        //The averages are missing for the territories of the rows left out by the scan.
+        let regionStat = regionsStats.get(&self.nom_reg);
+        let departmentStat = departmentsStats.get(&self.get_department());
         let information_access = InformationAccess::new(
             self.clean_and_parse_f64(&self.global_acces_region_1),
-            Some(regionStat.avg_entries_information_access as f64),
-            Some(departmentStat.avg_entries_information_access as f64),
-            Some(nationalStats.avg_entries_information_access as f64),
+            regionStat.and_then(|stat| stat.avg_entries_information_access),
+            departmentStat.and_then(|stat| stat.avg_entries_information_access),
+            nationalStats.avg_entries_information_access,
             self.clean_and_parse_f64(&self.part_des_familles_monoparentales),
             self.clean_and_parse_f64(&self.part_des_menages_personne),
             self.clean_and_parse_f64(&self.service_publics),
             None, // ?)?
-        );
+        )
+        .with_information_score(self.clean_and_parse_f64(&self.acces_information_region_1));
 
         let numeric_interfaces_access = NumericInterfacesAccess::new(
             self.clean_and_parse_f64(&self.acces_aux_interfaces_numeriques_region_1),
-            Some(regionStat.avg_entries_numeric_interface_access as f64),
-            Some(departmentStat.avg_entries_numeric_interface_access as f64),
-            Some(nationalStats.avg_entries_numeric_interface_access as f64),
+            regionStat.and_then(|stat| stat.avg_entries_numeric_interface_access),
+            departmentStat.and_then(|stat| stat.avg_entries_numeric_interface_access),
+            nationalStats.avg_entries_numeric_interface_access,
             match &self.taux_couv_hd_thd_1 {
                 Some(taux) => self.clean_and_parse_f64(taux),
                 None => None,
@@ -53,9 +66,9 @@ impl EntryCSV {
 
         let administrative_competencies = AdministrativeCompetencies::new(
             self.clean_and_parse_f64(&self.competences_administatives_region_1),
-            Some(regionStat.avg_entries_administrative_competencies as f64),
-            Some(departmentStat.avg_entries_administrative_competencies as f64),
-            Some(nationalStats.avg_entries_administrative_competencies as f64),
+            regionStat.and_then(|stat| stat.avg_entries_administrative_competencies),
+            departmentStat.and_then(|stat| stat.avg_entries_administrative_competencies),
+            nationalStats.avg_entries_administrative_competencies,
             match &self.part_chomeurs {
                 Some(part) => self.clean_and_parse_f32(part),
                 None => None,
@@ -65,9 +78,9 @@ impl EntryCSV {
 
         let numeric_competencies = NumericCompetencies::new(
             self.clean_and_parse_f64(&self.competences_numeriques_scolaires_region_1),
-            Some(regionStat.avg_entries_numeric_competencies as f64),
-            Some(departmentStat.avg_entries_numeric_competencies as f64),
-            Some(nationalStats.avg_entries_numeric_competencies as f64),
+            regionStat.and_then(|stat| stat.avg_entries_numeric_competencies),
+            departmentStat.and_then(|stat| stat.avg_entries_numeric_competencies),
+            nationalStats.avg_entries_numeric_competencies,
             self.clean_and_parse_f32(&self.part_des_personnes_agees_de_65_ans_plus),
             self.clean_and_parse_f32(
                 &self.part_des_non_peu_diplomes_population_non_scolarisee_15_ans_plus,
@@ -76,9 +89,9 @@ impl EntryCSV {
 
         Entry::new(
             self.clean_and_parse_f64(&self.score_global_region_star),
-            Some(regionStat.avg_entries_global_score as f64),
-            Some(departmentStat.avg_entries_global_score as f64),
-            Some(nationalStats.avg_entries_global_score as f64),
+            regionStat.and_then(|stat| stat.avg_entries_global_score),
+            departmentStat.and_then(|stat| stat.avg_entries_global_score),
+            nationalStats.avg_entries_global_score,
             Some(self.iris.to_owned()),
             Some(self.libiris.to_owned()),
             Some(information_access),
@@ -86,25 +99,153 @@ impl EntryCSV {
             Some(administrative_competencies),
             Some(numeric_competencies),
         )
+        .with_territory(Territory {
+            region: self.nom_reg.to_string(),
+            department: self.get_department(),
+            epci: self.get_epci(),
+            insee_com: self.insee_com.to_string(),
+        })
+    }
+
+    //Scores averaged by territory, in the order of the domain `get_scores`.
+    pub fn get_scores(&self) -> Averages {
+        [
+            self.clean_and_parse_f64(&self.score_global_region_star),
+            self.clean_and_parse_f64(&self.acces_information_region_1),
+            self.clean_and_parse_f64(&self.acces_aux_interfaces_numeriques_region_1),
+            self.clean_and_parse_f64(&self.competences_administatives_region_1),
+            self.clean_and_parse_f64(&self.competences_numeriques_scolaires_region_1),
+        ]
     }
+
+    //Invalid numbers are None, they are reported by get_invalid_cells.
     pub fn clean_and_parse_f64(&self, value: &String) -> Option<f64> {
-        match value.trim().is_empty() {
-            true => return None,
-            false => {
-                let cleaned_string = str::replace(&value, ",", ".");
-                Some(cleaned_string.parse::<f64>().unwrap())
-            }
-        }
+        parse_f64(value).ok().flatten()
     }
+
     pub fn clean_and_parse_f32(&self, value: &String) -> Option<f32> {
         match value.trim().is_empty() {
-            true => return None,
-            false => {
-                let cleaned_string = str::replace(&value, ",", ".");
-                Some(cleaned_string.parse::<f32>().unwrap())
-            }
+            true => None,
+            false => str::replace(value, ",", ".").parse::<f32>().ok(),
         }
     }
+
+    //Columns read by to_entry and the averages, in the order of SCORE_HEADERS.
+    fn get_numeric_cells(&self) -> Vec<(&'static str, Option<&String>)> {
+        let values = [
+            Some(&self.score_global_region_star),
+            Some(&self.global_acces_region_1),
+            Some(&self.part_des_familles_monoparentales),
+            Some(&self.part_des_menages_personne),
+            Some(&self.service_publics),
+            Some(&self.acces_information_region_1),
+            Some(&self.acces_aux_interfaces_numeriques_region_1),
+            self.taux_couv_hd_thd_1.as_ref(),
+            self.taux_couv_mobile.as_ref(),
+            self.taux_pauvrete.as_ref(),
+            Some(&self.cm_revenue_median_region),
+            Some(&self.competences_administatives_region_1),
+            self.part_chomeurs.as_ref(),
+            Some(&self.part_des_personnes_agees_de_15_29_ans),
+            Some(&self.competences_numeriques_scolaires_region_1),
+            Some(&self.part_des_personnes_agees_de_65_ans_plus),
+            Some(&self.part_des_non_peu_diplomes_population_non_scolarisee_15_ans_plus),
+        ];
+        SCORE_HEADERS
+            .iter()
+            .map(|(_, header)| *header)
+            .zip(values.iter().copied())
+            .collect()
+    }
+}
+
+//Headers of the score columns of the source file, by flat score column. The import reads the
+//scores from them and the CSV export writes them back there.
+pub const SCORE_HEADERS: [(&str, &str); 17] = [
+    ("global", "SCORE GLOBAL region * "),
+    ("information_access_global", "GLOBAL ACCES region 1"),
+    (
+        "monoparental_families_percent",
+        "Part des familles monoparentales",
+    ),
+    ("single_person_percent", "Part des ménages d'une personne"),
+    (
+        "number_of_public_service_per_citizen",
+        "Services publics / individu",
+    ),
+    ("information_score", "ACCES A L'INFORMATION region 1"),
+    (
+        "numeric_interfaces_access_global",
+        "ACCÈS AUX INTERFACES NUMERIQUES region 1",
+    ),
+    (
+        "high_speed_internet_access_percent",
+        "Taux de couverture HD / THD (DSL, câble, FttH) 1",
+    ),
+    (
+        "mobile_network_availability_percent",
+        "Taux de couverture mobile (2G)",
+    ),
+    ("percent_of_poor_people", "Taux de pauvreté"),
+    ("available_median_salary", "CM revenue median region"),
+    (
+        "administrative_competencies_global",
+        "COMPETENCES ADMINISTATIVES region 1",
+    ),
+    ("unemployed_percent", "Part des chômeurs (15 – 64 ans)"),
+    (
+        "people_15_29_percent",
+        "Part des personnes âgées de 15 – 29 ans",
+    ),
+    (
+        "numeric_competencies_global",
+        "COMPÉTENCES NUMÉRIQUES / SCOLAIRES region 1",
+    ),
+    (
+        "percent_of_65_plus_people",
+        "Part des personnes âgées de 65 ans / +",
+    ),
+    (
+        "percent_of_people_without_grade",
+        "Part des non ou peu diplômés dans la population non scolarisée de 15 ans ou plus",
+    ),
+];
+
+//Widths of the code columns. A workbook can hold the codes as numbers, they are zero-padded back
+//to their width (department "01" is read as 1).
+pub const CODE_WIDTHS: [(&str, usize); 10] = [
+    ("Code Iris", 9),
+    ("COM", 5),
+    ("DEP", 2),
+    ("Epci", 9),
+    ("Grd Quart", 7),
+    ("Insee Com", 5),
+    ("Insee Dep", 2),
+    ("Insee Reg", 2),
+    ("Iris", 9),
+    ("REG", 2),
+];
+
+impl CheckedRow for EntryCSV {
+    fn get_invalid_cells(&self) -> Vec<InvalidCell> {
+        self.get_numeric_cells()
+            .into_iter()
+            .filter_map(|(column, value)| match value {
+                Some(value) if parse_f64(value).is_err() => {
+                    Some(InvalidCell::new(column, value, "not a number"))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+//Empty values are missing, the decimal separator can be a comma.
+fn parse_f64(value: &str) -> Result<Option<f64>, ParseFloatError> {
+    match value.trim().is_empty() {
+        true => Ok(None),
+        false => str::replace(value, ",", ".").parse::<f64>().map(Some),
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -414,3 +555,19 @@ pub struct EntryCSV {
     #[serde(rename(deserialize = "SEUILS revenue median region"))]
     seuils_revenue_median_region: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::get_expected_headers;
+    use domain::core::flat_entry::FLAT_SCORE_COLUMNS;
+
+    #[test]
+    fn score_headers_are_read_by_the_import() {
+        let expected_headers = get_expected_headers::<EntryCSV>();
+        for (column, header) in SCORE_HEADERS.iter() {
+            assert!(expected_headers.contains(header), "{:?}", header);
+            assert!(FLAT_SCORE_COLUMNS.contains(column), "{:?}", column);
+        }
+    }
+}
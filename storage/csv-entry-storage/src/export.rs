@@ -0,0 +1,232 @@
+use crate::entry_csv::{EntryCSV, SCORE_HEADERS};
+use crate::mapping::get_expected_headers;
+use crate::DEFAULT_DELIMITER;
+use domain::core::flat_entry::{FlatEntry, FLAT_SCORE_COLUMNS};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+//Territory columns of the flat layout, before the scores.
+const FLAT_KEY_COLUMNS: [&str; 5] = [
+    "iris_code",
+    "iris_code_designation",
+    "region",
+    "department",
+    "insee_com",
+];
+
+//Layout of an exported CSV file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExportLayout {
+    //Headers of the source file, `;` and decimal commas.
+    Source,
+    //Territory keys and scores, like the Parquet export, `,` and decimal points.
+    Flat,
+}
+
+impl ExportLayout {
+    pub fn get_delimiter(&self) -> u8 {
+        match self {
+            ExportLayout::Source => DEFAULT_DELIMITER,
+            ExportLayout::Flat => b',',
+        }
+    }
+}
+
+//Write the entries in the layout, the source headers are mapped like the ones of the import.
+//Returns the number of entries written. The write errors are io errors, apart from the errors of
+//the read of the source files.
+pub fn write_entries<W, I, E>(
+    writer: W,
+    entries: I,
+    layout: ExportLayout,
+    delimiter: u8,
+    columns: &BTreeMap<String, String>,
+) -> Result<usize, E>
+where
+    W: Write,
+    I: Iterator<Item = Result<FlatEntry, E>>,
+    E: From<io::Error>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    let headers = match layout {
+        ExportLayout::Source => get_expected_headers::<EntryCSV>().to_vec(),
+        ExportLayout::Flat => FLAT_KEY_COLUMNS
+            .iter()
+            .chain(FLAT_SCORE_COLUMNS.iter())
+            .copied()
+            .collect(),
+    };
+    write_record(
+        &mut writer,
+        headers
+            .iter()
+            .map(|header| columns.get(*header).map(String::as_str).unwrap_or(header)),
+    )?;
+
+    let positions: HashMap<&str, usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(position, header)| (*header, position))
+        .collect();
+    let mut count = 0;
+    for entry in entries {
+        let entry = entry?;
+        let row = match layout {
+            ExportLayout::Source => get_source_row(&entry, &positions),
+            ExportLayout::Flat => get_flat_row(&entry),
+        };
+        write_record(&mut writer, row.iter())?;
+        count += 1;
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+fn write_record<W: Write, I, T>(writer: &mut csv::Writer<W>, record: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    Ok(writer.write_record(record)?)
+}
+
+fn get_flat_row(entry: &FlatEntry) -> Vec<String> {
+    let keys = vec![
+        &entry.iris_code,
+        &entry.iris_code_designation,
+        &entry.region,
+        &entry.department,
+        &entry.insee_com,
+    ];
+    keys.into_iter()
+        .map(|key| key.clone().unwrap_or_default())
+        .chain(entry.scores.iter().map(|score| format_score(*score, false)))
+        .collect()
+}
+
+//The territory columns are filled from the keys of the indexes and the territory of the entry,
+//"12 - Nom" giving DEP and Nom Dep. The scores are written under the headers the import reads them
+//from, the columns the database does not hold are left empty.
+fn get_source_row(entry: &FlatEntry, positions: &HashMap<&str, usize>) -> Vec<String> {
+    let mut row = vec![String::new(); positions.len()];
+    let mut set = |header: &str, value: String| {
+        if let Some(position) = positions.get(header) {
+            row[*position] = value;
+        }
+    };
+
+    let iris_code = entry.iris_code.clone().unwrap_or_default();
+    set("Code Iris", iris_code.to_string());
+    set("Iris", iris_code);
+    let designation = entry.iris_code_designation.clone().unwrap_or_default();
+    set("Libiris", designation.to_string());
+    set("Nom Iris", designation);
+    set("Nom Reg", entry.region.clone().unwrap_or_default());
+    let insee_com = entry.insee_com.clone().unwrap_or_default();
+    set("Insee Com", insee_com.to_string());
+    set("COM", insee_com);
+    set("Nom Com", entry.commune.clone().unwrap_or_default());
+    let (dep, nom_dep) = split_key(entry.department.as_deref());
+    set("DEP", dep);
+    set("Nom Dep", nom_dep);
+    let (epci, libepci) = split_key(entry.epci.as_deref());
+    set("Epci", epci);
+    set("Libepci", libepci);
+
+    for (score, header) in SCORE_HEADERS.iter() {
+        let position = FLAT_SCORE_COLUMNS.iter().position(|column| column == score);
+        if let Some(value) = position.and_then(|position| entry.scores[position]) {
+            set(header, format_score(Some(value), true));
+        }
+    }
+    row
+}
+
+//Code and name of a "code - name" key, a key without a name is a code.
+fn split_key(key: Option<&str>) -> (String, String) {
+    match key.unwrap_or_default().split_once(" - ") {
+        Some((code, name)) => (code.to_string(), name.to_string()),
+        None => (key.unwrap_or_default().to_string(), String::new()),
+    }
+}
+
+//The scores read as f32 are written without the digits of their conversion to f64.
+fn format_score(score: Option<f64>, decimal_comma: bool) -> String {
+    let value = match score {
+        Some(value) if value as f32 as f64 == value => (value as f32).to_string(),
+        Some(value) => value.to_string(),
+        None => return String::new(),
+    };
+    match decimal_comma {
+        true => value.replace('.', ","),
+        false => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AvgStat;
+    use domain::core::entry::{Entry, InformationAccess, Territory};
+
+    #[test]
+    fn source_layout_is_read_back_by_the_import() {
+        let entry = Entry::new(
+            Some(180.5),
+            None,
+            None,
+            None,
+            Some("12030000".to_string()),
+            Some("Ronville".to_string()),
+            Some(
+                InformationAccess::new(Some(86.5), None, None, None, Some(12.25), None, None, None)
+                    .with_information_score(Some(157.75)),
+            ),
+            None,
+            None,
+            None,
+        )
+        .with_territory(Territory {
+            region: "Reg1".to_string(),
+            department: "12 - Dep12".to_string(),
+            epci: "200033579 - CU d'Arras".to_string(),
+            insee_com: "1203".to_string(),
+        });
+        let flat_entry = FlatEntry::new(
+            &entry,
+            Some("Reg1".to_string()),
+            Some("12 - Dep12".to_string()),
+            Some("1203".to_string()),
+        );
+        let mut output = Vec::new();
+        write_entries::<_, _, io::Error>(
+            &mut output,
+            vec![Ok(flat_entry)].into_iter(),
+            ExportLayout::Source,
+            DEFAULT_DELIMITER,
+            &BTreeMap::new(),
+        )
+        .unwrap();
+
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(DEFAULT_DELIMITER)
+            .from_reader(output.as_slice());
+        let csv_entry: EntryCSV = reader.deserialize().next().unwrap().unwrap();
+        let imported =
+            csv_entry.to_entry(&AvgStat::new([None; 5]), &BTreeMap::new(), &BTreeMap::new());
+        assert_eq!(imported.iris_code, entry.iris_code);
+        assert_eq!(imported.iris_code_designation, entry.iris_code_designation);
+        assert_eq!(imported.global, Some(180.5));
+        let axis = imported.information_access.unwrap();
+        assert_eq!(axis.global, Some(86.5));
+        assert_eq!(axis.monoparental_families_percent, Some(12.25));
+        assert_eq!(axis.information_score, Some(157.75));
+        assert_eq!(
+            serde_json::to_value(&imported.territory).unwrap(),
+            serde_json::to_value(&entry.territory).unwrap()
+        );
+    }
+}
@@ -1,17 +1,34 @@
 #[macro_use]
 extern crate serde_derive;
 
+pub mod batch;
 pub mod entry_csv;
+pub mod export;
+pub mod mapping;
 pub mod postal_code_csv_index;
-
+pub mod row;
+pub mod scan;
+pub mod source;
+pub mod spreadsheet;
+
+use batch::CSVEntryBatches;
+use csv::StringRecord;
+use domain::business::aggregates::Averages;
+use domain::business::upsert::UpsertEntry;
 use domain::core::entry::Entry;
 use domain::core::entry::Iris;
+use domain::core::hierarchy::Hierarchy;
+use encoding_rs::Encoding;
 use entry_csv::EntryCSV;
+use mapping::{get_code_widths, map_headers};
 use postal_code_csv_index::PostalCodeIrisCodeCSV;
+use rayon::prelude::*;
+use row::{read_row, ErrorPolicy, RowError, RowErrors};
+use scan::{CSVEntryScan, EntryStats};
+use source::{RecordSource, Source};
 use std::collections::BTreeMap;
 use std::collections::HashSet;
-use std::fs;
-use std::iter::FromIterator;
+use std::sync::OnceLock;
 use thiserror::Error;
 
 //Define the possible errors
@@ -29,23 +46,69 @@ pub enum CSVEntryStorageError {
         #[from]
         source: csv::Error,
     },
+    #[error("Invalid row: {source}")]
+    Row {
+        #[from]
+        source: RowError,
+    },
+    #[error("Column mapping error: {source}")]
+    MappingJson {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("Column mapping error: {source}")]
+    MappingToml {
+        #[from]
+        source: toml::de::Error,
+    },
+    #[error("Column mapping error: unknown columns in [{0}]: '{1}'")]
+    UnknownColumns(String, String),
+    #[error("Spreadsheet error: {source}")]
+    Spreadsheet {
+        #[from]
+        source: calamine::Error,
+    },
 }
 
 //Define a generic error type to simplify return.
 pub type CSVEntryStorageResult<T> = std::result::Result<T, CSVEntryStorageError>;
 
+//Field delimiter of the source files when none is found in the header line.
+pub const DEFAULT_DELIMITER: u8 = b';';
+
 pub struct CSVEntryStorage {
     pub path: String,
     pub entries: Option<Vec<EntryCSV>>,
+    pub delimiter: Option<u8>,
+    pub encoding: Option<&'static Encoding>,
+    pub sheet: Option<String>,
+    pub on_error: ErrorPolicy,
+    pub columns: BTreeMap<String, String>,
+    //Found on the first read, a workbook is not opened again by the next ones.
+    source: OnceLock<Source>,
 }
 
+//Averages of a territory, None when none of its rows has the score.
 #[derive(Copy, Clone)]
 pub struct AvgStat {
-    avg_entries_global_score: f32,
-    avg_entries_numeric_competencies: f32,
-    avg_entries_administrative_competencies: f32,
-    avg_entries_numeric_interface_access: f32,
-    avg_entries_information_access: f32,
+    avg_entries_global_score: Option<f64>,
+    avg_entries_numeric_competencies: Option<f64>,
+    avg_entries_administrative_competencies: Option<f64>,
+    avg_entries_numeric_interface_access: Option<f64>,
+    avg_entries_information_access: Option<f64>,
+}
+
+impl AvgStat {
+    //The averages are in the order of the domain `get_scores`.
+    fn new(averages: Averages) -> Self {
+        AvgStat {
+            avg_entries_global_score: averages[0],
+            avg_entries_information_access: averages[1],
+            avg_entries_numeric_interface_access: averages[2],
+            avg_entries_administrative_competencies: averages[3],
+            avg_entries_numeric_competencies: averages[4],
+        }
+    }
 }
 
 impl CSVEntryStorage {
@@ -53,246 +116,200 @@ impl CSVEntryStorage {
         CSVEntryStorage {
             path: path,
             entries: None,
+            delimiter: None,
+            encoding: None,
+            sheet: None,
+            on_error: ErrorPolicy::Fail,
+            columns: BTreeMap::new(),
+            source: OnceLock::new(),
         }
     }
-    pub fn load(&mut self) -> CSVEntryStorageResult<()> {
-        let mut entries = Vec::new();
-        let file = fs::read(&self.path).unwrap();
-        let mut rdr = csv::ReaderBuilder::new()
-            .delimiter(b';')
-            .from_reader(&*file);
-
-        for result in rdr.deserialize() {
-            let record: EntryCSV = result?;
-            entries.push(record);
-        }
-        self.entries = Some(entries);
-        Ok(())
+
+    //None to detect the delimiter from the header line.
+    pub fn with_delimiter(mut self, delimiter: Option<u8>) -> Self {
+        self.delimiter = delimiter;
+        self
     }
 
-    fn concat_name(&self, code: String, name: String) -> String {
-        match name.trim().is_empty() {
-            false => format!("{} - {}", code, name),
-            true => "".to_string(),
-        }
+    //None to detect the encoding from the BOM or the content.
+    pub fn with_encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.encoding = encoding;
+        self
     }
 
-    fn get_stats(&self, csv_entries: Vec<EntryCSV>) -> AvgStat {
-        let number_of_entries: f32 = csv_entries.len() as f32;
-        let sum_entries_global_score: f32 = csv_entries
-            .iter()
-            .map(|csv_entry| {
-                csv_entry
-                    .clean_and_parse_f32(&csv_entry.score_global_region_star)
-                    .unwrap()
-            })
-            .sum();
+    //Sheet of a workbook source, None for the first one.
+    pub fn with_sheet(mut self, sheet: Option<String>) -> Self {
+        self.sheet = sheet;
+        self
+    }
 
-        let sum_entries_numeric_competencies: f32 = csv_entries
-            .iter()
-            .map(|csv_entry| {
-                csv_entry
-                    .clean_and_parse_f32(&csv_entry.competences_numeriques_scolaires_region_1)
-                    .unwrap()
-            })
-            .sum();
-        let sum_entries_administrative_competencies: f32 = csv_entries
-            .iter()
-            .map(|csv_entry| {
-                csv_entry
-                    .clean_and_parse_f32(&csv_entry.competences_administatives_region_1)
-                    .unwrap()
-            })
-            .sum();
-        let sum_entries_numeric_interface_access: f32 = csv_entries
-            .iter()
-            .map(|csv_entry| {
-                csv_entry
-                    .clean_and_parse_f32(&csv_entry.acces_aux_interfaces_numeriques_region_1)
-                    .unwrap()
-            })
-            .sum();
-        let sum_entries_information_access: f32 = csv_entries
-            .iter()
-            .map(|csv_entry| {
-                csv_entry
-                    .clean_and_parse_f32(&csv_entry.acces_information_region_1)
-                    .unwrap()
-            })
-            .sum();
+    pub fn with_error_policy(mut self, on_error: ErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
 
-        AvgStat {
-            avg_entries_global_score: sum_entries_global_score / number_of_entries,
-            avg_entries_numeric_competencies: sum_entries_numeric_competencies / number_of_entries,
-            avg_entries_administrative_competencies: sum_entries_administrative_competencies
-                / number_of_entries,
-            avg_entries_numeric_interface_access: sum_entries_numeric_interface_access
-                / number_of_entries,
-            avg_entries_information_access: sum_entries_information_access / number_of_entries,
+    //Source headers by expected header, see mapping::ColumnMapping.
+    pub fn with_columns(mut self, columns: BTreeMap<String, String>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    //Load the rows kept by the error policy, returns the errors of the rows.
+    pub fn load(&mut self) -> CSVEntryStorageResult<RowErrors> {
+        let mut entries = Vec::new();
+        let mut errors = RowErrors::default();
+        for batch in self.iter_csv_batches()? {
+            for row in batch? {
+                if let Some(csv_entry) = row.check(self.on_error, &mut errors)? {
+                    entries.push(csv_entry);
+                }
+            }
         }
+        self.entries = Some(entries);
+        Ok(errors)
     }
 
-    pub fn get_entries(&self) -> Vec<Entry> {
-        let nationalStats = self.get_stats(self.get_csv_entries());
-        let mut regionsStats: BTreeMap<String, AvgStat> = BTreeMap::new();
+    pub fn get_source(&self) -> CSVEntryStorageResult<&Source> {
+        if let Some(source) = self.source.get() {
+            return Ok(source);
+        }
+        let source = Source::find(
+            &self.path,
+            self.encoding,
+            self.delimiter,
+            self.sheet.as_deref(),
+        )?;
+        Ok(self.source.get_or_init(|| source))
+    }
 
+    //Rows of the file transcoded to UTF-8 or of the sheet, with the expected headers, the source
+    //ones being mapped.
+    fn get_records(&self) -> CSVEntryStorageResult<(Box<dyn RecordSource>, StringRecord)> {
+        let mut records = self.get_source()?.open(&self.path)?;
+        let headers = map_headers::<EntryCSV>(records.get_headers(), &self.columns);
+        records.set_code_widths(get_code_widths(&headers, &entry_csv::CODE_WIDTHS));
+        Ok((records, headers))
+    }
 
-        for region in self.get_regions() {
+    //Read the rows by batches, deserialized and checked on the thread pool.
+    pub fn iter_csv_batches(&self) -> CSVEntryStorageResult<CSVEntryBatches> {
+        let (records, headers) = self.get_records()?;
+        Ok(CSVEntryBatches::new(records, headers, &self.path))
+    }
 
-            regionsStats.insert(
-                region.to_string(),
-                self.get_stats(
-                    self.get_csv_entries()
-                        .iter()
-                        .filter_map(|entry_csv| match &entry_csv.nom_reg == &region {
-                            true => Some(entry_csv.clone()),
-                            false => None,
-                        })
-                        .collect(),
-                ),
-            );
+    //First pass over the file: the averages and the territory indexes.
+    //The rows are added in the order of the file, the f32 sums do not depend on the threads.
+    //The errors of the rows are reported by this pass only.
+    pub fn scan(&self) -> CSVEntryStorageResult<CSVEntryScan> {
+        let mut scan = CSVEntryScan::default();
+        for batch in self.iter_csv_batches()? {
+            for row in batch? {
+                if let Some(csv_entry) = row.check(self.on_error, &mut scan.errors)? {
+                    scan.add(&csv_entry);
+                }
+            }
+        }
+        Ok(scan)
+    }
 
+    //Second pass over the file: batches of entries, converted with the averages of the first pass.
+    pub fn iter_entry_batches<'a>(
+        &self,
+        stats: &'a EntryStats,
+    ) -> CSVEntryStorageResult<Box<dyn Iterator<Item = CSVEntryStorageResult<Vec<Entry>>> + 'a>>
+    {
+        let on_error = self.on_error;
+        Ok(Box::new(self.iter_csv_batches()?.map(move |batch| {
+            let mut csv_entries = Vec::new();
+            for row in batch? {
+                if let Some(csv_entry) = row.check(on_error, &mut RowErrors::default())? {
+                    csv_entries.push(csv_entry);
+                }
+            }
+            Ok(csv_entries
+                .par_iter()
+                .map(|csv_entry| {
+                    csv_entry.to_entry(&stats.national, &stats.regions, &stats.departments)
+                })
+                .collect())
+        })))
+    }
 
+    //Scan of the loaded rows.
+    fn scan_loaded(&self) -> CSVEntryScan {
+        let mut scan = CSVEntryScan::default();
+        for csv_entry in self.iter_loaded() {
+            scan.add(csv_entry);
         }
+        scan
+    }
 
-
-        let mut departmentsStats: BTreeMap<String, AvgStat> = BTreeMap::new();
-        for department in self.get_departments() {
-            departmentsStats.insert(
-                department.to_string(),
-                self.get_stats(
-                    self.get_csv_entries()
-                        .iter()
-                        .filter_map(|csv_entry| {
-                            match &department
-                                == &self.concat_name(
-                                    csv_entry.dep.to_owned(),
-                                    csv_entry.nom_dep.to_owned(),
-                                ) {
-                                true => Some(csv_entry.clone()),
-                                false => None,
-                            }
-                        })
-                        .collect(),
-                ),
-            );
+    fn iter_loaded(&self) -> std::slice::Iter<'_, EntryCSV> {
+        match &self.entries {
+            Some(entries) => entries.iter(),
+            None => [].iter(),
         }
+    }
 
-        self.get_csv_entries()
-            .iter()
-            .map(|csv_entry| csv_entry.to_entry(&nationalStats, &regionsStats, &departmentsStats))
+    pub fn get_entries(&self) -> Vec<Entry> {
+        let stats = self.scan_loaded().get_stats();
+        self.iter_loaded()
+            .map(|csv_entry| {
+                csv_entry.to_entry(&stats.national, &stats.regions, &stats.departments)
+            })
             .collect()
     }
 
-    pub fn get_departments(&self) -> HashSet<String> {
-        let all_dep: Vec<String> = self
-            .get_csv_entries()
-            .iter()
-            .map(|csv_entry| {
-                self.concat_name(csv_entry.dep.to_owned(), csv_entry.nom_dep.to_owned())
+    //Loaded entries with the keys of their territories, to be merged into an imported database.
+    pub fn get_upsert_entries(&self) -> Vec<UpsertEntry> {
+        let stats = self.scan_loaded().get_stats();
+        self.iter_loaded()
+            .map(|csv_entry| UpsertEntry {
+                entry: csv_entry.to_entry(&stats.national, &stats.regions, &stats.departments),
+                region: csv_entry.nom_reg.to_string(),
+                department: csv_entry.get_department(),
+                insee_com: csv_entry.insee_com.to_string(),
             })
-            .collect();
+            .collect()
+    }
+
+    pub fn get_hierarchy(&self) -> Hierarchy {
+        self.scan_loaded().get_hierarchy().clone()
+    }
 
-        std::collections::HashSet::from_iter(all_dep)
+    pub fn get_departments(&self) -> HashSet<String> {
+        self.iter_loaded()
+            .map(|csv_entry| csv_entry.get_department())
+            .collect()
     }
 
     pub fn get_insee_coms(&self) -> HashSet<String> {
-        let all_insee_com: Vec<String> = self
-            .get_csv_entries()
-            .iter()
+        self.iter_loaded()
             .map(|csv_entry| csv_entry.insee_com.to_owned())
-            .collect();
-
-        std::collections::HashSet::from_iter(all_insee_com)
+            .collect()
     }
 
     pub fn get_insee_com_with_iris(&self) -> BTreeMap<String, Vec<String>> {
-        let mut results: BTreeMap<String, Vec<String>> = BTreeMap::new();
-
-        for entry in self.get_csv_entries() {
-            let mut iris_list = match &mut results.get_mut(&entry.insee_com) {
-                Some(vec) => vec.to_vec(),
-                None => Vec::new(),
-            };
-
-            iris_list.push(entry.code_iris.to_string());
-            results.insert(entry.insee_com.to_string(), iris_list.to_vec());
-        }
-
-        //Remove unasigned items
-        results.remove_entry(&"".to_string());
-        results
+        self.scan_loaded().get_insee_com_with_iris().clone()
     }
 
     pub fn get_regions(&self) -> HashSet<String> {
-        let all_reg: Vec<String> = self
-            .get_csv_entries()
-            .iter()
+        self.iter_loaded()
             .map(|csv_entry| csv_entry.nom_reg.to_owned())
-            .collect();
-
-        std::collections::HashSet::from_iter(all_reg)
+            .collect()
     }
 
     pub fn get_regions_with_iris(&self) -> BTreeMap<String, Vec<String>> {
-        let mut results: BTreeMap<String, Vec<String>> = BTreeMap::new();
-        let regions = self.get_regions();
-
-        for region in regions {
-            results.insert(
-                region.clone(),
-                self.get_csv_entries()
-                    .iter()
-                    .filter_map(|csv_entry| match &csv_entry.nom_reg == &region {
-                        true => Some(csv_entry.code_iris.clone()),
-                        false => None,
-                    })
-                    .collect(),
-            );
-        }
-
-        //Remove unasigned items
-        results.remove_entry(&"".to_string());
-        results
+        self.scan_loaded().get_regions_with_iris().clone()
     }
 
     pub fn get_departements_with_iris(&self) -> BTreeMap<String, Vec<String>> {
-        let mut results: BTreeMap<String, Vec<String>> = BTreeMap::new();
-        let departements = self.get_departments();
-
-        for departement in departements {
-            results.insert(
-                departement.clone(),
-                self.get_csv_entries()
-                    .iter()
-                    .filter_map(|csv_entry| {
-                        match &self
-                            .concat_name(csv_entry.dep.to_owned(), csv_entry.nom_dep.to_owned())
-                            == &departement
-                        {
-                            true => Some(csv_entry.code_iris.clone()),
-                            false => None,
-                        }
-                    })
-                    .collect(),
-            );
-        }
-
-        //Remove unasigned items
-        results.remove_entry(&"".to_string());
-        results
+        self.scan_loaded().get_departements_with_iris().clone()
     }
 
     pub fn get_national_entries() -> Entry {
         unimplemented!()
     }
-
-    pub fn get_csv_entries(&self) -> Vec<EntryCSV> {
-        match &self.entries {
-            Some(entries) => entries.to_vec(),
-            None => Vec::new(),
-        }
-    }
 }
 
 #[derive(Error, Debug)]
@@ -316,6 +333,13 @@ pub type PostalCodeCsvStorageResult<T> = std::result::Result<T, PostalCodeCsvSto
 pub struct PostalCodeCsvStorage {
     pub path: String,
     pub postal_codes: Option<Vec<PostalCodeIrisCodeCSV>>,
+    pub delimiter: Option<u8>,
+    pub encoding: Option<&'static Encoding>,
+    pub sheet: Option<String>,
+    pub on_error: ErrorPolicy,
+    pub columns: BTreeMap<String, String>,
+    //Found on the first read, a workbook is not opened again by the next ones.
+    source: OnceLock<Source>,
 }
 
 impl PostalCodeCsvStorage {
@@ -323,24 +347,80 @@ impl PostalCodeCsvStorage {
         PostalCodeCsvStorage {
             path: path,
             postal_codes: None,
+            delimiter: None,
+            encoding: None,
+            sheet: None,
+            on_error: ErrorPolicy::Fail,
+            columns: BTreeMap::new(),
+            source: OnceLock::new(),
         }
     }
 
-    pub fn load(&mut self) -> CSVEntryStorageResult<()> {
-        let mut entries = Vec::new();
+    //None to detect the delimiter from the header line.
+    pub fn with_delimiter(mut self, delimiter: Option<u8>) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
 
-        let file = fs::read(&self.path).unwrap();
-        let mut rdr = csv::ReaderBuilder::new()
-            .delimiter(b';')
-            .from_reader(&*file);
+    //None to detect the encoding from the BOM or the content.
+    pub fn with_encoding(mut self, encoding: Option<&'static Encoding>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    //Sheet of a workbook source, None for the first one.
+    pub fn with_sheet(mut self, sheet: Option<String>) -> Self {
+        self.sheet = sheet;
+        self
+    }
 
-        for result in rdr.deserialize() {
-            let record: PostalCodeIrisCodeCSV = result?;
-            entries.push(record);
+    pub fn with_error_policy(mut self, on_error: ErrorPolicy) -> Self {
+        self.on_error = on_error;
+        self
+    }
+
+    //Source headers by expected header, see mapping::ColumnMapping.
+    pub fn with_columns(mut self, columns: BTreeMap<String, String>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    pub fn get_source(&self) -> CSVEntryStorageResult<&Source> {
+        if let Some(source) = self.source.get() {
+            return Ok(source);
+        }
+        let source = Source::find(
+            &self.path,
+            self.encoding,
+            self.delimiter,
+            self.sheet.as_deref(),
+        )?;
+        Ok(self.source.get_or_init(|| source))
+    }
+
+    //Load the rows kept by the error policy, returns the errors of the rows.
+    pub fn load(&mut self) -> CSVEntryStorageResult<RowErrors> {
+        let mut entries = Vec::new();
+        let mut errors = RowErrors::default();
+
+        let mut records = self.get_source()?.open(&self.path)?;
+        let source_headers = records.get_headers().clone();
+        let headers = map_headers::<PostalCodeIrisCodeCSV>(&source_headers, &self.columns);
+        records.set_code_widths(get_code_widths(
+            &headers,
+            &postal_code_csv_index::CODE_WIDTHS,
+        ));
+
+        while let Some(record) = records.next_record()? {
+            let row =
+                read_row::<PostalCodeIrisCodeCSV>(&self.path, &headers, &source_headers, record);
+            if let Some(record) = row.check(self.on_error, &mut errors)? {
+                entries.push(record);
+            }
         }
 
         self.postal_codes = Some(entries);
-        Ok(())
+        Ok(errors)
     }
 
     fn concat_name(&self, code: String, name: String) -> String {
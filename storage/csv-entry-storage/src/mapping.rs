@@ -0,0 +1,181 @@
+use crate::entry_csv::EntryCSV;
+use crate::postal_code_csv_index::PostalCodeIrisCodeCSV;
+use crate::{CSVEntryStorageError, CSVEntryStorageResult};
+use csv::StringRecord;
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+//Source headers of the input files by header expected by the rows, read from a TOML or JSON file:
+//  [entries]
+//  "Code Iris" = "CODE_IRIS"
+//  [postal]
+//  "Geo Point" = "coordonnees_gps"
+//The headers which are not mapped keep their default names, an unknown section or expected header
+//is an error.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColumnMapping {
+    #[serde(default)]
+    pub entries: BTreeMap<String, String>,
+    #[serde(default)]
+    pub postal: BTreeMap<String, String>,
+}
+
+impl ColumnMapping {
+    //JSON for a .json file, TOML otherwise.
+    pub fn from_file(path: &str) -> CSVEntryStorageResult<Self> {
+        let content = fs::read_to_string(path)?;
+        let mapping: ColumnMapping = match Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("json") => serde_json::from_str(&content)?,
+            _ => toml::from_str(&content)?,
+        };
+        check_columns::<EntryCSV>("entries", &mapping.entries)?;
+        check_columns::<PostalCodeIrisCodeCSV>("postal", &mapping.postal)?;
+        Ok(mapping)
+    }
+}
+
+//The mapped headers are expected by the rows of T, a misspelt one would be left unmapped.
+fn check_columns<T: DeserializeOwned>(
+    section: &str,
+    columns: &BTreeMap<String, String>,
+) -> CSVEntryStorageResult<()> {
+    let expected_headers = get_expected_headers::<T>();
+    let unknown: Vec<&str> = columns
+        .keys()
+        .map(String::as_str)
+        .filter(|header| !expected_headers.contains(header))
+        .collect();
+    match unknown.is_empty() {
+        true => Ok(()),
+        false => Err(CSVEntryStorageError::UnknownColumns(
+            section.to_string(),
+            unknown.join("', '"),
+        )),
+    }
+}
+
+//Headers of a file with the expected headers of T in place of their source headers.
+//The headers are compared without their leading, trailing and repeated spaces, a re-spaced
+//header is found without being mapped.
+pub fn map_headers<T: DeserializeOwned>(
+    headers: &StringRecord,
+    columns: &BTreeMap<String, String>,
+) -> StringRecord {
+    let expected_headers = get_expected_headers::<T>();
+    headers
+        .iter()
+        .map(|header| {
+            let header_key = get_header_key(header);
+            let mapped = columns
+                .iter()
+                .find(|(_, source)| get_header_key(source) == header_key)
+                .map(|(expected, _)| expected.as_str());
+            let expected = expected_headers.iter().find(|expected| {
+                !columns.contains_key(**expected) && get_header_key(expected) == header_key
+            });
+            mapped.or_else(|| expected.copied()).unwrap_or(header)
+        })
+        .collect()
+}
+
+//Width of the code column at each position of the headers, 0 for the other columns.
+pub fn get_code_widths(headers: &StringRecord, code_widths: &[(&str, usize)]) -> Vec<usize> {
+    headers
+        .iter()
+        .map(|header| {
+            code_widths
+                .iter()
+                .find(|(code, _)| *code == header)
+                .map(|(_, width)| *width)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn get_header_key(header: &str) -> String {
+    header.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+//Headers of the fields of T, as named by its serde attributes.
+pub(crate) fn get_expected_headers<T: DeserializeOwned>() -> &'static [&'static str] {
+    let mut fields = FieldsDeserializer::default();
+    let _ = T::deserialize(&mut fields);
+    fields.fields
+}
+
+//Deserializer stopping at the struct to read the names of its fields.
+#[derive(Default)]
+struct FieldsDeserializer {
+    fields: &'static [&'static str],
+}
+
+impl<'de> Deserializer<'de> for &mut FieldsDeserializer {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only the fields of a struct are read"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.fields = fields;
+        Err(de::Error::custom("only the fields of a struct are read"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_columns(columns: &[(&str, &str)]) -> BTreeMap<String, String> {
+        columns
+            .iter()
+            .map(|(expected, source)| (expected.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn expected_headers_can_be_mapped() {
+        let columns = get_columns(&[("Code Iris", "CODE_IRIS"), ("Nom Reg", "REGION")]);
+        assert!(check_columns::<EntryCSV>("entries", &columns).is_ok());
+        let columns = get_columns(&[("Geo Point", "coordonnees_gps")]);
+        assert!(check_columns::<PostalCodeIrisCodeCSV>("postal", &columns).is_ok());
+    }
+
+    #[test]
+    fn unknown_headers_are_rejected() {
+        let columns = get_columns(&[("Code iris", "CODE_IRIS"), ("Nom Reg", "REGION")]);
+        match check_columns::<EntryCSV>("entries", &columns) {
+            Err(CSVEntryStorageError::UnknownColumns(section, columns)) => {
+                assert_eq!(section, "entries");
+                assert_eq!(columns, "Code iris");
+            }
+            _ => panic!("the unknown header is not an error"),
+        }
+        //An entries header is not a postal one.
+        let columns = get_columns(&[("Code Iris", "CODE_IRIS")]);
+        assert!(check_columns::<PostalCodeIrisCodeCSV>("postal", &columns).is_err());
+    }
+
+    #[test]
+    fn unknown_sections_are_rejected() {
+        let mapping = toml::from_str::<ColumnMapping>("[entry]\n\"Code Iris\" = \"CODE_IRIS\"\n");
+        assert!(mapping.is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::row::{CheckedRow, InvalidCell};
 use domain::core::entry::*;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,16 +13,25 @@ pub struct PostalCodeIrisCodeCSV {
     pub geo_point: String,
 }
 
+//Widths of the code columns, see entry_csv::CODE_WIDTHS.
+pub const CODE_WIDTHS: [(&str, usize); 2] = [("INSEE_COM", 5), ("Code_postal", 5)];
+
 impl PostalCodeIrisCodeCSV {
+    //An invalid geo point is None, it is reported by get_invalid_cells.
     pub fn to_postal_code(&self) -> Iris {
+        Iris::new(Some(self.iris_code.clone()), self.get_geo_loc())
+    }
+
+    //"latitude,longitude"
+    fn get_geo_loc(&self) -> Option<GeoLoc> {
         let geo_parts: Vec<&str> = self.geo_point.split(",").collect();
-        Iris::new(
-            Some(self.iris_code.clone()),
-            Some(GeoLoc::new(
-                geo_parts[0].parse::<f64>().unwrap(),
-                geo_parts[1].parse::<f64>().unwrap(),
+        match geo_parts.as_slice() {
+            [latitude, longitude] => Some(GeoLoc::new(
+                latitude.trim().parse::<f64>().ok()?,
+                longitude.trim().parse::<f64>().ok()?,
             )),
-        )
+            _ => None,
+        }
     }
 
     pub fn get_code(&self) -> String {
@@ -31,3 +41,16 @@ impl PostalCodeIrisCodeCSV {
         }
     }
 }
+
+impl CheckedRow for PostalCodeIrisCodeCSV {
+    fn get_invalid_cells(&self) -> Vec<InvalidCell> {
+        match self.get_geo_loc() {
+            Some(_) => Vec::new(),
+            None => vec![InvalidCell::new(
+                "Geo Point",
+                &self.geo_point,
+                "not a latitude,longitude pair",
+            )],
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::CSVEntryStorageResult;
+use csv::{Position, Reader, StringRecord};
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::io::Read;
+use std::str::FromStr;
+
+//What the import does with a row holding an invalid value.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ErrorPolicy {
+    //Stop at the first invalid value.
+    Fail,
+    //Leave the row out.
+    Skip,
+    //Keep the row, without its invalid values.
+    Null,
+}
+
+impl FromStr for ErrorPolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "fail" => Ok(ErrorPolicy::Fail),
+            "skip" => Ok(ErrorPolicy::Skip),
+            "null" => Ok(ErrorPolicy::Null),
+            _ => Err(format!("'{}' is not one of fail, skip, null", value)),
+        }
+    }
+}
+
+//Invalid row of a source file. The column is unknown when the row itself is malformed.
+#[derive(Debug, Clone)]
+pub struct RowError {
+    pub file: String,
+    pub line: u64,
+    pub column: Option<String>,
+    pub value: String,
+    pub reason: String,
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.reason)?;
+        if let Some(column) = &self.column {
+            write!(f, " in column '{}': '{}'", column, self.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RowError {}
+
+//Errors of the rows of a file, with the number of rows left out.
+#[derive(Debug, Default)]
+pub struct RowErrors {
+    pub skipped: usize,
+    pub errors: Vec<RowError>,
+}
+
+//Value of a row which can not be converted.
+pub struct InvalidCell {
+    pub column: &'static str,
+    pub value: String,
+    pub reason: &'static str,
+}
+
+impl InvalidCell {
+    pub fn new(column: &'static str, value: &str, reason: &'static str) -> Self {
+        InvalidCell {
+            column,
+            value: value.to_string(),
+            reason,
+        }
+    }
+}
+
+//Rows checking their values once deserialized.
+pub trait CheckedRow {
+    fn get_invalid_cells(&self) -> Vec<InvalidCell>;
+}
+
+//Row of a source file with the errors found in it, the record is None when it can not be read.
+pub struct CSVRow<T> {
+    pub line: u64,
+    pub record: Option<T>,
+    pub errors: Vec<RowError>,
+}
+
+impl<T> CSVRow<T> {
+    //The record to import following the policy, None when the row is left out.
+    //The errors of the rows kept or left out are added to `errors`.
+    pub fn check(
+        self,
+        policy: ErrorPolicy,
+        errors: &mut RowErrors,
+    ) -> CSVEntryStorageResult<Option<T>> {
+        let CSVRow {
+            record,
+            errors: row_errors,
+            ..
+        } = self;
+        if row_errors.is_empty() {
+            return Ok(record);
+        }
+
+        match (policy, record) {
+            (ErrorPolicy::Fail, _) => Err(row_errors[0].clone().into()),
+            (ErrorPolicy::Null, Some(record)) => {
+                errors.errors.extend(row_errors);
+                Ok(Some(record))
+            }
+            _ => {
+                errors.skipped += 1;
+                errors.errors.extend(row_errors);
+                Ok(None)
+            }
+        }
+    }
+}
+
+//Next record of the file. A row with a wrong number of fields is a row error, not a read error.
+pub fn read_record<R: Read>(
+    reader: &mut Reader<R>,
+    file: &str,
+) -> CSVEntryStorageResult<Option<Result<StringRecord, RowError>>> {
+    let mut record = StringRecord::new();
+    match reader.read_record(&mut record) {
+        Ok(true) => Ok(Some(Ok(record))),
+        Ok(false) => Ok(None),
+        Err(error) => match error.kind() {
+            csv::ErrorKind::UnequalLengths {
+                pos,
+                expected_len,
+                len,
+            } => Ok(Some(Err(RowError {
+                file: file.to_string(),
+                line: pos.as_ref().map(get_line).unwrap_or_default(),
+                column: None,
+                value: String::new(),
+                reason: format!("{} fields instead of {}", len, expected_len),
+            }))),
+            _ => Err(error.into()),
+        },
+    }
+}
+
+//Line of the file where the record starts, the header being the first one.
+fn get_line(position: &Position) -> u64 {
+    position.line()
+}
+
+//Deserialize a record and check its values. The records are read with the expected headers, the
+//errors name the columns with the source headers.
+pub fn read_row<T: DeserializeOwned + CheckedRow>(
+    file: &str,
+    headers: &StringRecord,
+    source_headers: &StringRecord,
+    record: Result<StringRecord, RowError>,
+) -> CSVRow<T> {
+    let record = match record {
+        Ok(record) => record,
+        Err(error) => {
+            return CSVRow {
+                line: error.line,
+                record: None,
+                errors: vec![error],
+            }
+        }
+    };
+    let line = record.position().map(get_line).unwrap_or_default();
+
+    match record.deserialize::<T>(Some(headers)) {
+        Ok(value) => {
+            let errors = value
+                .get_invalid_cells()
+                .into_iter()
+                .map(|cell| RowError {
+                    file: file.to_string(),
+                    line,
+                    column: Some(get_source_header(headers, source_headers, cell.column)),
+                    value: cell.value,
+                    reason: cell.reason.to_string(),
+                })
+                .collect();
+            CSVRow {
+                line,
+                record: Some(value),
+                errors,
+            }
+        }
+        Err(error) => CSVRow {
+            line,
+            record: None,
+            errors: vec![get_deserialize_error(
+                file,
+                line,
+                source_headers,
+                &record,
+                &error,
+            )],
+        },
+    }
+}
+
+//Source header of the column with the expected header, the expected one when it is not found.
+fn get_source_header(
+    headers: &StringRecord,
+    source_headers: &StringRecord,
+    column: &str,
+) -> String {
+    headers
+        .iter()
+        .position(|header| header == column)
+        .and_then(|position| source_headers.get(position))
+        .unwrap_or(column)
+        .to_string()
+}
+
+fn get_deserialize_error(
+    file: &str,
+    line: u64,
+    source_headers: &StringRecord,
+    record: &StringRecord,
+    error: &csv::Error,
+) -> RowError {
+    let (field, reason) = match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => (err.field(), err.kind().to_string()),
+        _ => (None, error.to_string()),
+    };
+    let field = field.map(|field| field as usize);
+    RowError {
+        file: file.to_string(),
+        line,
+        column: field
+            .and_then(|field| source_headers.get(field))
+            .map(str::to_string),
+        value: field
+            .and_then(|field| record.get(field))
+            .unwrap_or_default()
+            .to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::map_headers;
+    use crate::postal_code_csv_index::PostalCodeIrisCodeCSV;
+    use crate::source::LfReader;
+    use std::collections::BTreeMap;
+
+    //Rows of a CSV text, read like the import reads the source files.
+    fn read_rows(text: &str, columns: &[(&str, &str)]) -> Vec<CSVRow<PostalCodeIrisCodeCSV>> {
+        let columns: BTreeMap<String, String> = columns
+            .iter()
+            .map(|(expected, source)| (expected.to_string(), source.to_string()))
+            .collect();
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(b';')
+            .from_reader(LfReader::new(text.as_bytes()));
+        let source_headers = reader.headers().unwrap().clone();
+        let headers = map_headers::<PostalCodeIrisCodeCSV>(&source_headers, &columns);
+        let mut rows = Vec::new();
+        while let Some(record) = read_record(&mut reader, "postal.csv").unwrap() {
+            rows.push(read_row("postal.csv", &headers, &source_headers, record));
+        }
+        rows
+    }
+
+    fn check_rows(
+        rows: Vec<CSVRow<PostalCodeIrisCodeCSV>>,
+        policy: ErrorPolicy,
+        errors: &mut RowErrors,
+    ) -> CSVEntryStorageResult<Vec<String>> {
+        let mut kept = Vec::new();
+        for row in rows {
+            if let Some(record) = row.check(policy, errors)? {
+                kept.push(record.nom_com);
+            }
+        }
+        Ok(kept)
+    }
+
+    const POSTAL_CSV: &str = "INSEE_COM;NOM_COM;Code_postal;Geo Point\n\
+        62041;Arras;62000;50.29,2.78\n\
+        62042;Avion;62210;north\n\
+        62043;Achicourt;62217;50.27,2.75\n";
+
+    #[test]
+    fn invalid_cells_name_the_source_header_and_the_line() {
+        let text = POSTAL_CSV.replace("Geo Point", "Coordonnées");
+        let rows = read_rows(&text, &[("Geo Point", "Coordonnées")]);
+        assert!(rows[0].errors.is_empty());
+        let error = &rows[1].errors[0];
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column.as_deref(), Some("Coordonnées"));
+        assert_eq!(error.value, "north");
+    }
+
+    #[test]
+    fn lines_are_counted_in_crlf_files() {
+        let text = "INSEE_COM;NOM_COM;Code_postal;Geo Point\r\n\
+            62041;Arras;62000;50.29,2.78\r\n\
+            62042;Avion\r\n";
+        let rows = read_rows(text, &[]);
+        assert_eq!(rows[1].line, 3);
+        assert_eq!(rows[1].errors[0].reason, "2 fields instead of 4");
+    }
+
+    #[test]
+    fn fail_stops_at_the_first_invalid_row() {
+        let mut errors = RowErrors::default();
+        let result = check_rows(read_rows(POSTAL_CSV, &[]), ErrorPolicy::Fail, &mut errors);
+        match result {
+            Err(crate::CSVEntryStorageError::Row { source }) => assert_eq!(source.line, 3),
+            _ => panic!("the invalid row is not an error"),
+        }
+    }
+
+    #[test]
+    fn skip_leaves_the_invalid_rows_out() {
+        let mut errors = RowErrors::default();
+        let kept = check_rows(read_rows(POSTAL_CSV, &[]), ErrorPolicy::Skip, &mut errors).unwrap();
+        assert_eq!(kept, vec!["Arras", "Achicourt"]);
+        assert_eq!(errors.skipped, 1);
+        assert_eq!(errors.errors.len(), 1);
+    }
+
+    #[test]
+    fn null_keeps_the_invalid_rows_without_their_invalid_values() {
+        let mut errors = RowErrors::default();
+        let kept = check_rows(read_rows(POSTAL_CSV, &[]), ErrorPolicy::Null, &mut errors).unwrap();
+        assert_eq!(kept, vec!["Arras", "Avion", "Achicourt"]);
+        assert_eq!(errors.skipped, 0);
+        assert_eq!(errors.errors.len(), 1);
+    }
+
+    #[test]
+    fn malformed_rows_are_left_out_by_null() {
+        let text = format!("{}62044;Agny\n", POSTAL_CSV);
+        let mut errors = RowErrors::default();
+        let kept = check_rows(read_rows(&text, &[]), ErrorPolicy::Null, &mut errors).unwrap();
+        assert_eq!(kept, vec!["Arras", "Avion", "Achicourt"]);
+        assert_eq!(errors.skipped, 1);
+        assert_eq!(errors.errors.len(), 2);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::entry_csv::EntryCSV;
+use crate::row::RowErrors;
+use crate::AvgStat;
+use domain::business::aggregates::ScoresSum;
+use domain::core::hierarchy::Hierarchy;
+use std::collections::BTreeMap;
+
+//Averages of the scores by territory, needed to convert the rows to entries.
+pub struct EntryStats {
+    pub national: AvgStat,
+    pub regions: BTreeMap<String, AvgStat>,
+    pub departments: BTreeMap<String, AvgStat>,
+}
+
+//Statistics and territory indexes, accumulated in a single pass over the rows.
+#[derive(Default)]
+pub struct CSVEntryScan {
+    pub rows: usize,
+    pub errors: RowErrors,
+    national: ScoresSum,
+    regions: BTreeMap<String, ScoresSum>,
+    departments: BTreeMap<String, ScoresSum>,
+    insee_coms_with_iris: BTreeMap<String, Vec<String>>,
+    regions_with_iris: BTreeMap<String, Vec<String>>,
+    departments_with_iris: BTreeMap<String, Vec<String>>,
+    hierarchy: Hierarchy,
+}
+
+impl CSVEntryScan {
+    pub fn add(&mut self, csv_entry: &EntryCSV) {
+        let department = csv_entry.get_department();
+        let scores = csv_entry.get_scores();
+        self.rows += 1;
+        self.national.add(&scores);
+        self.regions
+            .entry(csv_entry.nom_reg.to_string())
+            .or_default()
+            .add(&scores);
+        self.departments
+            .entry(department.to_string())
+            .or_default()
+            .add(&scores);
+
+        add_to_index(
+            &mut self.insee_coms_with_iris,
+            &csv_entry.insee_com,
+            csv_entry,
+        );
+        add_to_index(&mut self.regions_with_iris, &csv_entry.nom_reg, csv_entry);
+        add_to_index(&mut self.departments_with_iris, &department, csv_entry);
+        self.hierarchy.add(
+            &csv_entry.nom_reg,
+            &department,
+            &csv_entry.get_epci(),
+            &csv_entry.insee_com,
+            &csv_entry.code_iris,
+        );
+    }
+
+    pub fn get_stats(&self) -> EntryStats {
+        EntryStats {
+            national: AvgStat::new(self.national.get_averages()),
+            regions: get_averages(&self.regions),
+            departments: get_averages(&self.departments),
+        }
+    }
+
+    pub fn get_insee_com_with_iris(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.insee_coms_with_iris
+    }
+
+    pub fn get_regions_with_iris(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.regions_with_iris
+    }
+
+    pub fn get_departements_with_iris(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.departments_with_iris
+    }
+
+    pub fn get_hierarchy(&self) -> &Hierarchy {
+        &self.hierarchy
+    }
+}
+
+//The unassigned items are not indexed.
+fn add_to_index(index: &mut BTreeMap<String, Vec<String>>, key: &str, csv_entry: &EntryCSV) {
+    if !key.is_empty() {
+        index
+            .entry(key.to_string())
+            .or_default()
+            .push(csv_entry.code_iris.to_string());
+    }
+}
+
+fn get_averages(sums: &BTreeMap<String, ScoresSum>) -> BTreeMap<String, AvgStat> {
+    sums.iter()
+        .map(|(territory, sum)| (territory.to_string(), AvgStat::new(sum.get_averages())))
+        .collect()
+}
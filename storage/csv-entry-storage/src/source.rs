@@ -0,0 +1,378 @@
+use crate::row::{read_record, RowError};
+use crate::spreadsheet::{is_spreadsheet, Sheet, SheetRecords};
+use crate::{CSVEntryStorageResult, DEFAULT_DELIMITER};
+use csv::{Position, StringRecord};
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
+use encoding_rs_io::{DecodeReaderBytes, DecodeReaderBytesBuilder};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+
+//Start of the file read to detect its encoding and its delimiter.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+
+//Delimiters looked for in the header line, the first one wins a tie.
+const DELIMITERS: [u8; 4] = [b';', b',', b'\t', b'|'];
+
+//Bytes read at once by LfReader.
+const LF_BUFFER_SIZE: usize = 8 * 1024;
+
+//Source file transcoded to UTF-8, without its BOM and with LF line ends.
+pub type SourceReader = LfReader<DecodeReaderBytes<File, Vec<u8>>>;
+
+//Reader dropping the CR of the CRLF line ends. The csv reader counts the lines of a CRLF file one
+//line early, the lines of the row errors are right once the line ends are LF.
+pub struct LfReader<R> {
+    inner: R,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    eof: bool,
+}
+
+impl<R: Read> LfReader<R> {
+    pub fn new(inner: R) -> Self {
+        LfReader {
+            inner,
+            buffer: vec![0; LF_BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            eof: false,
+        }
+    }
+
+    //Nothing is left to read but a CR, whose next byte is unknown.
+    fn needs_fill(&self) -> bool {
+        !self.eof && matches!(&self.buffer[self.start..self.end], [] | [b'\r'])
+    }
+
+    //Read the next bytes after the ones left.
+    fn fill(&mut self) -> io::Result<()> {
+        self.buffer.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+        let read = self.inner.read(&mut self.buffer[self.end..])?;
+        self.end += read;
+        self.eof = read == 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LfReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.needs_fill() {
+            self.fill()?;
+        }
+
+        let mut length = 0;
+        while length < buf.len() && self.start < self.end {
+            let byte = self.buffer[self.start];
+            if byte == b'\r' {
+                match self.buffer[self.start + 1..self.end].first() {
+                    Some(b'\n') => {
+                        self.start += 1;
+                        continue;
+                    }
+                    None if !self.eof => break,
+                    _ => (),
+                }
+            }
+            buf[length] = byte;
+            length += 1;
+            self.start += 1;
+        }
+        Ok(length)
+    }
+}
+
+//Encoding and delimiter of a source file, detected or given.
+#[derive(Debug, Copy, Clone)]
+pub struct SourceFormat {
+    pub encoding: &'static Encoding,
+    pub bom: bool,
+    pub delimiter: u8,
+}
+
+impl SourceFormat {
+    //The given encoding and delimiter win over the detected ones.
+    //Without a BOM, a file which is not valid UTF-8 is read as Windows-1252 (a superset of Latin-1).
+    pub fn detect(
+        path: &str,
+        encoding: Option<&'static Encoding>,
+        delimiter: Option<u8>,
+    ) -> io::Result<Self> {
+        let mut sample = Vec::new();
+        File::open(path)?
+            .take(SAMPLE_SIZE)
+            .read_to_end(&mut sample)?;
+
+        let bom = Encoding::for_bom(&sample);
+        let encoding = encoding
+            .or_else(|| bom.map(|(encoding, _)| encoding))
+            .unwrap_or_else(|| detect_encoding(&sample));
+        let bom_length = bom.map(|(_, length)| length).unwrap_or_default();
+        let (text, _) = encoding.decode_without_bom_handling(&sample[bom_length..]);
+        Ok(SourceFormat {
+            encoding,
+            bom: bom.is_some(),
+            delimiter: delimiter.unwrap_or_else(|| detect_delimiter(&text)),
+        })
+    }
+
+    pub fn open(&self, path: &str) -> io::Result<SourceReader> {
+        Ok(LfReader::new(
+            DecodeReaderBytesBuilder::new()
+                .encoding(Some(self.encoding))
+                .strip_bom(true)
+                .build(File::open(path)?),
+        ))
+    }
+}
+
+//Source file: a CSV file with its format, or the sheet of a workbook.
+#[derive(Debug, Clone)]
+pub enum Source {
+    Csv(SourceFormat),
+    Sheet(Sheet),
+}
+
+impl Source {
+    //Workbooks are found by their extension, the first sheet is read when none is given.
+    //The sheet is loaded here, the records opened afterwards share its cells.
+    pub fn find(
+        path: &str,
+        encoding: Option<&'static Encoding>,
+        delimiter: Option<u8>,
+        sheet: Option<&str>,
+    ) -> CSVEntryStorageResult<Self> {
+        match is_spreadsheet(path) {
+            true => Ok(Source::Sheet(Sheet::load(path, sheet)?)),
+            false => Ok(Source::Csv(SourceFormat::detect(
+                path, encoding, delimiter,
+            )?)),
+        }
+    }
+
+    pub fn open(&self, path: &str) -> CSVEntryStorageResult<Box<dyn RecordSource>> {
+        match self {
+            Source::Csv(format) => Ok(Box::new(CSVRecords::open(path, format)?)),
+            Source::Sheet(sheet) => Ok(Box::new(SheetRecords::open(sheet))),
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Csv(format) => write!(
+                f,
+                "Encoding {}{}, delimiter {:?}",
+                format.encoding.name(),
+                if format.bom { " with BOM" } else { "" },
+                format.delimiter as char
+            ),
+            Source::Sheet(sheet) => write!(f, "Sheet {:?}", sheet.name),
+        }
+    }
+}
+
+//Rows of a source file as CSV records, with the headers of the file.
+pub trait RecordSource {
+    fn get_headers(&self) -> &StringRecord;
+    //A row with a wrong number of fields is a row error, not a read error.
+    fn next_record(&mut self) -> CSVEntryStorageResult<Option<Result<StringRecord, RowError>>>;
+    //Widths of the code columns by position, see mapping::get_code_widths. The values of a CSV
+    //file are text, they keep their leading zeros.
+    fn set_code_widths(&mut self, _code_widths: Vec<usize>) {}
+}
+
+pub struct CSVRecords {
+    reader: csv::Reader<SourceReader>,
+    headers: StringRecord,
+    path: String,
+    encoding: &'static Encoding,
+}
+
+impl CSVRecords {
+    pub fn open(path: &str, format: &SourceFormat) -> CSVEntryStorageResult<Self> {
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(format.delimiter)
+            .from_reader(format.open(path)?);
+        let headers = reader.headers()?.clone();
+        Ok(CSVRecords {
+            reader,
+            headers,
+            path: path.to_string(),
+            encoding: format.encoding,
+        })
+    }
+
+    //The bytes which are not valid in the encoding are decoded as U+FFFD. The encoding is detected
+    //from the start of the file only, a row further holding them is an error rather than a value
+    //imported with them.
+    fn check_encoding(&self, record: StringRecord) -> Result<StringRecord, RowError> {
+        let field = record
+            .iter()
+            .position(|value| value.contains(char::REPLACEMENT_CHARACTER));
+        match field {
+            None => Ok(record),
+            Some(field) => Err(RowError {
+                file: self.path.to_string(),
+                line: record.position().map(Position::line).unwrap_or_default(),
+                column: self.headers.get(field).map(str::to_string),
+                value: record[field].to_string(),
+                reason: format!(
+                    "not valid {}, give the encoding of the file with --encoding",
+                    self.encoding.name()
+                ),
+            }),
+        }
+    }
+}
+
+impl RecordSource for CSVRecords {
+    fn get_headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn next_record(&mut self) -> CSVEntryStorageResult<Option<Result<StringRecord, RowError>>> {
+        Ok(read_record(&mut self.reader, &self.path)?
+            .map(|record| record.and_then(|record| self.check_encoding(record))))
+    }
+}
+
+//Encoding name ("utf-8", "windows-1252", "latin1", ...), for the options.
+pub fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.as_bytes()).ok_or(format!("'{}' is not a known encoding", label))
+}
+
+//The sample can end in the middle of a character.
+fn detect_encoding(sample: &[u8]) -> &'static Encoding {
+    match std::str::from_utf8(sample) {
+        Ok(_) => UTF_8,
+        Err(error) if error.error_len().is_none() => UTF_8,
+        Err(_) => WINDOWS_1252,
+    }
+}
+
+//Delimiter found the most in the header line, out of the quoted values.
+fn detect_delimiter(text: &str) -> u8 {
+    let header = text.lines().next().unwrap_or_default();
+    let mut counts = [0; DELIMITERS.len()];
+    let mut quoted = false;
+    for byte in header.bytes() {
+        match byte {
+            b'"' => quoted = !quoted,
+            _ if quoted => (),
+            _ => {
+                if let Some(position) = DELIMITERS.iter().position(|delimiter| *delimiter == byte) {
+                    counts[position] += 1;
+                }
+            }
+        }
+    }
+
+    let (position, count) = counts
+        .iter()
+        .enumerate()
+        .rev()
+        .max_by_key(|(_, count)| **count)
+        .unwrap_or((0, &0));
+    match count {
+        0 => DEFAULT_DELIMITER,
+        _ => DELIMITERS[position],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    //Reader returning a few bytes at a time, the CR and LF of a line end can be read apart.
+    struct ChunkReader<'a> {
+        bytes: &'a [u8],
+        chunk: usize,
+    }
+
+    impl<'a> Read for ChunkReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = self.chunk.min(buf.len()).min(self.bytes.len());
+            buf[..length].copy_from_slice(&self.bytes[..length]);
+            self.bytes = &self.bytes[length..];
+            Ok(length)
+        }
+    }
+
+    fn read_lf(bytes: &[u8], chunk: usize) -> String {
+        let mut text = String::new();
+        LfReader::new(ChunkReader { bytes, chunk })
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn lf_reader_drops_the_cr_of_the_line_ends() {
+        let bytes = b"a;b\r\n1;\"x\r\ny\"\r\n\r\n2;z\rw\r";
+        for chunk in 1..bytes.len() {
+            assert_eq!(read_lf(bytes, chunk), "a;b\n1;\"x\ny\"\n\n2;z\rw\r");
+        }
+    }
+
+    #[test]
+    fn utf8_is_detected_even_cut_in_a_character() {
+        assert_eq!(detect_encoding("Nom;Libellé\n".as_bytes()), UTF_8);
+        let sample = "Libellé".as_bytes();
+        assert_eq!(detect_encoding(&sample[..sample.len() - 1]), UTF_8);
+    }
+
+    #[test]
+    fn invalid_utf8_is_read_as_windows_1252() {
+        assert_eq!(detect_encoding(b"Nom;Libell\xe9\n"), WINDOWS_1252);
+    }
+
+    #[test]
+    fn delimiter_is_the_most_found_in_the_header_line() {
+        assert_eq!(detect_delimiter("a;b;c\n1,2;3"), b';');
+        assert_eq!(detect_delimiter("a,b,c\n1;2;3"), b',');
+        assert_eq!(detect_delimiter("a\tb|c\tc"), b'\t');
+        //Out of the quoted values.
+        assert_eq!(detect_delimiter("\"a,b,c\";d;e"), b';');
+        //The first one wins a tie.
+        assert_eq!(detect_delimiter("a,b;c"), b';');
+        assert_eq!(detect_delimiter("abc"), DEFAULT_DELIMITER);
+    }
+
+    #[test]
+    fn values_not_valid_in_the_detected_encoding_are_row_errors() {
+        //The Latin-1 bytes are after the sample, the file is detected as UTF-8.
+        let mut bytes = b"Nom;Commune\n".to_vec();
+        let row = b"Arras;Arras\n";
+        while bytes.len() <= SAMPLE_SIZE as usize {
+            bytes.extend_from_slice(row);
+        }
+        let line = bytes.iter().filter(|byte| **byte == b'\n').count() as u64 + 1;
+        bytes.extend_from_slice(b"Sainte;Sainte-Genevi\xe8ve\n");
+        let path =
+            std::env::temp_dir().join(format!("csv-entry-storage-{}.csv", std::process::id()));
+        fs::write(&path, &bytes).unwrap();
+
+        let path = path.to_str().unwrap();
+        let format = SourceFormat::detect(path, None, None).unwrap();
+        assert_eq!(format.encoding, UTF_8);
+        let mut records = CSVRecords::open(path, &format).unwrap();
+        let mut error = None;
+        while let Some(record) = records.next_record().unwrap() {
+            if let Err(row_error) = record {
+                error = Some(row_error);
+            }
+        }
+        fs::remove_file(path).unwrap();
+
+        let error = error.unwrap();
+        assert_eq!(error.line, line);
+        assert_eq!(error.column.as_deref(), Some("Commune"));
+        assert!(error.reason.contains("--encoding"));
+    }
+}
@@ -0,0 +1,346 @@
+use crate::row::RowError;
+use crate::source::RecordSource;
+use crate::CSVEntryStorageResult;
+use calamine::{open_workbook_auto, Data, Range, Reader};
+use csv::{Position, StringRecord};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+//Extensions of the workbooks read instead of a CSV file.
+const SPREADSHEET_EXTENSIONS: [&str; 5] = ["xlsx", "xlsm", "xlsb", "xls", "ods"];
+
+pub fn is_spreadsheet(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| SPREADSHEET_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+        .unwrap_or_default()
+}
+
+//Sheet of a workbook, loaded once when the source is found. The clones share the cells.
+#[derive(Clone)]
+pub struct Sheet {
+    pub name: String,
+    range: Arc<Range<Data>>,
+}
+
+impl Sheet {
+    //The given sheet, or the first one of the workbook.
+    pub fn load(path: &str, sheet: Option<&str>) -> CSVEntryStorageResult<Self> {
+        let mut workbook = open_workbook_auto(path)?;
+        let name = match sheet {
+            Some(sheet) => sheet.to_string(),
+            None => workbook
+                .sheet_names()
+                .first()
+                .cloned()
+                .ok_or(calamine::Error::Msg("the workbook has no sheet"))?,
+        };
+        let range = workbook.worksheet_range(&name)?;
+        Ok(Sheet {
+            name,
+            range: Arc::new(range),
+        })
+    }
+}
+
+//The cells are left out.
+impl fmt::Debug for Sheet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sheet").field("name", &self.name).finish()
+    }
+}
+
+//Rows of a sheet as CSV records, the first row holding the headers. Unlike a CSV file, the sheet
+//is loaded in memory. The empty rows are left out, like the empty lines of a CSV file.
+pub struct SheetRecords {
+    range: Arc<Range<Data>>,
+    headers: StringRecord,
+    //Row of the range read next.
+    row: usize,
+    //Row of the sheet where the range starts.
+    start: u64,
+    code_widths: Vec<usize>,
+}
+
+impl SheetRecords {
+    pub fn open(sheet: &Sheet) -> Self {
+        let range = sheet.range.clone();
+        let start = range.start().map(|(row, _)| row as u64).unwrap_or_default();
+        let mut records = SheetRecords {
+            range,
+            headers: StringRecord::new(),
+            row: 0,
+            start,
+            code_widths: Vec::new(),
+        };
+        records.headers = records.next_row().unwrap_or_default();
+        records
+    }
+
+    fn next_row(&mut self) -> Option<StringRecord> {
+        let (height, width) = self.range.get_size();
+        while self.row < height {
+            let row = self.row;
+            self.row += 1;
+            let record: StringRecord = (0..width)
+                .map(|column| match self.range.get((row, column)) {
+                    Some(cell) => format_cell(cell, self.get_code_width(column)),
+                    None => String::new(),
+                })
+                .collect();
+            if record.iter().any(|value| !value.is_empty()) {
+                return Some(self.with_position(record, row));
+            }
+        }
+        None
+    }
+
+    fn get_code_width(&self, column: usize) -> usize {
+        self.code_widths.get(column).copied().unwrap_or_default()
+    }
+
+    //The line of the errors is the row number shown by the spreadsheet applications.
+    fn with_position(&self, mut record: StringRecord, row: usize) -> StringRecord {
+        let mut position = Position::new();
+        position.set_record(self.start + row as u64);
+        position.set_line(self.start + row as u64 + 1);
+        record.set_position(Some(position));
+        record
+    }
+}
+
+impl RecordSource for SheetRecords {
+    fn get_headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn next_record(&mut self) -> CSVEntryStorageResult<Option<Result<StringRecord, RowError>>> {
+        Ok(self.next_row().map(Ok))
+    }
+
+    fn set_code_widths(&mut self, code_widths: Vec<usize>) {
+        self.code_widths = code_widths;
+    }
+}
+
+//A code held as a number loses its leading zeros, it is zero-padded to the width of its column.
+fn format_cell(cell: &Data, code_width: usize) -> String {
+    match cell {
+        Data::Int(code) if code_width > 0 && *code >= 0 => {
+            format!("{:0width$}", code, width = code_width)
+        }
+        Data::Float(code) if code_width > 0 && *code >= 0.0 && code.fract() == 0.0 => {
+            format!("{:0width$}", *code as u64, width = code_width)
+        }
+        _ => cell.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PostalCodeCsvStorage;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    const SPREADSHEET_NS: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+    const RELATIONSHIPS_NS: &str = "http://schemas.openxmlformats.org/package/2006/relationships";
+    const DOCUMENT_NS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+    //Cell of a test sheet, a number or a text.
+    enum Cell {
+        Number(&'static str),
+        Text(&'static str),
+    }
+
+    fn column_name(column: usize) -> char {
+        (b'A' + column as u8) as char
+    }
+
+    fn sheet_xml(rows: &[Vec<Cell>]) -> String {
+        let mut xml = format!("<worksheet xmlns=\"{}\"><sheetData>", SPREADSHEET_NS);
+        for (row, cells) in rows.iter().enumerate() {
+            xml.push_str(&format!("<row r=\"{}\">", row + 1));
+            for (column, cell) in cells.iter().enumerate() {
+                let reference = format!("{}{}", column_name(column), row + 1);
+                xml.push_str(&match cell {
+                    Cell::Number(value) => format!("<c r=\"{}\"><v>{}</v></c>", reference, value),
+                    Cell::Text(value) => format!(
+                        "<c r=\"{}\" t=\"inlineStr\"><is><t>{}</t></is></c>",
+                        reference, value
+                    ),
+                });
+            }
+            xml.push_str("</row>");
+        }
+        xml.push_str("</sheetData></worksheet>");
+        xml
+    }
+
+    //XLSX workbook holding the given sheets, in their order.
+    fn write_workbook(name: &str, sheets: &[(&str, Vec<Vec<Cell>>)]) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "csv-entry-storage-{}-{}.xlsx",
+            name,
+            std::process::id()
+        ));
+        let mut sheet_types = String::new();
+        let mut sheet_list = String::new();
+        let mut sheet_relationships = String::new();
+        for (index, (sheet, _)) in numbered(sheets) {
+            sheet_types.push_str(&format!(
+                "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml\"/>",
+                index
+            ));
+            sheet_list.push_str(&format!(
+                "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+                sheet, index, index
+            ));
+            sheet_relationships.push_str(&format!(
+                "<Relationship Id=\"rId{}\" Type=\"{}/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+                index, DOCUMENT_NS, index
+            ));
+        }
+
+        let mut files = vec![
+            (
+                "[Content_Types].xml".to_string(),
+                format!(
+                    "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\"><Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/><Default Extension=\"xml\" ContentType=\"application/xml\"/><Override PartName=\"/xl/workbook.xml\" ContentType=\"application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml\"/>{}</Types>",
+                    sheet_types
+                ),
+            ),
+            (
+                "_rels/.rels".to_string(),
+                format!(
+                    "<Relationships xmlns=\"{}\"><Relationship Id=\"rId1\" Type=\"{}/officeDocument\" Target=\"xl/workbook.xml\"/></Relationships>",
+                    RELATIONSHIPS_NS, DOCUMENT_NS
+                ),
+            ),
+            (
+                "xl/workbook.xml".to_string(),
+                format!(
+                    "<workbook xmlns=\"{}\" xmlns:r=\"{}\"><sheets>{}</sheets></workbook>",
+                    SPREADSHEET_NS, DOCUMENT_NS, sheet_list
+                ),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels".to_string(),
+                format!(
+                    "<Relationships xmlns=\"{}\">{}</Relationships>",
+                    RELATIONSHIPS_NS, sheet_relationships
+                ),
+            ),
+        ];
+        for (index, (_, rows)) in numbered(sheets) {
+            files.push((format!("xl/worksheets/sheet{}.xml", index), sheet_xml(rows)));
+        }
+
+        let mut workbook = ZipWriter::new(File::create(&path).unwrap());
+        for (name, content) in files {
+            workbook
+                .start_file(name, SimpleFileOptions::default())
+                .unwrap();
+            workbook.write_all(content.as_bytes()).unwrap();
+        }
+        workbook.finish().unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    //Sheets with their number in the workbook, from 1.
+    fn numbered<'a>(
+        sheets: &'a [(&'a str, Vec<Vec<Cell>>)],
+    ) -> impl Iterator<Item = (usize, &'a (&'a str, Vec<Vec<Cell>>))> {
+        sheets
+            .iter()
+            .enumerate()
+            .map(|(index, sheet)| (index + 1, sheet))
+    }
+
+    fn postal_code_rows(code: Cell, postal_code: Cell) -> Vec<Vec<Cell>> {
+        vec![
+            vec![
+                Cell::Text("INSEE_COM"),
+                Cell::Text("NOM_COM"),
+                Cell::Text("Code_postal"),
+                Cell::Text("Geo Point"),
+            ],
+            vec![
+                code,
+                Cell::Text("Ambérieu-en-Bugey"),
+                postal_code,
+                Cell::Text("45.96,5.37"),
+            ],
+        ]
+    }
+
+    fn load_postal_codes(path: &str, sheet: Option<&str>) -> Vec<(String, String)> {
+        let mut storage =
+            PostalCodeCsvStorage::new(path.to_string()).with_sheet(sheet.map(str::to_string));
+        storage.load().unwrap();
+        storage
+            .postal_codes
+            .unwrap()
+            .into_iter()
+            .map(|postal_code| (postal_code.iris_code, postal_code.postal_code))
+            .collect()
+    }
+
+    #[test]
+    fn numeric_codes_are_zero_padded_to_the_width_of_their_column() {
+        let path = write_workbook(
+            "numeric-codes",
+            &[(
+                "Codes",
+                postal_code_rows(Cell::Number("1004"), Cell::Number("1500.0")),
+            )],
+        );
+        let postal_codes = load_postal_codes(&path, None);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            postal_codes,
+            vec![("01004".to_string(), "01500".to_string())]
+        );
+    }
+
+    #[test]
+    fn only_the_whole_numbers_of_the_code_columns_are_padded() {
+        assert_eq!(format_cell(&Data::Int(1), 2), "01");
+        assert_eq!(format_cell(&Data::Float(62041.0), 9), "000062041");
+        //Out of a code column, a decimal or negative number, a text code.
+        assert_eq!(format_cell(&Data::Int(1), 0), "1");
+        assert_eq!(format_cell(&Data::Float(1.5), 5), "1.5");
+        assert_eq!(format_cell(&Data::Int(-1), 5), "-1");
+        assert_eq!(format_cell(&Data::String("1".to_string()), 5), "1");
+    }
+
+    #[test]
+    fn the_given_sheet_is_read_or_the_first_one() {
+        let path = write_workbook(
+            "sheets",
+            &[
+                (
+                    "Ain",
+                    postal_code_rows(Cell::Text("01004"), Cell::Text("01500")),
+                ),
+                (
+                    "Aisne",
+                    postal_code_rows(Cell::Text("02001"), Cell::Text("02300")),
+                ),
+            ],
+        );
+        let first = load_postal_codes(&path, None);
+        let given = load_postal_codes(&path, Some("Aisne"));
+        let unknown = Sheet::load(&path, Some("Allier"));
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, vec![("01004".to_string(), "01500".to_string())]);
+        assert_eq!(given, vec![("02001".to_string(), "02300".to_string())]);
+        assert!(unknown.is_err());
+    }
+}
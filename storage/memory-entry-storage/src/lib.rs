@@ -0,0 +1,219 @@
+use domain::core::entry::Entry;
+use domain::storage::error::*;
+use domain::storage::traits::{EntryIterator, EntryStorageTrait};
+use serde_cbor::de::from_reader;
+use serde_cbor::ser::to_writer;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+
+//Start and end of a CBOR map of unknown length.
+const CBOR_MAP_START: u8 = 0xbf;
+const CBOR_BREAK: u8 = 0xff;
+
+//Read-only entries storage, fully loaded in memory from a snapshot file.
+pub struct MemoryEntryStorage {
+    pub entries: HashMap<String, Entry>,
+    first_iris_code: Option<String>,
+}
+
+impl MemoryEntryStorage {
+    pub fn new(path: String) -> StorageResult<Self> {
+        let file = File::open(path)?;
+        let reader = BufReader::new(file);
+        let snapshot: BTreeMap<String, Entry> = match from_reader(reader) {
+            Ok(snapshot) => snapshot,
+            Err(error) => return Err(StorageError::Serialization(error.to_string())),
+        };
+
+        Ok(MemoryEntryStorage {
+            first_iris_code: snapshot.keys().next().cloned(),
+            entries: snapshot.into_iter().collect(),
+        })
+    }
+
+    //Write the snapshot file loaded by `MemoryEntryStorage::new`.
+    pub fn write_snapshot(path: String, entries: &BTreeMap<String, Entry>) -> StorageResult<()> {
+        let file = File::create(path)?;
+        let writer = BufWriter::new(file);
+        match to_writer(writer, entries) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Serialization(error.to_string())),
+        }
+    }
+}
+
+//Write the snapshot entry by entry, without keeping the entries in memory.
+pub struct SnapshotWriter {
+    writer: BufWriter<File>,
+}
+
+impl SnapshotWriter {
+    pub fn create(path: String) -> StorageResult<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&[CBOR_MAP_START])?;
+        Ok(SnapshotWriter { writer })
+    }
+
+    pub fn write(&mut self, iris_code: &str, entry: &Entry) -> StorageResult<()> {
+        to_writer(&mut self.writer, &iris_code)
+            .and_then(|_| to_writer(&mut self.writer, entry))
+            .map_err(|error| StorageError::Serialization(error.to_string()))
+    }
+
+    pub fn finish(mut self) -> StorageResult<()> {
+        self.writer.write_all(&[CBOR_BREAK])?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl EntryStorageTrait for MemoryEntryStorage {
+    fn get_all(&self) -> StorageResult<Vec<Entry>> {
+        Ok(self.entries.values().cloned().collect())
+    }
+
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>> {
+        Ok(Box::new(self.entries.values().cloned().map(Ok)))
+    }
+
+    fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        Ok(self.entries.get(&iris_code).cloned())
+    }
+
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>> {
+        Ok(iris_codes
+            .iter()
+            .filter_map(|iris_code| {
+                self.entries
+                    .get(iris_code)
+                    .map(|entry| (iris_code.to_string(), entry.clone()))
+            })
+            .collect())
+    }
+
+    fn get_national_entry(&self) -> StorageResult<Option<Entry>> {
+        let first_entry = match &self.first_iris_code {
+            Some(iris_code) => self.entries.get(iris_code),
+            None => None,
+        };
+        Ok(first_entry.map(|entry| entry.to_national_entry()))
+    }
+
+    fn get_region_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        Ok(self
+            .entries
+            .get(&iris_code)
+            .map(|entry| entry.to_regional_entry()))
+    }
+
+    fn get_department_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        Ok(self
+            .entries
+            .get(&iris_code)
+            .map(|entry| entry.to_departmental_entry()))
+    }
+
+    fn create(&self, _iris_code: String, _entry: Entry) -> StorageResult<()> {
+        //The snapshot is read-only, entries are written by the import.
+        Err(StorageError::NotImplemented)
+    }
+
+    fn update(&self, _iris_code: String, _entry: Entry) -> StorageResult<Option<Entry>> {
+        Err(StorageError::NotImplemented)
+    }
+
+    fn update_entries(&self, _entries: &[Entry]) -> StorageResult<()> {
+        Err(StorageError::NotImplemented)
+    }
+
+    fn delete(&self, _iris_code: String) -> StorageResult<Option<Entry>> {
+        Err(StorageError::NotImplemented)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use domain::core::entry::{InformationAccess, Territory};
+    use std::fs;
+
+    fn get_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!(
+                "memory-entries-{}-{}.cbor",
+                name,
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn get_entry(iris_code: &str, global: f64) -> Entry {
+        Entry::new(
+            Some(global),
+            Some(100.0),
+            None,
+            Some(98.0),
+            Some(iris_code.to_string()),
+            Some("Arras".to_string()),
+            Some(
+                InformationAccess::new(Some(1.0), None, None, None, None, None, None, None)
+                    .with_information_score(Some(2.0)),
+            ),
+            None,
+            None,
+            None,
+        )
+        .with_territory(Territory {
+            region: "Hauts-de-France".to_string(),
+            department: "62 - Pas-de-Calais".to_string(),
+            epci: "200033579 - CU d'Arras".to_string(),
+            insee_com: "62041".to_string(),
+        })
+    }
+
+    fn get_entries() -> BTreeMap<String, Entry> {
+        vec![get_entry("620410101", 95.5), get_entry("620410102", 110.0)]
+            .into_iter()
+            .map(|entry| (entry.iris_code.clone().unwrap(), entry))
+            .collect()
+    }
+
+    fn assert_loaded(storage: &MemoryEntryStorage) {
+        assert_eq!(storage.entries.len(), 2);
+        let entry = storage.get_entry("620410102".to_string()).unwrap().unwrap();
+        assert_eq!(entry.global, Some(110.0));
+        assert_eq!(entry.iris_code_designation.as_deref(), Some("Arras"));
+        let information_access = entry.information_access.unwrap();
+        assert_eq!(information_access.global, Some(1.0));
+        assert_eq!(information_access.information_score, Some(2.0));
+        assert_eq!(entry.territory.unwrap().insee_com, "62041");
+        let national = storage.get_national_entry().unwrap().unwrap();
+        assert_eq!(national.global, Some(98.0));
+    }
+
+    #[test]
+    fn snapshots_are_loaded_back() {
+        let path = get_path("snapshot");
+        MemoryEntryStorage::write_snapshot(path.clone(), &get_entries()).unwrap();
+        let storage = MemoryEntryStorage::new(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_loaded(&storage);
+    }
+
+    #[test]
+    fn snapshots_written_entry_by_entry_are_loaded_back() {
+        let path = get_path("writer");
+        let mut writer = SnapshotWriter::create(path.clone()).unwrap();
+        for (iris_code, entry) in get_entries().iter() {
+            writer.write(iris_code, entry).unwrap();
+        }
+        writer.finish().unwrap();
+        let storage = MemoryEntryStorage::new(path.clone()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_loaded(&storage);
+    }
+}
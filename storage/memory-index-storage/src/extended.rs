@@ -1,22 +1,133 @@
+use crate::snapshot;
 use domain::core::entry::Iris;
 use domain::storage::error::*;
 use domain::storage::traits::IndexStoragePostalTrait;
+use fst::automaton::{Automaton, Levenshtein, Str};
+use fst::map::StreamBuilder;
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use memmap2::Mmap;
+use regex_automata::dense;
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufReader;
-use std::ops::Bound::Included;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard};
 
+//Bytes of the FST, memory mapped from the import output or built at load time.
+pub enum FstData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl AsRef<[u8]> for FstData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            FstData::Mapped(mmap) => mmap.as_ref(),
+            FstData::Owned(bytes) => bytes.as_ref(),
+        }
+    }
+}
+
+//FST mapping each key to the position of its Iris in `values`.
+struct PostalIndex {
+    map: Map<FstData>,
+    values: Vec<Iris>,
+}
+
+//Commune names index, kept in a FST.
 pub struct MemoryIndexStoragePostal {
-    pub index: BTreeMap<String, Iris>,
+    path: String,
+    index: RwLock<PostalIndex>,
 }
 
 impl MemoryIndexStoragePostal {
+    //Load the FST written by the import when there is one, the snapshot or the JSON file otherwise.
     pub fn new(path: String) -> StorageResult<Self> {
-        let file = File::open(path)?;
+        let fst_path = MemoryIndexStoragePostal::get_fst_path(&path);
+        let values_path = MemoryIndexStoragePostal::get_values_path(&path);
+        if Path::new(&fst_path).exists() {
+            if let Some(values) = snapshot::load_snapshot::<Vec<Iris>>(&values_path) {
+                let file = File::open(&fst_path)?;
+                let mmap = unsafe { Mmap::map(&file)? };
+                let map = to_storage_result(Map::new(FstData::Mapped(mmap)))?;
+                //Both files are written by the import, a difference means one of them is stale.
+                if map.len() == values.len() {
+                    return Ok(MemoryIndexStoragePostal {
+                        path,
+                        index: RwLock::new(PostalIndex { map, values }),
+                    });
+                }
+                log::warn!(
+                    "{} has {} keys but {} has {} values, falling back to the index file",
+                    fst_path,
+                    map.len(),
+                    values_path,
+                    values.len()
+                );
+            }
+        }
+
+        if let Some(index) = snapshot::load_snapshot(&snapshot::get_snapshot_path(&path)) {
+            return MemoryIndexStoragePostal::from_index(path, index);
+        }
+
+        let file = File::open(&path)?;
         let reader = BufReader::new(file);
         let index: BTreeMap<String, Iris> = serde_json::from_reader(reader)?;
 
-        Ok(MemoryIndexStoragePostal { index: index })
+        MemoryIndexStoragePostal::from_index(path, index)
+    }
+
+    pub fn from_index(path: String, index: BTreeMap<String, Iris>) -> StorageResult<Self> {
+        Ok(MemoryIndexStoragePostal {
+            path,
+            index: RwLock::new(MemoryIndexStoragePostal::build_index(index)?),
+        })
+    }
+
+    fn build_index(index: BTreeMap<String, Iris>) -> StorageResult<PostalIndex> {
+        let map = to_storage_result(Map::from_iter(
+            index
+                .keys()
+                .enumerate()
+                .map(|(position, key)| (key, position as u64)),
+        ))?;
+
+        Ok(PostalIndex {
+            map: to_storage_result(map.map_data(FstData::Owned))?,
+            values: index.into_values().collect(),
+        })
+    }
+
+    fn read_index(&self) -> RwLockReadGuard<'_, PostalIndex> {
+        self.index.read().unwrap()
+    }
+
+    //Write the FST and its values next to the JSON index, to be loaded by `new`.
+    pub fn write_fst(path: String, index: &BTreeMap<String, Iris>) -> StorageResult<()> {
+        let writer = BufWriter::new(File::create(MemoryIndexStoragePostal::get_fst_path(&path))?);
+        let mut builder = to_storage_result(MapBuilder::new(writer))?;
+        for (position, key) in index.keys().enumerate() {
+            to_storage_result(builder.insert(key, position as u64))?;
+        }
+        to_storage_result(builder.finish())?;
+
+        let values: Vec<&Iris> = index.values().collect();
+        snapshot::write_snapshot(&MemoryIndexStoragePostal::get_values_path(&path), &values)
+    }
+
+    fn get_fst_path(path: &str) -> String {
+        Path::new(path)
+            .with_extension("fst")
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn get_values_path(path: &str) -> String {
+        Path::new(path)
+            .with_extension("values.bin")
+            .to_string_lossy()
+            .to_string()
     }
 }
 
@@ -26,47 +137,192 @@ impl IndexStoragePostalTrait for MemoryIndexStoragePostal {
         query: String,
         start_with: Option<String>,
     ) -> StorageResult<Vec<String>> {
-        let mut results = Vec::new();
-
-        let index = match start_with {
+        let index = self.read_index();
+        let keys = match start_with {
             Some(value) => {
-                let start: &String = &value;
-                let end: &String = &format!("{}{}", value.to_string(), "z");
-
-                let bound = (Included(start), Included(end));
-                self.index.range::<String, _>(bound)
+                let end = format!("{}{}", value, "z");
+                collect_keys(index.map.range().ge(value).le(end))
             }
-            None => self.index.range("0".to_string()..),
+            None => collect_keys(index.map.range().ge("0")),
         };
 
         let query = query.to_uppercase();
-        for (key, _) in index {
-            if key.contains(&query) {
-                results.push(key.to_string());
-            }
-        }
-        Ok(results)
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.contains(&query))
+            .collect())
     }
 
     fn get_index(&self, value: String) -> StorageResult<Option<Iris>> {
-        match self.index.get(&value) {
-            Some(results) => Ok(Some(results.clone())),
+        let index = self.read_index();
+        match index.map.get(&value) {
+            Some(position) => Ok(index.values.get(position as usize).cloned()),
             None => Ok(None),
         }
     }
 
     fn get_all_values(&self) -> StorageResult<Vec<Iris>> {
-        let mut result: Vec<Iris> = Vec::new();
-        for index in self.index.iter() {
-            result.push(index.1.clone())
-        }
-        Ok(result)
+        Ok(self.read_index().values.clone())
     }
 
     fn get_all_keys(&self) -> StorageResult<Vec<String>> {
-        let keys: Vec<String> = self.index.keys().cloned().collect();
+        let index = self.read_index();
+        let mut keys = Vec::with_capacity(index.map.len());
+        let mut stream = index.map.keys();
+        while let Some(key) = stream.next() {
+            keys.push(String::from_utf8_lossy(key).to_string());
+        }
+        Ok(keys)
+    }
+
+    fn find_keys(&self, code: String) -> StorageResult<Vec<String>> {
+        let index = self.read_index();
+        let mut keys = Vec::new();
+        let mut stream = index.map.stream();
+        while let Some((key, position)) = stream.next() {
+            let iris_code = index
+                .values
+                .get(position as usize)
+                .and_then(|iris| iris.code.as_ref());
+            if iris_code == Some(&code) {
+                keys.push(String::from_utf8_lossy(key).to_string());
+            }
+        }
         Ok(keys)
     }
+
+    //Rebuild the FST without the key, then write the JSON file and the FST again.
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        let mut index = self.index.write().unwrap();
+        let mut entries: BTreeMap<String, Iris> = BTreeMap::new();
+        {
+            let mut stream = index.map.stream();
+            while let Some((stored_key, position)) = stream.next() {
+                if let Some(iris) = index.values.get(position as usize) {
+                    entries.insert(
+                        String::from_utf8_lossy(stored_key).to_string(),
+                        iris.clone(),
+                    );
+                }
+            }
+        }
+        if entries.remove(&key).is_none() {
+            return Ok(());
+        }
+
+        //Release the memory map before writing the FST file again.
+        *index = MemoryIndexStoragePostal::build_index(entries.clone())?;
+        let writer = BufWriter::new(File::create(&self.path)?);
+        serde_json::to_writer(writer, &entries)?;
+        MemoryIndexStoragePostal::write_fst(self.path.to_string(), &entries)
+    }
+
+    fn search_prefix(&self, prefix: String) -> StorageResult<Vec<String>> {
+        Ok(collect_keys(
+            self.read_index()
+                .map
+                .search(Str::new(&prefix).starts_with()),
+        ))
+    }
+
+    fn search_range(&self, start: String, end: String) -> StorageResult<Vec<String>> {
+        Ok(collect_keys(
+            self.read_index().map.range().ge(start).lt(end),
+        ))
+    }
+
+    fn search_regex(&self, pattern: String) -> StorageResult<Vec<String>> {
+        match dense::Builder::new().anchored(true).build(&pattern) {
+            Ok(automaton) => Ok(collect_keys(self.read_index().map.search(&automaton))),
+            Err(error) => Err(StorageError::InvalidQuery(error.to_string())),
+        }
+    }
+
+    fn search_fuzzy(&self, query: String, distance: u32) -> StorageResult<Vec<String>> {
+        match Levenshtein::new(&query.to_uppercase(), distance) {
+            Ok(automaton) => Ok(collect_keys(self.read_index().map.search(automaton))),
+            Err(error) => Err(StorageError::InvalidQuery(error.to_string())),
+        }
+    }
+}
+
+fn collect_keys<A: Automaton>(builder: StreamBuilder<'_, A>) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut stream = builder.into_stream();
+    while let Some((key, _)) = stream.next() {
+        keys.push(String::from_utf8_lossy(key).to_string());
+    }
+    keys
 }
 
-impl MemoryIndexStoragePostal {}
+//Map the FST errors on the storage errors.
+fn to_storage_result<T>(result: fst::Result<T>) -> StorageResult<T> {
+    result.map_err(|error| StorageError::Serialization(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn get_index() -> BTreeMap<String, Iris> {
+        ["PARIS", "PARIS 01", "PARTHENAY", "PAU", "PERPIGNAN"]
+            .iter()
+            .map(|name| (name.to_string(), Iris::new(Some(name.to_string()), None)))
+            .collect()
+    }
+
+    #[test]
+    fn searches_commune_names() {
+        let storage = MemoryIndexStoragePostal::from_index("".to_string(), get_index()).unwrap();
+        let search = |result: StorageResult<Vec<String>>| result.unwrap().join(",");
+
+        assert_eq!(
+            search(storage.search_prefix("PAR".to_string())),
+            "PARIS,PARIS 01,PARTHENAY"
+        );
+        assert_eq!(
+            search(storage.search_range("PARIS 01".to_string(), "PAU".to_string())),
+            "PARIS 01,PARTHENAY"
+        );
+        assert_eq!(
+            search(storage.search_regex("P.R.*".to_string())),
+            "PARIS,PARIS 01,PARTHENAY,PERPIGNAN"
+        );
+        assert_eq!(search(storage.search_fuzzy("pari".to_string(), 1)), "PARIS");
+        assert_eq!(
+            search(storage.search_on_key("s".to_string(), Some("PA".to_string()))),
+            "PARIS,PARIS 01"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_index_file_when_the_fst_is_stale() {
+        let directory = std::env::temp_dir().join(format!("postal-fst-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory
+            .join("idx_postal.json")
+            .to_string_lossy()
+            .to_string();
+
+        let mut index = get_index();
+        MemoryIndexStoragePostal::write_fst(path.to_string(), &index).unwrap();
+        index.remove("PAU");
+        let values: Vec<&Iris> = index.values().collect();
+        snapshot::write_snapshot(&MemoryIndexStoragePostal::get_values_path(&path), &values)
+            .unwrap();
+        serde_json::to_writer(File::create(&path).unwrap(), &index).unwrap();
+
+        let storage = MemoryIndexStoragePostal::new(path).unwrap();
+        assert_eq!(storage.get_all_keys().unwrap().len(), 4);
+        assert_eq!(
+            storage
+                .get_index("PERPIGNAN".to_string())
+                .unwrap()
+                .unwrap()
+                .code,
+            Some("PERPIGNAN".to_string())
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
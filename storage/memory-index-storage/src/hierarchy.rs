@@ -0,0 +1,79 @@
+use crate::snapshot;
+use domain::core::hierarchy::Hierarchy;
+use domain::storage::error::*;
+use domain::storage::traits::HierarchyStorageTrait;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+//Hierarchy of `idx_hierarchy.json` and its binary snapshot, read again on each access: it is only
+//used by the deletions.
+pub struct MemoryHierarchyStorage {
+    path: String,
+}
+
+impl MemoryHierarchyStorage {
+    pub fn new(path: String) -> Self {
+        MemoryHierarchyStorage { path }
+    }
+}
+
+impl HierarchyStorageTrait for MemoryHierarchyStorage {
+    fn get_hierarchy(&self) -> StorageResult<Option<Hierarchy>> {
+        if let Some(regions) = snapshot::load_snapshot(&snapshot::get_snapshot_path(&self.path)) {
+            return Ok(Some(Hierarchy::from_regions(regions)));
+        }
+        if !Path::new(&self.path).exists() {
+            return Ok(None);
+        }
+
+        let reader = BufReader::new(File::open(&self.path)?);
+        Ok(Some(Hierarchy::from_regions(serde_json::from_reader(
+            reader,
+        )?)))
+    }
+
+    fn set_hierarchy(&self, hierarchy: &Hierarchy) -> StorageResult<()> {
+        let writer = BufWriter::new(File::create(&self.path)?);
+        serde_json::to_writer(writer, hierarchy.get_regions())?;
+        snapshot::write_snapshot(
+            &snapshot::get_snapshot_path(&self.path),
+            hierarchy.get_regions(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn writes_the_hierarchy_and_its_snapshot() {
+        let directory = std::env::temp_dir().join(format!("hierarchy-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory
+            .join("idx_hierarchy.json")
+            .to_string_lossy()
+            .to_string();
+        let storage = MemoryHierarchyStorage::new(path.to_string());
+        assert!(storage.get_hierarchy().unwrap().is_none());
+
+        let mut hierarchy = Hierarchy::default();
+        hierarchy.add("R1", "D1", "E1", "C1", "1");
+        hierarchy.add("R1", "D1", "", "C2", "2");
+        storage.set_hierarchy(&hierarchy).unwrap();
+        assert_eq!(
+            storage.get_hierarchy().unwrap().unwrap().get_regions(),
+            hierarchy.get_regions()
+        );
+
+        //Without the snapshot the JSON file is read.
+        fs::remove_file(snapshot::get_snapshot_path(&path)).unwrap();
+        assert_eq!(
+            storage.get_hierarchy().unwrap().unwrap().get_regions(),
+            hierarchy.get_regions()
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -2,22 +2,57 @@ use domain::storage::error::*;
 use domain::storage::traits::IndexStorageTrait;
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
 use std::ops::Bound::Included;
+use std::sync::{RwLock, RwLockReadGuard};
 
 pub mod extended;
+pub mod hierarchy;
+pub mod snapshot;
 
 pub struct MemoryIndexStorage {
-    pub index: BTreeMap<String, Vec<String>>,
+    path: String,
+    index: RwLock<BTreeMap<String, Vec<String>>>,
 }
 
 impl MemoryIndexStorage {
+    //Load the binary snapshot of the index when there is one, the JSON file otherwise.
     pub fn new(path: String) -> StorageResult<Self> {
-        let file = File::open(path)?;
+        if let Some(index) = snapshot::load_snapshot(&snapshot::get_snapshot_path(&path)) {
+            return Ok(MemoryIndexStorage {
+                path,
+                index: RwLock::new(index),
+            });
+        }
+
+        let file = File::open(&path)?;
         let reader = BufReader::new(file);
         let index: BTreeMap<String, Vec<String>> = serde_json::from_reader(reader)?;
 
-        Ok(MemoryIndexStorage { index: index })
+        Ok(MemoryIndexStorage {
+            path,
+            index: RwLock::new(index),
+        })
+    }
+
+    fn read_index(&self) -> RwLockReadGuard<'_, BTreeMap<String, Vec<String>>> {
+        self.index.read().unwrap()
+    }
+
+    //Apply a change on the index, then write the JSON file and the snapshot again when the
+    //change returns that the index was modified.
+    fn write_index<F>(&self, change: F) -> StorageResult<()>
+    where
+        F: FnOnce(&mut BTreeMap<String, Vec<String>>) -> bool,
+    {
+        let mut index = self.index.write().unwrap();
+        if !change(&mut index) {
+            return Ok(());
+        }
+
+        let writer = BufWriter::new(File::create(&self.path)?);
+        serde_json::to_writer(writer, &*index)?;
+        snapshot::write_snapshot(&snapshot::get_snapshot_path(&self.path), &*index)
     }
 }
 
@@ -29,14 +64,15 @@ impl IndexStorageTrait for MemoryIndexStorage {
     ) -> StorageResult<Vec<String>> {
         let mut results = Vec::new();
 
+        let stored_index = self.read_index();
         let index = match start_with {
             Some(value) => {
                 let start: &String = &value;
                 let end: &String = &format!("{}{}", value.to_string(), "z");
                 let bound = (Included(start), Included(end));
-                self.index.range::<String, _>(bound)
+                stored_index.range::<String, _>(bound)
             }
-            None => self.index.range("0".to_string()..),
+            None => stored_index.range("0".to_string()..),
         };
 
         let query = query.to_uppercase();
@@ -49,26 +85,50 @@ impl IndexStorageTrait for MemoryIndexStorage {
     }
 
     fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>> {
-        match self.index.get(&value) {
+        match self.read_index().get(&value) {
             Some(results) => Ok(Some(results.to_vec())),
             None => Ok(None),
         }
     }
 
     fn get_all_values(&self) -> StorageResult<Vec<String>> {
-        let size = self.index.iter().fold(0, |acc, index| acc + index.1.len());
+        let index = self.read_index();
+        let size = index.iter().fold(0, |acc, index| acc + index.1.len());
 
         let mut result: Vec<String> = Vec::with_capacity(size);
-        for index in self.index.iter() {
+        for index in index.iter() {
             result.extend_from_slice(&index.1);
         }
         Ok(result)
     }
 
     fn get_all_keys(&self) -> StorageResult<Vec<String>> {
-        let keys: Vec<String> = self.index.keys().cloned().collect();
+        let keys: Vec<String> = self.read_index().keys().cloned().collect();
         Ok(keys)
     }
+
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>> {
+        Ok(self
+            .read_index()
+            .iter()
+            .filter(|(_, values)| values.contains(&value))
+            .map(|(key, _)| key.to_string())
+            .collect())
+    }
+
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()> {
+        self.write_index(|index| {
+            if index.get(&key) == Some(&values) {
+                return false;
+            }
+            index.insert(key, values);
+            true
+        })
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        self.write_index(|index| index.remove(&key).is_some())
+    }
 }
 
 impl MemoryIndexStorage {
@@ -0,0 +1,171 @@
+use domain::storage::error::*;
+use memmap2::Mmap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+//Binary snapshot: magic bytes, format version (u32 little endian), then the bincode payload.
+//The snapshot is not zero-copy: it is memory mapped to avoid reading the file in a buffer first,
+//but the payload is still deserialized into an owned index. It only saves the JSON parsing.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"D4GINDEX";
+pub const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_HEADER_LEN: usize = 12;
+
+//Path of the snapshot written next to a JSON index (`idx_regions.json` -> `idx_regions.bin`).
+pub fn get_snapshot_path(json_path: &str) -> String {
+    Path::new(json_path)
+        .with_extension("bin")
+        .to_string_lossy()
+        .to_string()
+}
+
+pub fn write_snapshot<T: Serialize>(path: &str, value: &T) -> StorageResult<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(SNAPSHOT_MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    match bincode::serialize_into(&mut writer, value) {
+        Ok(_) => Ok(writer.flush()?),
+        Err(error) => Err(StorageError::Serialization(error.to_string())),
+    }
+}
+
+//Read a snapshot through a memory map, `None` when it is missing or from another format version.
+pub fn read_snapshot<T: DeserializeOwned>(path: &str) -> StorageResult<Option<T>> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+
+    let file = File::open(path)?;
+    let mmap = unsafe { Mmap::map(&file)? };
+    if mmap.len() < SNAPSHOT_HEADER_LEN || &mmap[..SNAPSHOT_MAGIC.len()] != SNAPSHOT_MAGIC {
+        return Err(StorageError::Serialization(format!(
+            "{} is not an index snapshot",
+            path
+        )));
+    }
+
+    let version = u32::from_le_bytes(
+        mmap[SNAPSHOT_MAGIC.len()..SNAPSHOT_HEADER_LEN]
+            .try_into()
+            .unwrap(),
+    );
+    if version != SNAPSHOT_VERSION {
+        return Ok(None);
+    }
+
+    match bincode::deserialize(&mmap[SNAPSHOT_HEADER_LEN..]) {
+        Ok(value) => Ok(Some(value)),
+        Err(error) => Err(StorageError::Serialization(error.to_string())),
+    }
+}
+
+//Read a snapshot to load an index, logging why it cannot be used before the JSON file is loaded.
+pub fn load_snapshot<T: DeserializeOwned>(path: &str) -> Option<T> {
+    match read_snapshot(path) {
+        Ok(Some(value)) => Some(value),
+        Ok(None) => None,
+        Err(error) => {
+            log::warn!(
+                "cannot load the snapshot {}, falling back to JSON: {}",
+                path,
+                error
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryIndexStorage;
+    use domain::storage::traits::IndexStorageTrait;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    fn get_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("snapshot-{}-{}", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn get_index() -> BTreeMap<String, Vec<String>> {
+        let mut index = BTreeMap::new();
+        index.insert("Hauts-de-France".to_string(), vec!["620410801".to_string()]);
+        index.insert("Occitanie".to_string(), vec![]);
+        index
+    }
+
+    //Snapshot with the given header and the payload of the index.
+    fn write_header(path: &str, magic: &[u8], version: u32) {
+        let mut bytes = magic.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&bincode::serialize(&get_index()).unwrap());
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn snapshots_are_read_back() {
+        let path = get_path("round-trip.bin");
+        write_snapshot(&path, &get_index()).unwrap();
+        let index: Option<BTreeMap<String, Vec<String>>> = read_snapshot(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(index, Some(get_index()));
+        let missing: Option<BTreeMap<String, Vec<String>>> = read_snapshot(&path).unwrap();
+        assert_eq!(missing, None);
+    }
+
+    #[test]
+    fn snapshots_of_another_version_are_left_out() {
+        let path = get_path("version.bin");
+        write_header(&path, SNAPSHOT_MAGIC, SNAPSHOT_VERSION + 1);
+        let index: Option<BTreeMap<String, Vec<String>>> = read_snapshot(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(index, None);
+    }
+
+    #[test]
+    fn files_without_the_magic_bytes_are_not_snapshots() {
+        let path = get_path("magic.bin");
+        write_header(&path, b"D4GOTHER", SNAPSHOT_VERSION);
+        let error = read_snapshot::<BTreeMap<String, Vec<String>>>(&path).unwrap_err();
+        let loaded = load_snapshot::<BTreeMap<String, Vec<String>>>(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(error.to_string().contains("is not an index snapshot"));
+        //The error is logged, the JSON file is loaded instead.
+        assert_eq!(loaded, None);
+    }
+
+    #[test]
+    fn the_json_index_is_loaded_without_a_readable_snapshot() {
+        let path = get_path("idx_regions.json");
+        let json_index = get_index();
+        fs::write(&path, serde_json::to_string(&json_index).unwrap()).unwrap();
+        fs::write(get_snapshot_path(&path), b"not a snapshot").unwrap();
+        let from_json = MemoryIndexStorage::new(path.clone()).unwrap();
+        assert_eq!(
+            from_json.get_all_keys().unwrap(),
+            vec!["Hauts-de-France".to_string(), "Occitanie".to_string()]
+        );
+
+        //A readable snapshot wins over the JSON file.
+        let mut snapshot_index = get_index();
+        snapshot_index.remove("Occitanie");
+        write_snapshot(&get_snapshot_path(&path), &snapshot_index).unwrap();
+        let from_snapshot = MemoryIndexStorage::new(path.clone()).unwrap();
+        fs::remove_file(get_snapshot_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            from_snapshot.get_all_keys().unwrap(),
+            vec!["Hauts-de-France".to_string()]
+        );
+    }
+}
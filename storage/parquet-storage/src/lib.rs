@@ -0,0 +1,186 @@
+use arrow_array::builder::{Float64Builder, StringBuilder};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use domain::core::flat_entry::{FlatEntry, FLAT_SCORE_COLUMNS};
+use domain::storage::error::*;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::io::Write;
+use std::sync::Arc;
+
+//Rows buffered before writing a record batch.
+const BATCH_SIZE: usize = 8192;
+
+//Text columns of a flat entry, before the scores.
+pub const TEXT_COLUMNS: [&str; 5] = [
+    "iris_code",
+    "iris_code_designation",
+    "region",
+    "department",
+    "insee_com",
+];
+
+pub fn get_schema() -> SchemaRef {
+    let mut fields: Vec<Field> = TEXT_COLUMNS
+        .iter()
+        .map(|column| Field::new(*column, DataType::Utf8, true))
+        .collect();
+    fields.extend(
+        FLAT_SCORE_COLUMNS
+            .iter()
+            .map(|column| Field::new(*column, DataType::Float64, true)),
+    );
+    Arc::new(Schema::new(fields))
+}
+
+//Write the flat entries as a Parquet file, return the number of written rows.
+pub fn write_entries<W, I, E>(writer: W, entries: I) -> Result<usize, E>
+where
+    W: Write + Send,
+    I: Iterator<Item = Result<FlatEntry, E>>,
+    E: From<StorageError>,
+{
+    let schema = get_schema();
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    let mut writer = to_storage_result(ArrowWriter::try_new(
+        writer,
+        schema.clone(),
+        Some(properties),
+    ))?;
+
+    let mut batch = FlatEntryBatch::new();
+    let mut count = 0;
+    for entry in entries {
+        batch.push(&entry?);
+        count += 1;
+        if batch.len == BATCH_SIZE {
+            to_storage_result(writer.write(&batch.finish(&schema)?))?;
+        }
+    }
+    if batch.len > 0 {
+        to_storage_result(writer.write(&batch.finish(&schema)?))?;
+    }
+
+    to_storage_result(writer.close())?;
+    Ok(count)
+}
+
+//Column builders of the next record batch.
+struct FlatEntryBatch {
+    texts: Vec<StringBuilder>,
+    scores: Vec<Float64Builder>,
+    len: usize,
+}
+
+impl FlatEntryBatch {
+    fn new() -> Self {
+        FlatEntryBatch {
+            texts: TEXT_COLUMNS.iter().map(|_| StringBuilder::new()).collect(),
+            scores: FLAT_SCORE_COLUMNS
+                .iter()
+                .map(|_| Float64Builder::new())
+                .collect(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, entry: &FlatEntry) {
+        let texts = [
+            &entry.iris_code,
+            &entry.iris_code_designation,
+            &entry.region,
+            &entry.department,
+            &entry.insee_com,
+        ];
+        for (builder, text) in self.texts.iter_mut().zip(texts.iter()) {
+            builder.append_option(text.as_deref());
+        }
+        for (position, builder) in self.scores.iter_mut().enumerate() {
+            builder.append_option(entry.scores.get(position).cloned().flatten());
+        }
+        self.len += 1;
+    }
+
+    //Build the record batch and reset the builders.
+    fn finish(&mut self, schema: &SchemaRef) -> StorageResult<RecordBatch> {
+        let mut columns: Vec<ArrayRef> = self
+            .texts
+            .iter_mut()
+            .map(|builder| Arc::new(builder.finish()) as ArrayRef)
+            .collect();
+        columns.extend(
+            self.scores
+                .iter_mut()
+                .map(|builder| Arc::new(builder.finish()) as ArrayRef),
+        );
+        self.len = 0;
+
+        RecordBatch::try_new(schema.clone(), columns)
+            .map_err(|error| StorageError::Serialization(error.to_string()))
+    }
+}
+
+fn to_storage_result<T>(result: parquet::errors::Result<T>) -> StorageResult<T> {
+    result.map_err(|error| StorageError::Serialization(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Float64Type;
+    use arrow_array::Array;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::fs::{self, File};
+
+    fn get_flat_entry(position: usize) -> FlatEntry {
+        let mut scores = vec![None; FLAT_SCORE_COLUMNS.len()];
+        scores[0] = Some(position as f64 + 0.5);
+        FlatEntry {
+            iris_code: Some(format!("{:09}", position)),
+            iris_code_designation: None,
+            region: Some("Hauts-de-France".to_string()),
+            department: Some("62 - Pas-de-Calais".to_string()),
+            insee_com: Some("62041".to_string()),
+            epci: None,
+            commune: None,
+            scores,
+        }
+    }
+
+    #[test]
+    fn entries_are_read_back_with_the_schema() {
+        let path = std::env::temp_dir().join(format!("entries-{}.parquet", std::process::id()));
+        //More rows than a record batch.
+        let entries = (0..BATCH_SIZE + 1).map(|position| Ok(get_flat_entry(position)));
+        let count =
+            write_entries::<_, _, StorageError>(File::create(&path).unwrap(), entries).unwrap();
+        assert_eq!(count, BATCH_SIZE + 1);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(Result::unwrap).collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            batches.iter().map(RecordBatch::num_rows).sum::<usize>(),
+            count
+        );
+        let batch = &batches[0];
+        assert_eq!(batch.schema().fields(), get_schema().fields());
+        let iris_codes = batch.column(0).as_string::<i32>();
+        assert_eq!(iris_codes.value(1), "000000001");
+        assert!(batch.column(1).is_null(1));
+        assert_eq!(batch.column(4).as_string::<i32>().value(1), "62041");
+        let global = batch
+            .column(TEXT_COLUMNS.len())
+            .as_primitive::<Float64Type>();
+        assert_eq!(global.value(1), 1.5);
+        assert!(batch.column(TEXT_COLUMNS.len() + 1).is_null(1));
+    }
+}
@@ -0,0 +1,155 @@
+use crate::SledEntriesStorage;
+use domain::core::entry::Iris;
+use domain::storage::error::*;
+use domain::storage::traits::IndexStoragePostalTrait;
+use serde_cbor::de::from_slice;
+use serde_cbor::ser::to_vec;
+use sled::Tree;
+use std::collections::BTreeMap;
+
+const POSTAL_INDEX_NAME: &str = "postal";
+
+//Postal codes index stored in the `idx_postal` tree of the entries database.
+pub struct SledIndexStoragePostal {
+    storage: SledEntriesStorage,
+    tree: Tree,
+}
+
+impl SledIndexStoragePostal {
+    pub fn new(storage: &SledEntriesStorage) -> Self {
+        SledIndexStoragePostal {
+            storage: storage.clone(),
+            tree: storage.get_index_tree(POSTAL_INDEX_NAME),
+        }
+    }
+
+    //Index of a database loaded by the API, refused when it was written by a newer version.
+    pub fn open(storage: &SledEntriesStorage) -> StorageResult<Self> {
+        storage.check_index_version(POSTAL_INDEX_NAME)?;
+        Ok(SledIndexStoragePostal::new(storage))
+    }
+
+    //Replace the whole content of the index, atomically with its version.
+    pub fn create_index(&self, index: &BTreeMap<String, Iris>) -> StorageResult<()> {
+        let mut content = Vec::with_capacity(index.len());
+        for (key, iris) in index {
+            match to_vec(iris) {
+                Ok(cbor_iris) => content.push((key.to_string(), cbor_iris)),
+                Err(error) => return Err(StorageError::Serialization(error.to_string())),
+            }
+        }
+        self.storage.replace_index(POSTAL_INDEX_NAME, content)
+    }
+
+    fn decode_key(key: &[u8]) -> String {
+        String::from_utf8_lossy(key).to_string()
+    }
+
+    fn collect_keys(iter: sled::Iter) -> StorageResult<Vec<String>> {
+        let mut keys: Vec<String> = Vec::new();
+        for key in iter.keys() {
+            match key {
+                Ok(key) => keys.push(SledIndexStoragePostal::decode_key(&key)),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(keys)
+    }
+
+    fn decode_iris(cbor: &[u8]) -> StorageResult<Iris> {
+        match from_slice(cbor) {
+            Ok(iris) => Ok(iris),
+            Err(error) => Err(StorageError::Serialization(error.to_string())),
+        }
+    }
+}
+
+impl IndexStoragePostalTrait for SledIndexStoragePostal {
+    fn search_on_key(
+        &self,
+        query: String,
+        start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        let mut results = Vec::new();
+
+        let index = match start_with {
+            Some(value) => {
+                let end = format!("{}{}", value, "z");
+                self.tree.range(value.as_bytes()..=end.as_bytes())
+            }
+            None => self.tree.range("0".as_bytes()..),
+        };
+
+        let query = query.to_uppercase();
+        for item in index {
+            let key = match item {
+                Ok((key, _)) => SledIndexStoragePostal::decode_key(&key),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            };
+            if key.contains(&query) {
+                results.push(key);
+            }
+        }
+        Ok(results)
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Iris>> {
+        match self.tree.get(value.as_bytes()) {
+            Ok(Some(cbor)) => Ok(Some(SledIndexStoragePostal::decode_iris(&cbor)?)),
+            Ok(None) => Ok(None),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<Iris>> {
+        let mut result: Vec<Iris> = Vec::new();
+        for item in self.tree.iter() {
+            match item {
+                Ok((_, cbor)) => result.push(SledIndexStoragePostal::decode_iris(&cbor)?),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(result)
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        let mut keys: Vec<String> = Vec::new();
+        for key in self.tree.iter().keys() {
+            match key {
+                Ok(key) => keys.push(SledIndexStoragePostal::decode_key(&key)),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(keys)
+    }
+
+    fn find_keys(&self, code: String) -> StorageResult<Vec<String>> {
+        let mut keys: Vec<String> = Vec::new();
+        for item in self.tree.iter() {
+            match item {
+                Ok((key, cbor)) => {
+                    if SledIndexStoragePostal::decode_iris(&cbor)?.code.as_ref() == Some(&code) {
+                        keys.push(SledIndexStoragePostal::decode_key(&key));
+                    }
+                }
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(keys)
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        match self.tree.remove(key.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn search_prefix(&self, prefix: String) -> StorageResult<Vec<String>> {
+        SledIndexStoragePostal::collect_keys(self.tree.scan_prefix(prefix.as_bytes()))
+    }
+
+    fn search_range(&self, start: String, end: String) -> StorageResult<Vec<String>> {
+        SledIndexStoragePostal::collect_keys(self.tree.range(start.as_bytes()..end.as_bytes()))
+    }
+}
@@ -0,0 +1,146 @@
+use crate::SledEntriesStorage;
+use domain::storage::error::*;
+use domain::storage::traits::IndexStorageTrait;
+use serde_cbor::de::from_slice;
+use serde_cbor::ser::to_vec;
+use sled::Tree;
+use std::collections::BTreeMap;
+
+//Territory index stored in the `idx_{name}` tree of the entries database.
+pub struct SledIndexStorage {
+    storage: SledEntriesStorage,
+    name: String,
+    tree: Tree,
+}
+
+impl SledIndexStorage {
+    pub fn new(storage: &SledEntriesStorage, name: String) -> Self {
+        SledIndexStorage {
+            storage: storage.clone(),
+            tree: storage.get_index_tree(&name),
+            name,
+        }
+    }
+
+    //Index of a database loaded by the API, refused when it was written by a newer version.
+    pub fn open(storage: &SledEntriesStorage, name: String) -> StorageResult<Self> {
+        storage.check_index_version(&name)?;
+        Ok(SledIndexStorage::new(storage, name))
+    }
+
+    //Replace the whole content of the index, atomically with its version.
+    pub fn create_index(&self, index: &BTreeMap<String, Vec<String>>) -> StorageResult<()> {
+        let mut content = Vec::with_capacity(index.len());
+        for (key, values) in index {
+            match to_vec(values) {
+                Ok(cbor_values) => content.push((key.to_string(), cbor_values)),
+                Err(error) => return Err(StorageError::Serialization(error.to_string())),
+            }
+        }
+        self.storage.replace_index(&self.name, content)
+    }
+
+    fn decode_key(key: &[u8]) -> String {
+        String::from_utf8_lossy(key).to_string()
+    }
+
+    fn decode_values(cbor: &[u8]) -> StorageResult<Vec<String>> {
+        match from_slice(cbor) {
+            Ok(values) => Ok(values),
+            Err(error) => Err(StorageError::Serialization(error.to_string())),
+        }
+    }
+}
+
+impl IndexStorageTrait for SledIndexStorage {
+    fn search_on_key(
+        &self,
+        query: String,
+        start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        let mut results = Vec::new();
+
+        let index = match start_with {
+            Some(value) => {
+                let end = format!("{}{}", value, "z");
+                self.tree.range(value.as_bytes()..=end.as_bytes())
+            }
+            None => self.tree.range("0".as_bytes()..),
+        };
+
+        let query = query.to_uppercase();
+        for item in index {
+            let key = match item {
+                Ok((key, _)) => SledIndexStorage::decode_key(&key),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            };
+            if key.contains(&query) {
+                results.push(key);
+            }
+        }
+        Ok(results)
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>> {
+        match self.tree.get(value.as_bytes()) {
+            Ok(Some(cbor)) => Ok(Some(SledIndexStorage::decode_values(&cbor)?)),
+            Ok(None) => Ok(None),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<String>> {
+        let mut result: Vec<String> = Vec::new();
+        for item in self.tree.iter() {
+            match item {
+                Ok((_, cbor)) => result.extend(SledIndexStorage::decode_values(&cbor)?),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(result)
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        let mut keys: Vec<String> = Vec::new();
+        for key in self.tree.iter().keys() {
+            match key {
+                Ok(key) => keys.push(SledIndexStorage::decode_key(&key)),
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(keys)
+    }
+
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>> {
+        let mut keys: Vec<String> = Vec::new();
+        for item in self.tree.iter() {
+            match item {
+                Ok((key, cbor)) => {
+                    if SledIndexStorage::decode_values(&cbor)?.contains(&value) {
+                        keys.push(SledIndexStorage::decode_key(&key));
+                    }
+                }
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        Ok(keys)
+    }
+
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()> {
+        let cbor_values = match to_vec(&values) {
+            Ok(cbor_values) => cbor_values,
+            Err(error) => return Err(StorageError::Serialization(error.to_string())),
+        };
+        match self.tree.insert(key.as_bytes(), cbor_values) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        match self.tree.remove(key.as_bytes()) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+}
@@ -1,16 +1,28 @@
-use domain::core::entry::{
-    AdministrativeCompetencies, Entry, InformationAccess, NumericCompetencies,
-    NumericInterfacesAccess,
-};
+use domain::core::entry::Entry;
 use domain::storage::error::*;
-use domain::storage::traits::EntryStorageTrait;
-use serde_cbor::de::from_slice;
-use serde_cbor::ser::to_vec;
-use sled::Db;
+use domain::storage::traits::{EntryIterator, EntryStorageTrait};
+use schema::{
+    decode_entry, encode_entry, get_record_version, reencode_entry, upgrade_record,
+    INDEX_SCHEMA_VERSION, INFORMATION_SCORE_SCHEMA_VERSION,
+};
+use sled::transaction::ConflictableTransactionResult;
 use sled::Tree;
+use sled::{Batch, Db, IVec, Transactional};
+use std::collections::{HashMap, HashSet};
+
+pub mod extended;
+pub mod index;
+pub mod schema;
 
 const ENTRIES_TREE_NAME: &str = "entries";
 
+//Schema version of each index, by name of its tree.
+const INDEX_VERSIONS_TREE_NAME: &str = "index_versions";
+
+//Keys skipped between two requested entries before a new range scan is started.
+const MAX_SKIPPED_KEYS: usize = 32;
+
+#[derive(Clone)]
 pub struct SledEntriesStorage {
     storage: Db,
 }
@@ -22,91 +34,199 @@ impl SledEntriesStorage {
         }
     }
 
+    //Open the database without panicking, when it is loaded on a running API.
+    pub fn open(path: String) -> StorageResult<Self> {
+        match sled::open(path) {
+            Ok(storage) => Ok(SledEntriesStorage { storage }),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
     fn get_entries_tree(&self) -> Tree {
         self.storage
             .open_tree(ENTRIES_TREE_NAME)
             .expect("cannot open tree")
     }
+
+    fn get_index_tree(&self, name: &str) -> Tree {
+        self.storage
+            .open_tree(format!("idx_{}", name))
+            .expect("cannot open tree")
+    }
+
+    //Replace the whole content of an index tree and its version in a single transaction: a crash
+    //leaves either the previous index or the new one, never an empty or a partial one.
+    pub(crate) fn replace_index(
+        &self,
+        name: &str,
+        content: Vec<(String, Vec<u8>)>,
+    ) -> StorageResult<()> {
+        let tree = self.get_index_tree(name);
+        let versions = self.get_index_versions_tree();
+
+        let mut batch = Batch::default();
+        let new_keys: HashSet<&[u8]> = content.iter().map(|(key, _)| key.as_bytes()).collect();
+        for key in tree.iter().keys() {
+            match key {
+                Ok(key) if !new_keys.contains(&*key) => batch.remove(key),
+                Ok(_) => {}
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            }
+        }
+        for (key, value) in &content {
+            batch.insert(key.as_bytes(), value.as_slice());
+        }
+
+        let version = INDEX_SCHEMA_VERSION.to_be_bytes();
+        let result = (&tree, &versions).transaction(
+            |(tree, versions)| -> ConflictableTransactionResult<(), sled::Error> {
+                tree.apply_batch(&batch)?;
+                versions.insert(name.as_bytes(), &version[..])?;
+                Ok(())
+            },
+        );
+        match result {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    //Schema version of an index, None when it was written before the indexes were versioned.
+    pub fn get_index_version(&self, name: &str) -> StorageResult<Option<u32>> {
+        match self.get_index_versions_tree().get(name.as_bytes()) {
+            Ok(Some(bytes)) if bytes.len() == 4 => Ok(Some(u32::from_be_bytes([
+                bytes[0], bytes[1], bytes[2], bytes[3],
+            ]))),
+            Ok(Some(_)) => Err(StorageError::Serialization(format!(
+                "invalid version of the index {}",
+                name
+            ))),
+            Ok(None) => Ok(None),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    //Check that an index was not written by a newer schema version.
+    pub(crate) fn check_index_version(&self, name: &str) -> StorageResult<()> {
+        match self.get_index_version(name)? {
+            Some(version) if version > INDEX_SCHEMA_VERSION => {
+                Err(StorageError::Serialization(format!(
+                    "unsupported schema version {} of the index {}, expected at most {}",
+                    version, name, INDEX_SCHEMA_VERSION
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn get_index_versions_tree(&self) -> Tree {
+        self.storage
+            .open_tree(INDEX_VERSIONS_TREE_NAME)
+            .expect("cannot open tree")
+    }
+
+    //Wait for the entries and the indexes to be written on disk.
+    pub fn flush(&self) -> StorageResult<()> {
+        match self.storage.flush() {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    //Rewrite the entries stored with an older schema version, return the number of upgraded
+    //entries. They are upgraded to the last version without the information score, which only a
+    //new import of the entries brings.
+    pub fn migrate(&self) -> StorageResult<usize> {
+        let tree = self.get_entries_tree();
+        let mut migrated = 0;
+        for record in tree.iter() {
+            let (iris_code, bytes) = match record {
+                Ok(record) => record,
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            };
+            let upgraded = match upgrade_record(&bytes)? {
+                Some(upgraded) => upgraded,
+                None => continue,
+            };
+            if let Err(error) = tree.insert(iris_code, upgraded) {
+                return Err(StorageError::Database(error.to_string()));
+            }
+            migrated += 1;
+        }
+        self.flush()?;
+        Ok(migrated)
+    }
 }
 
 impl EntryStorageTrait for SledEntriesStorage {
     fn get_all(&self) -> StorageResult<Vec<Entry>> {
+        self.iter_entries()?.collect()
+    }
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>> {
         let tree = self.get_entries_tree();
-        let entries: Vec<Entry> = tree
-            .iter()
-            .map(|kv| {
-                let cbor_entry = kv.unwrap().1;
-                from_slice(&cbor_entry).unwrap()
-            })
-            .collect();
-        Ok(entries)
+        Ok(Box::new(tree.iter().values().map(
+            |cbor_entry| match cbor_entry {
+                Ok(cbor) => decode_entry(&cbor),
+                Err(error) => Err(StorageError::Database(error.to_string())),
+            },
+        )))
     }
+
     fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
         let tree = self.get_entries_tree();
         match tree.get(iris_code.to_string()) {
             Ok(wrap_cbor_entry) => match wrap_cbor_entry {
-                Some(cbor) => match from_slice(&cbor) {
-                    Ok(deser_value) => Ok(Some(deser_value)),
-                    Err(_) => Err(StorageError::NotImplemented),
-                },
-                None => Err(StorageError::NotImplemented),
+                Some(cbor) => Ok(Some(decode_entry(&cbor)?)),
+                None => Ok(None),
             },
             Err(_) => Err(StorageError::NotImplemented),
         }
     }
+    //The IRIS codes are read in key order: the codes of a commune or a department are read with a
+    //single range scan, a new scan starts from the next code when the requested codes are far apart.
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>> {
+        let mut codes: Vec<&String> = iris_codes.iter().collect();
+        codes.sort();
+        codes.dedup();
+        let last = match codes.last() {
+            Some(last) => last.as_bytes(),
+            None => return Ok(HashMap::new()),
+        };
+
+        let tree = self.get_entries_tree();
+        let mut entries: HashMap<String, Entry> = HashMap::with_capacity(codes.len());
+        let mut range = tree.range(codes[0].as_bytes()..=last);
+        let mut current = next_item(&mut range)?;
+        for code in codes {
+            let mut skipped = 0;
+            while let Some((key, _)) = &current {
+                if key.as_ref() >= code.as_bytes() {
+                    break;
+                }
+                if skipped == MAX_SKIPPED_KEYS {
+                    range = tree.range(code.as_bytes()..=last);
+                } else {
+                    skipped += 1;
+                }
+                current = next_item(&mut range)?;
+            }
+
+            if let Some((key, cbor)) = &current {
+                if key.as_ref() == code.as_bytes() {
+                    entries.insert(code.to_string(), decode_entry(cbor)?);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
     fn get_national_entry(&self) -> StorageResult<Option<Entry>> {
         let tree = self.get_entries_tree();
 
         match tree.first() {
             Ok(wrap_cbor_entry) => {
-                let entry: Entry = from_slice(&wrap_cbor_entry.unwrap().1).unwrap();
-
-                let national_entry = Entry::new(
-                    entry.global_national,
-                    None,
-                    None,
-                    None,
-                    None,
-                    None,
-                    Some(InformationAccess::new(
-                        entry.information_access.unwrap().global_national,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                    )),
-                    Some(NumericInterfacesAccess::new(
-                        entry.numeric_interfaces_access.unwrap().global_national,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                    )),
-                    Some(AdministrativeCompetencies::new(
-                        entry.administrative_competencies.unwrap().global_national,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                    )),
-                    Some(NumericCompetencies::new(
-                        entry.numeric_competencies.unwrap().global_national,
-                        None,
-                        None,
-                        None,
-                        None,
-                        None,
-                    )),
-                );
-
-                Ok(Some(national_entry))
+                let entry: Entry = decode_entry(&wrap_cbor_entry.unwrap().1)?;
+                Ok(Some(entry.to_national_entry()))
             }
             Err(_) => Err(StorageError::AnotherError),
         }
@@ -118,7 +238,7 @@ impl EntryStorageTrait for SledEntriesStorage {
         let mut first_region_entry: Option<Entry> = None;
         for entry in tree.iter() {
             let entry_by_code = entry.unwrap();
-            let decoded_entry: Entry = from_slice(&entry_by_code.1).unwrap();
+            let decoded_entry: Entry = decode_entry(&entry_by_code.1)?;
             let stored_iris = decoded_entry.iris_code.clone().unwrap();
             if &stored_iris == &code_iris {
                 first_region_entry = Some(decoded_entry.clone());
@@ -127,82 +247,7 @@ impl EntryStorageTrait for SledEntriesStorage {
         }
 
         match first_region_entry {
-            Some(found_entry) => Ok(Some(Entry::new(
-                found_entry.global_region,
-                None,
-                None,
-                found_entry.global_national,
-                None,
-                None,
-                Some(InformationAccess::new(
-                    found_entry
-                        .information_access
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    None,
-                    found_entry
-                        .information_access
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                    None,
-                    None,
-                )),
-                Some(NumericInterfacesAccess::new(
-                    found_entry
-                        .numeric_interfaces_access
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    None,
-                    found_entry
-                        .numeric_interfaces_access
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                    None,
-                    None,
-                )),
-                Some(AdministrativeCompetencies::new(
-                    found_entry
-                        .administrative_competencies
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    None,
-                    found_entry
-                        .administrative_competencies
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                )),
-                Some(NumericCompetencies::new(
-                    found_entry
-                        .numeric_competencies
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    None,
-                    found_entry
-                        .numeric_competencies
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                )),
-            ))),
+            Some(found_entry) => Ok(Some(found_entry.to_regional_entry())),
             None => Err(StorageError::AnotherError),
         }
     }
@@ -213,7 +258,7 @@ impl EntryStorageTrait for SledEntriesStorage {
         let mut first_dept_entry: Option<Entry> = None;
         for entry in tree.iter() {
             let entry_by_code = entry.unwrap();
-            let decoded_entry: Entry = from_slice(&entry_by_code.1).unwrap();
+            let decoded_entry: Entry = decode_entry(&entry_by_code.1)?;
             let stored_iris = decoded_entry.iris_code.clone().unwrap();
             if &stored_iris == &iris_code {
                 first_dept_entry = Some(decoded_entry.clone());
@@ -222,103 +267,241 @@ impl EntryStorageTrait for SledEntriesStorage {
         }
 
         match first_dept_entry {
-            Some(found_entry) => Ok(Some(Entry::new(
-                found_entry.global_dept,
-                found_entry.global_region,
-                None,
-                found_entry.global_national,
-                None,
-                None,
-                Some(InformationAccess::new(
-                    found_entry.information_access.clone().unwrap().global_dept,
-                    found_entry
-                        .information_access
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    found_entry
-                        .information_access
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                    None,
-                    None,
-                )),
-                Some(NumericInterfacesAccess::new(
-                    found_entry
-                        .numeric_interfaces_access
-                        .clone()
-                        .unwrap()
-                        .global_dept,
-                    found_entry
-                        .numeric_interfaces_access
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    found_entry
-                        .numeric_interfaces_access
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                    None,
-                    None,
-                )),
-                Some(AdministrativeCompetencies::new(
-                    found_entry
-                        .administrative_competencies
-                        .clone()
-                        .unwrap()
-                        .global_dept,
-                    found_entry
-                        .administrative_competencies
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    found_entry
-                        .administrative_competencies
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                )),
-                Some(NumericCompetencies::new(
-                    found_entry
-                        .numeric_competencies
-                        .clone()
-                        .unwrap()
-                        .global_dept,
-                    found_entry
-                        .numeric_competencies
-                        .clone()
-                        .unwrap()
-                        .global_region,
-                    None,
-                    found_entry
-                        .numeric_competencies
-                        .clone()
-                        .unwrap()
-                        .global_national,
-                    None,
-                    None,
-                )),
-            ))),
+            Some(found_entry) => Ok(Some(found_entry.to_departmental_entry())),
             None => Err(StorageError::AnotherError),
         }
     }
 
     fn create(&self, iris_code: String, entry: Entry) -> StorageResult<()> {
         let tree = self.get_entries_tree();
-        match tree.insert(iris_code, to_vec(&entry).unwrap()) {
+        match tree.insert(iris_code, encode_entry(&entry)?) {
             Ok(_) => Ok(()),
             Err(_) => Err(StorageError::NotImplemented),
         }
     }
+
+    //The entries without the information score keep the version of their record.
+    fn update(&self, iris_code: String, entry: Entry) -> StorageResult<Option<Entry>> {
+        let tree = self.get_entries_tree();
+        let mut encoding_error = None;
+        let previous = tree.fetch_and_update(iris_code, |stored| {
+            stored.map(|stored| match reencode_entry(&entry, stored) {
+                Ok(cbor) => cbor,
+                Err(error) => {
+                    encoding_error = Some(error);
+                    stored.to_vec()
+                }
+            })
+        });
+        if let Some(error) = encoding_error {
+            return Err(error);
+        }
+        match previous {
+            Ok(Some(stored)) => Ok(Some(decode_entry(&stored)?)),
+            Ok(None) => Ok(None),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn update_entries(&self, entries: &[Entry]) -> StorageResult<()> {
+        let tree = self.get_entries_tree();
+        let mut batch = Batch::default();
+        for entry in entries {
+            let iris_code = match &entry.iris_code {
+                Some(iris_code) => iris_code,
+                None => return Err(StorageError::CreationImpossible),
+            };
+            let cbor = match tree.get(iris_code.as_bytes()) {
+                Ok(Some(stored)) => reencode_entry(entry, &stored)?,
+                Ok(None) => encode_entry(entry)?,
+                Err(error) => return Err(StorageError::Database(error.to_string())),
+            };
+            batch.insert(iris_code.as_bytes(), cbor);
+        }
+
+        match tree.apply_batch(batch) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+
+    fn delete(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        let tree = self.get_entries_tree();
+        match tree.remove(iris_code) {
+            Ok(Some(cbor)) => Ok(Some(decode_entry(&cbor)?)),
+            Ok(None) => Ok(None),
+            Err(error) => Err(StorageError::Database(error.to_string())),
+        }
+    }
+    fn count_outdated_entries(&self) -> StorageResult<usize> {
+        let mut outdated = 0;
+        for bytes in self.get_entries_tree().iter().values() {
+            let bytes = bytes.map_err(|error| StorageError::Database(error.to_string()))?;
+            if get_record_version(&bytes)? < INFORMATION_SCORE_SCHEMA_VERSION {
+                outdated += 1;
+            }
+        }
+        Ok(outdated)
+    }
+}
+
+fn next_item(range: &mut sled::Iter) -> StorageResult<Option<(IVec, IVec)>> {
+    match range.next() {
+        Some(Ok(item)) => Ok(Some(item)),
+        Some(Err(error)) => Err(StorageError::Database(error.to_string())),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extended::SledIndexStoragePostal;
+    use crate::index::SledIndexStorage;
+    use crate::schema::{ENTRY_SCHEMA_VERSION, WITHOUT_INFORMATION_SCORE_VERSION};
+    use domain::business::domain::EntryDomain;
+    use domain::business::error::EntryDomainError;
+    use domain::business::traits::EntryDomainTrait;
+    use std::collections::BTreeMap;
+
+    fn get_temporary_storage() -> SledEntriesStorage {
+        SledEntriesStorage {
+            storage: sled::Config::new().temporary(true).open().unwrap(),
+        }
+    }
+
+    #[test]
+    fn create_index_replaces_stale_keys_and_stores_version() {
+        let storage = get_temporary_storage();
+        let index = SledIndexStorage::new(&storage, "regions".to_string());
+        assert_eq!(storage.get_index_version("regions").unwrap(), None);
+
+        let mut content = BTreeMap::new();
+        content.insert("11".to_string(), vec!["751010101".to_string()]);
+        content.insert("84".to_string(), vec!["010040101".to_string()]);
+        index.create_index(&content).unwrap();
+        content.remove("84");
+        index.create_index(&content).unwrap();
+
+        let keys: Vec<_> = storage
+            .get_index_tree("regions")
+            .iter()
+            .keys()
+            .map(|key| String::from_utf8(key.unwrap().to_vec()).unwrap())
+            .collect();
+        assert_eq!(keys, vec!["11".to_string()]);
+        assert_eq!(
+            storage.get_index_version("regions").unwrap(),
+            Some(INDEX_SCHEMA_VERSION)
+        );
+        assert!(SledIndexStorage::open(&storage, "regions".to_string()).is_ok());
+    }
+
+    fn get_entry(iris_code: &str) -> Entry {
+        Entry::new(
+            Some(100.0),
+            None,
+            None,
+            None,
+            Some(iris_code.to_string()),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn get_entries_reads_codes_far_apart_and_skips_missing_ones() {
+        let storage = get_temporary_storage();
+        let entries: Vec<Entry> = (0..200)
+            .map(|number| get_entry(&format!("75101{:04}", number)))
+            .collect();
+        storage.update_entries(&entries).unwrap();
+
+        let codes: Vec<String> = [
+            "751010150",
+            "751010003",
+            "751019999",
+            "751010003",
+            "751010004",
+        ]
+        .iter()
+        .map(|code| code.to_string())
+        .collect();
+        let found = storage.get_entries(&codes).unwrap();
+        let mut found_codes: Vec<&String> = found.keys().collect();
+        found_codes.sort();
+        assert_eq!(found_codes, vec!["751010003", "751010004", "751010150"]);
+        assert_eq!(found["751010150"].iris_code, Some("751010150".to_string()));
+        assert!(storage.get_entries(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_index_refuses_newer_version() {
+        let storage = get_temporary_storage();
+        storage
+            .get_index_versions_tree()
+            .insert("regions", &(INDEX_SCHEMA_VERSION + 1).to_be_bytes()[..])
+            .unwrap();
+        assert!(SledIndexStorage::open(&storage, "regions".to_string()).is_err());
+    }
+
+    #[test]
+    fn entries_without_the_information_score_stay_outdated_until_imported_again() {
+        let storage = get_temporary_storage();
+        let tree = storage.get_entries_tree();
+        let version =
+            |iris_code: &str| get_record_version(&tree.get(iris_code).unwrap().unwrap()).unwrap();
+        //Legacy record, written before the envelope.
+        let legacy = serde_cbor::to_vec(&get_entry("751010001")).unwrap();
+        tree.insert("751010001", legacy).unwrap();
+        storage
+            .create("751010002".to_string(), get_entry("751010002"))
+            .unwrap();
+        assert_eq!(storage.count_outdated_entries().unwrap(), 1);
+
+        assert_eq!(storage.migrate().unwrap(), 1);
+        assert_eq!(storage.migrate().unwrap(), 0);
+        assert_eq!(version("751010001"), WITHOUT_INFORMATION_SCORE_VERSION);
+        assert_eq!(storage.count_outdated_entries().unwrap(), 1);
+
+        //The rewrites keep the version, like the territories filled by the import `migrate`.
+        storage
+            .update_entries(&[get_entry("751010001"), get_entry("751010002")])
+            .unwrap();
+        storage
+            .update("751010001".to_string(), get_entry("751010001"))
+            .unwrap();
+        assert_eq!(version("751010001"), WITHOUT_INFORMATION_SCORE_VERSION);
+        assert_eq!(version("751010002"), ENTRY_SCHEMA_VERSION);
+
+        let domain = EntryDomain::new(
+            Box::new(SledIndexStorage::new(&storage, "regions".to_string())),
+            Box::new(SledIndexStorage::new(&storage, "departments".to_string())),
+            Box::new(SledIndexStoragePostal::new(&storage)),
+            Box::new(SledIndexStorage::new(&storage, "insee_coms".to_string())),
+            Box::new(SledIndexStorage::new(
+                &storage,
+                "departments_by_region".to_string(),
+            )),
+            Box::new(storage.clone()),
+        );
+        assert!(matches!(
+            domain.delete_district("751010002".to_string()),
+            Err(EntryDomainError::OutdatedEntries(1))
+        ));
+        assert!(storage
+            .get_entry("751010002".to_string())
+            .unwrap()
+            .is_some());
+
+        //A new import of the entry brings the current version.
+        storage
+            .create("751010001".to_string(), get_entry("751010001"))
+            .unwrap();
+        assert_eq!(storage.count_outdated_entries().unwrap(), 0);
+        assert!(domain.delete_district("751010002".to_string()).is_ok());
+    }
 }
@@ -0,0 +1,295 @@
+use domain::core::entry::Entry;
+use domain::storage::error::*;
+use serde_cbor::de::from_slice;
+use serde_cbor::ser::to_vec;
+use serde_cbor::value::{from_value, Value};
+use serde_derive::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+//Version of the `Entry` layout written by this build.
+pub const ENTRY_SCHEMA_VERSION: u32 = 3;
+
+//Version of the layout of the index trees, stored in the `index_versions` tree.
+pub const INDEX_SCHEMA_VERSION: u32 = 1;
+
+//Records written before the envelope was introduced have no version marker.
+const LEGACY_SCHEMA_VERSION: u32 = 0;
+
+//The records of the older versions lack the information score, which cannot be recovered: they
+//are upgraded to the version before it and kept there until the entries are imported again.
+pub const INFORMATION_SCORE_SCHEMA_VERSION: u32 = 3;
+pub const WITHOUT_INFORMATION_SCORE_VERSION: u32 = INFORMATION_SCORE_SCHEMA_VERSION - 1;
+
+const VERSION_FIELD: &str = "version";
+const ENTRY_FIELD: &str = "entry";
+
+//Envelope stored for each entry: the schema version and the CBOR entry.
+#[derive(Serialize)]
+struct EntryRecord<'a> {
+    version: u32,
+    entry: &'a Entry,
+}
+
+//Envelope read back directly as an entry, for the records of the current version.
+#[derive(Deserialize)]
+struct StoredEntryRecord {
+    version: u32,
+    entry: Entry,
+}
+
+//Version of an envelope, read without decoding the entry.
+#[derive(Deserialize)]
+struct StoredRecordVersion {
+    version: u32,
+}
+
+//Upgrade a CBOR entry from the version `from` to the version `from + 1`.
+struct Migration {
+    from: u32,
+    migrate: fn(Value) -> StorageResult<Value>,
+}
+
+//Registry of the migrations, one per schema version.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        from: LEGACY_SCHEMA_VERSION,
+        migrate: migrate_legacy_entry,
+    },
+    Migration {
+        from: 1,
+        migrate: migrate_entry_without_territory,
+    },
+    Migration {
+        from: 2,
+        migrate: migrate_entry_without_information_score,
+    },
+];
+
+//The legacy records have the layout of the version 1, only the envelope is added: the entry
+//is the whole record, unwrapped by `decode_record`.
+fn migrate_legacy_entry(entry: Value) -> StorageResult<Value> {
+    Ok(entry)
+}
+
+//The version 2 adds the optional territory of the entry, it is decoded as None: the territories
+//are only known by the indexes, the `migrate` command of the import fills them from there.
+fn migrate_entry_without_territory(entry: Value) -> StorageResult<Value> {
+    Ok(entry)
+}
+
+//The version 3 adds the optional ACCES A L'INFORMATION score of the entry, averaged for the
+//information access axis. It is decoded as None, the record keeps its version so the entry is
+//counted by `SledEntriesStorage::count_outdated_entries` until it is imported again.
+fn migrate_entry_without_information_score(entry: Value) -> StorageResult<Value> {
+    Ok(entry)
+}
+
+pub fn encode_entry(entry: &Entry) -> StorageResult<Vec<u8>> {
+    encode_entry_version(entry, ENTRY_SCHEMA_VERSION)
+}
+
+//Encode an entry replacing a stored record. The entry of a record without the information score
+//still lacks it, the version of the record is kept.
+pub fn reencode_entry(entry: &Entry, stored: &[u8]) -> StorageResult<Vec<u8>> {
+    match get_record_version(stored)? < INFORMATION_SCORE_SCHEMA_VERSION {
+        true => encode_entry_version(entry, WITHOUT_INFORMATION_SCORE_VERSION),
+        false => encode_entry(entry),
+    }
+}
+
+//Upgrade an older record to the last version without the information score, `None` when it is
+//already there or newer.
+pub fn upgrade_record(bytes: &[u8]) -> StorageResult<Option<Vec<u8>>> {
+    if get_record_version(bytes)? >= WITHOUT_INFORMATION_SCORE_VERSION {
+        return Ok(None);
+    }
+    let entry = decode_entry(bytes)?;
+    Ok(Some(encode_entry_version(
+        &entry,
+        WITHOUT_INFORMATION_SCORE_VERSION,
+    )?))
+}
+
+fn encode_entry_version(entry: &Entry, version: u32) -> StorageResult<Vec<u8>> {
+    let record = EntryRecord { version, entry };
+    to_vec(&record).map_err(|error| StorageError::Serialization(error.to_string()))
+}
+
+//Decode a stored record, migrating it in memory when it was written by an older version. The
+//records of the current version are decoded once, the generic CBOR value is only built for the
+//legacy and older records.
+pub fn decode_entry(bytes: &[u8]) -> StorageResult<Entry> {
+    if let Ok(record) = from_slice::<StoredEntryRecord>(bytes) {
+        if record.version == ENTRY_SCHEMA_VERSION {
+            return Ok(record.entry);
+        }
+    }
+
+    let (version, entry) = decode_record(bytes)?;
+    let entry = migrate_entry(version, entry)?;
+    from_value(entry).map_err(|error| StorageError::Serialization(error.to_string()))
+}
+
+//Schema version of a stored record.
+pub fn get_record_version(bytes: &[u8]) -> StorageResult<u32> {
+    if let Ok(record) = from_slice::<StoredRecordVersion>(bytes) {
+        return Ok(record.version);
+    }
+    Ok(decode_record(bytes)?.0)
+}
+
+fn decode_record(bytes: &[u8]) -> StorageResult<(u32, Value)> {
+    let value: Value =
+        from_slice(bytes).map_err(|error| StorageError::Serialization(error.to_string()))?;
+
+    if let Value::Map(fields) = &value {
+        let version = fields.get(&Value::Text(VERSION_FIELD.to_string()));
+        let entry = fields.get(&Value::Text(ENTRY_FIELD.to_string()));
+        if let (Some(Value::Integer(version)), Some(entry), 2) = (version, entry, fields.len()) {
+            return match u32::try_from(*version) {
+                Ok(version) => Ok((version, entry.clone())),
+                Err(_) => Err(StorageError::Serialization(format!(
+                    "invalid entry schema version {}",
+                    version
+                ))),
+            };
+        }
+    }
+
+    Ok((LEGACY_SCHEMA_VERSION, value))
+}
+
+fn migrate_entry(version: u32, entry: Value) -> StorageResult<Value> {
+    if version > ENTRY_SCHEMA_VERSION {
+        return Err(StorageError::Serialization(format!(
+            "unsupported entry schema version {}, expected at most {}",
+            version, ENTRY_SCHEMA_VERSION
+        )));
+    }
+
+    let mut entry = entry;
+    for current in version..ENTRY_SCHEMA_VERSION {
+        let migration = match MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == current)
+        {
+            Some(migration) => migration,
+            None => {
+                return Err(StorageError::Serialization(format!(
+                    "no migration from the entry schema version {}",
+                    current
+                )))
+            }
+        };
+        entry = (migration.migrate)(entry)?;
+    }
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn get_entry() -> Entry {
+        Entry::new(
+            Some(120.5),
+            Some(110.0),
+            None,
+            Some(100.0),
+            Some("751010101".to_string()),
+            Some("Saint-Germain".to_string()),
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    fn get_record(version: Value, entry: &Entry) -> Vec<u8> {
+        let mut fields = BTreeMap::new();
+        fields.insert(Value::Text(VERSION_FIELD.to_string()), version);
+        fields.insert(
+            Value::Text(ENTRY_FIELD.to_string()),
+            serde_cbor::value::to_value(entry).unwrap(),
+        );
+        to_vec(&Value::Map(fields)).unwrap()
+    }
+
+    fn assert_same_entry(decoded: &Entry, entry: &Entry) {
+        assert_eq!(
+            serde_cbor::value::to_value(decoded).unwrap(),
+            serde_cbor::value::to_value(entry).unwrap()
+        );
+    }
+
+    #[test]
+    fn decodes_the_current_version() {
+        let entry = get_entry();
+        let bytes = encode_entry(&entry).unwrap();
+        assert_eq!(get_record_version(&bytes).unwrap(), ENTRY_SCHEMA_VERSION);
+        assert_same_entry(&decode_entry(&bytes).unwrap(), &entry);
+    }
+
+    #[test]
+    fn migrates_legacy_records_to_the_current_version() {
+        let entry = get_entry();
+        let bytes = to_vec(&entry).unwrap();
+        assert_eq!(get_record_version(&bytes).unwrap(), LEGACY_SCHEMA_VERSION);
+
+        let decoded = decode_entry(&bytes).unwrap();
+        assert_same_entry(&decoded, &entry);
+        assert!(decoded.territory.is_none());
+        let bytes = encode_entry(&decoded).unwrap();
+        assert_eq!(get_record_version(&bytes).unwrap(), ENTRY_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn migrates_records_of_every_older_version() {
+        let entry = get_entry();
+        for version in 1..ENTRY_SCHEMA_VERSION {
+            let bytes = get_record(Value::Integer(version.into()), &entry);
+            assert_eq!(get_record_version(&bytes).unwrap(), version);
+            assert_same_entry(&decode_entry(&bytes).unwrap(), &entry);
+        }
+    }
+
+    #[test]
+    fn older_records_stop_before_the_information_score() {
+        let entry = get_entry();
+        let legacy = to_vec(&entry).unwrap();
+        let upgraded = upgrade_record(&legacy).unwrap().unwrap();
+        assert_eq!(
+            get_record_version(&upgraded).unwrap(),
+            WITHOUT_INFORMATION_SCORE_VERSION
+        );
+        assert_same_entry(&decode_entry(&upgraded).unwrap(), &entry);
+        assert!(upgrade_record(&upgraded).unwrap().is_none());
+        assert!(upgrade_record(&encode_entry(&entry).unwrap())
+            .unwrap()
+            .is_none());
+
+        let rewritten = reencode_entry(&entry, &legacy).unwrap();
+        assert_eq!(
+            get_record_version(&rewritten).unwrap(),
+            WITHOUT_INFORMATION_SCORE_VERSION
+        );
+        let current = encode_entry(&entry).unwrap();
+        let rewritten = reencode_entry(&entry, &current).unwrap();
+        assert_eq!(
+            get_record_version(&rewritten).unwrap(),
+            ENTRY_SCHEMA_VERSION
+        );
+    }
+
+    #[test]
+    fn refuses_newer_and_invalid_versions() {
+        let entry = get_entry();
+        let newer = get_record(Value::Integer((ENTRY_SCHEMA_VERSION + 1).into()), &entry);
+        assert!(decode_entry(&newer).is_err());
+
+        let overflowing = get_record(Value::Integer(u32::MAX as i128 + 1), &entry);
+        assert!(get_record_version(&overflowing).is_err());
+        assert!(decode_entry(&overflowing).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use domain::core::entry::*;
+use domain::core::flat_entry::{get_scores, scores_to_entry, FLAT_SCORE_COLUMNS};
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, Row};
+
+//Text columns of the entries table, before the FLAT_SCORE_COLUMNS.
+pub const KEY_COLUMNS: [&str; 2] = ["iris_code", "iris_code_designation"];
+
+//Columns of the territory of the entries, after the scores.
+pub const TERRITORY_COLUMNS: [&str; 4] = ["region", "department", "epci", "insee_com"];
+
+//Position of the first territory column.
+const TERRITORY_START: usize = KEY_COLUMNS.len() + FLAT_SCORE_COLUMNS.len();
+
+fn get_columns() -> Vec<&'static str> {
+    KEY_COLUMNS
+        .iter()
+        .chain(FLAT_SCORE_COLUMNS.iter())
+        .chain(TERRITORY_COLUMNS.iter())
+        .copied()
+        .collect()
+}
+
+fn get_column_definition(position: usize, column: &str) -> String {
+    match position {
+        0 => format!("{} TEXT PRIMARY KEY NOT NULL", column),
+        1 => format!("{} TEXT", column),
+        _ if position >= TERRITORY_START => format!("{} TEXT", column),
+        _ => format!("{} REAL", column),
+    }
+}
+
+pub fn create_entries_table_sql() -> String {
+    let columns: Vec<String> = get_columns()
+        .iter()
+        .enumerate()
+        .map(|(position, column)| get_column_definition(position, column))
+        .collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS entries ({})",
+        columns.join(", ")
+    )
+}
+
+//Add the columns missing from an entries table created by an older version (the territory, the
+//information score), their values stay NULL.
+pub fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+    let mut statement = connection.prepare("PRAGMA table_info(entries)")?;
+    let columns = statement
+        .query_map(params![], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    for (position, column) in get_columns().iter().enumerate() {
+        if !columns.iter().any(|existing| existing == column) {
+            connection.execute(
+                &format!(
+                    "ALTER TABLE entries ADD COLUMN {}",
+                    get_column_definition(position, column)
+                ),
+                params![],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn select_entries_sql() -> String {
+    format!("SELECT {} FROM entries", get_columns().join(", "))
+}
+
+pub fn insert_entry_sql() -> String {
+    let columns = get_columns();
+    let placeholders: Vec<String> = (1..=columns.len())
+        .map(|position| format!("?{}", position))
+        .collect();
+    format!(
+        "INSERT OR REPLACE INTO entries ({}) VALUES ({})",
+        columns.join(", "),
+        placeholders.join(", ")
+    )
+}
+
+//Insert the entry with the query of `insert_entry_sql`.
+pub fn execute_insert(
+    statement: &mut rusqlite::Statement,
+    iris_code: &str,
+    entry: &Entry,
+) -> rusqlite::Result<usize> {
+    let scores = get_scores(entry);
+    let territory: Vec<Option<&String>> = match &entry.territory {
+        Some(territory) => vec![
+            Some(&territory.region),
+            Some(&territory.department),
+            Some(&territory.epci),
+            Some(&territory.insee_com),
+        ],
+        None => vec![None; TERRITORY_COLUMNS.len()],
+    };
+    let mut values: Vec<&dyn ToSql> = vec![&iris_code, &entry.iris_code_designation];
+    values.extend(scores.iter().map(|score| score as &dyn ToSql));
+    values.extend(territory.iter().map(|key| key as &dyn ToSql));
+    statement.execute(values)
+}
+
+//Rebuild an entry from a row selected with `select_entries_sql`.
+pub fn row_to_entry(row: &Row) -> rusqlite::Result<Entry> {
+    let mut scores: Vec<Option<f64>> = Vec::with_capacity(FLAT_SCORE_COLUMNS.len());
+    for position in KEY_COLUMNS.len()..TERRITORY_START {
+        scores.push(row.get(position)?);
+    }
+    let mut keys: Vec<Option<String>> = Vec::with_capacity(TERRITORY_COLUMNS.len());
+    for position in TERRITORY_START..TERRITORY_START + TERRITORY_COLUMNS.len() {
+        keys.push(row.get(position)?);
+    }
+    //The entries written before the territories were stored have NULL keys.
+    let territory = match keys.iter().all(Option::is_none) {
+        true => None,
+        false => {
+            let key = |position: usize| keys[position].clone().unwrap_or_default();
+            Some(Territory {
+                region: key(0),
+                department: key(1),
+                epci: key(2),
+                insee_com: key(3),
+            })
+        }
+    };
+
+    let mut entry = scores_to_entry(row.get(0)?, row.get(1)?, &scores);
+    entry.territory = territory;
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_table_columns(connection: &Connection) -> Vec<String> {
+        let mut statement = connection.prepare("PRAGMA table_info(entries)").unwrap();
+        let columns = statement
+            .query_map(params![], |row| row.get::<_, String>(1))
+            .unwrap();
+        columns.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn missing_columns_are_added_to_an_older_table() {
+        let connection = Connection::open_in_memory().unwrap();
+        //Table of a version without the territory and the information score.
+        let old_columns: Vec<String> = get_columns()
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| {
+                **column != "information_score" && !TERRITORY_COLUMNS.contains(column)
+            })
+            .map(|(position, column)| get_column_definition(position, column))
+            .collect();
+        connection
+            .execute_batch(&format!(
+                "CREATE TABLE entries ({}); INSERT INTO entries (iris_code, global) VALUES ('620410101', 101.5);",
+                old_columns.join(", ")
+            ))
+            .unwrap();
+
+        add_missing_columns(&connection).unwrap();
+        let mut columns = get_table_columns(&connection);
+        columns.sort();
+        let mut expected: Vec<String> = get_columns()
+            .iter()
+            .map(|column| column.to_string())
+            .collect();
+        expected.sort();
+        assert_eq!(columns, expected);
+
+        //The older rows are read without the new values.
+        let entry = connection
+            .query_row(&select_entries_sql(), params![], row_to_entry)
+            .unwrap();
+        assert_eq!(entry.global, Some(101.5));
+        assert!(entry.territory.is_none());
+        assert!(entry.information_access.is_none());
+
+        //Adding them again changes nothing.
+        add_missing_columns(&connection).unwrap();
+        assert_eq!(get_table_columns(&connection).len(), expected.len());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{to_storage_result, SqliteStorage};
+use domain::core::entry::{GeoLoc, Iris};
+use domain::storage::error::*;
+use domain::storage::traits::IndexStoragePostalTrait;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+//Postal codes index read from the postal_codes table.
+pub struct SqliteIndexStoragePostal {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteIndexStoragePostal {
+    pub fn new(storage: &SqliteStorage) -> Self {
+        SqliteIndexStoragePostal {
+            connection: storage.connection.clone(),
+        }
+    }
+
+    fn get_connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    fn query_names(&self, sql: &str, arguments: &[&str]) -> StorageResult<Vec<String>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(connection.prepare(sql))?;
+        let rows = to_storage_result(statement.query_map(arguments, |row| row.get(0)))?;
+        to_storage_result(rows.collect())
+    }
+}
+
+fn row_to_iris(row: &Row) -> rusqlite::Result<Iris> {
+    let lat: Option<f64> = row.get(1)?;
+    let long: Option<f64> = row.get(2)?;
+    let geo_loc = match (lat, long) {
+        (Some(lat), Some(long)) => Some(GeoLoc::new(lat, long)),
+        _ => None,
+    };
+    Ok(Iris::new(row.get(0)?, geo_loc))
+}
+
+impl IndexStoragePostalTrait for SqliteIndexStoragePostal {
+    fn search_on_key(
+        &self,
+        query: String,
+        start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        let (start, end) = match start_with {
+            Some(value) => (value.to_string(), format!("{}{}", value, "z")),
+            None => ("0".to_string(), String::from(char::MAX)),
+        };
+
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(connection.prepare(
+            "SELECT name FROM postal_codes WHERE name >= ?1 AND name <= ?2 ORDER BY name",
+        ))?;
+        let rows = to_storage_result(statement.query_map(params![start, end], |row| row.get(0)))?;
+        let keys: Vec<String> = to_storage_result(rows.collect())?;
+
+        let query = query.to_uppercase();
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.contains(&query))
+            .collect())
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Iris>> {
+        let connection = self.get_connection();
+        to_storage_result(
+            connection
+                .query_row(
+                    "SELECT insee_code, lat, long FROM postal_codes WHERE name = ?1",
+                    params![value],
+                    row_to_iris,
+                )
+                .optional(),
+        )
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<Iris>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(
+            connection.prepare("SELECT insee_code, lat, long FROM postal_codes ORDER BY name"),
+        )?;
+        let rows = to_storage_result(statement.query_map(params![], row_to_iris))?;
+        to_storage_result(rows.collect())
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        let connection = self.get_connection();
+        let mut statement =
+            to_storage_result(connection.prepare("SELECT name FROM postal_codes ORDER BY name"))?;
+        let rows = to_storage_result(statement.query_map(params![], |row| row.get(0)))?;
+        to_storage_result(rows.collect())
+    }
+
+    fn find_keys(&self, code: String) -> StorageResult<Vec<String>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(
+            connection.prepare("SELECT name FROM postal_codes WHERE insee_code = ?1 ORDER BY name"),
+        )?;
+        let rows = to_storage_result(statement.query_map(params![code], |row| row.get(0)))?;
+        to_storage_result(rows.collect())
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        let connection = self.get_connection();
+        to_storage_result(
+            connection.execute("DELETE FROM postal_codes WHERE name = ?1", params![key]),
+        )?;
+        Ok(())
+    }
+
+    fn search_prefix(&self, prefix: String) -> StorageResult<Vec<String>> {
+        self.query_names(
+            "SELECT name FROM postal_codes WHERE substr(name, 1, length(?1)) = ?1 ORDER BY name",
+            &[&prefix],
+        )
+    }
+
+    fn search_range(&self, start: String, end: String) -> StorageResult<Vec<String>> {
+        self.query_names(
+            "SELECT name FROM postal_codes WHERE name >= ?1 AND name < ?2 ORDER BY name",
+            &[&start, &end],
+        )
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{to_storage_result, SqliteStorage};
+use domain::storage::error::*;
+use domain::storage::traits::IndexStorageTrait;
+use rusqlite::{params, Connection};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+//Territory index (`regions`, `departments`, `insee_coms`...) read from the territories table.
+pub struct SqliteIndexStorage {
+    connection: Arc<Mutex<Connection>>,
+    name: String,
+}
+
+impl SqliteIndexStorage {
+    pub fn new(storage: &SqliteStorage, name: String) -> Self {
+        SqliteIndexStorage {
+            connection: storage.connection.clone(),
+            name,
+        }
+    }
+
+    fn get_connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    fn query_strings(&self, sql: &str, arguments: &[&str]) -> StorageResult<Vec<String>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(connection.prepare(sql))?;
+        let rows = to_storage_result(statement.query_map(arguments, |row| row.get(0)))?;
+        to_storage_result(rows.collect())
+    }
+}
+
+impl IndexStorageTrait for SqliteIndexStorage {
+    fn search_on_key(
+        &self,
+        query: String,
+        start_with: Option<String>,
+    ) -> StorageResult<Vec<String>> {
+        let (start, end) = match start_with {
+            Some(value) => (value.to_string(), format!("{}{}", value, "z")),
+            None => ("0".to_string(), String::from(char::MAX)),
+        };
+        let keys = self.query_strings(
+            "SELECT DISTINCT name FROM territories WHERE kind = ?1 AND name >= ?2 AND name <= ?3 ORDER BY name",
+            &[&self.name, &start, &end],
+        )?;
+
+        let query = query.to_uppercase();
+        Ok(keys
+            .into_iter()
+            .filter(|key| key.contains(&query))
+            .collect())
+    }
+
+    fn get_index(&self, value: String) -> StorageResult<Option<Vec<String>>> {
+        let members = self.query_strings(
+            "SELECT member FROM territories WHERE kind = ?1 AND name = ?2 ORDER BY position",
+            &[&self.name, &value],
+        )?;
+
+        match members.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(members)),
+        }
+    }
+
+    fn get_all_values(&self) -> StorageResult<Vec<String>> {
+        self.query_strings(
+            "SELECT member FROM territories WHERE kind = ?1 ORDER BY name, position",
+            &[&self.name],
+        )
+    }
+
+    fn get_all_keys(&self) -> StorageResult<Vec<String>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(
+            connection
+                .prepare("SELECT DISTINCT name FROM territories WHERE kind = ?1 ORDER BY name"),
+        )?;
+        let rows = to_storage_result(statement.query_map(params![self.name], |row| row.get(0)))?;
+        to_storage_result(rows.collect())
+    }
+
+    fn find_keys(&self, value: String) -> StorageResult<Vec<String>> {
+        self.query_strings(
+            "SELECT DISTINCT name FROM territories WHERE kind = ?1 AND member = ?2 ORDER BY name",
+            &[&self.name, &value],
+        )
+    }
+
+    fn update_index(&self, key: String, values: Vec<String>) -> StorageResult<()> {
+        let mut connection = self.get_connection();
+        let transaction = to_storage_result(connection.transaction())?;
+        to_storage_result(transaction.execute(
+            "DELETE FROM territories WHERE kind = ?1 AND name = ?2",
+            params![self.name, key],
+        ))?;
+        {
+            let mut statement = to_storage_result(transaction.prepare(
+                "INSERT OR IGNORE INTO territories (kind, name, member, position) VALUES (?1, ?2, ?3, ?4)",
+            ))?;
+            for (position, member) in values.iter().enumerate() {
+                to_storage_result(statement.execute(params![
+                    self.name,
+                    key,
+                    member,
+                    position as i64
+                ]))?;
+            }
+        }
+        to_storage_result(transaction.commit())
+    }
+
+    fn delete_index(&self, key: String) -> StorageResult<()> {
+        let connection = self.get_connection();
+        to_storage_result(connection.execute(
+            "DELETE FROM territories WHERE kind = ?1 AND name = ?2",
+            params![self.name, key],
+        ))?;
+        Ok(())
+    }
+}
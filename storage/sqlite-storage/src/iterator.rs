@@ -0,0 +1,62 @@
+use crate::entry::{row_to_entry, select_entries_sql};
+use crate::to_storage_result;
+use domain::core::entry::Entry;
+use domain::storage::error::*;
+use rusqlite::{params, Connection};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+const PAGE_SIZE: i64 = 500;
+
+//Iterate over the entries by pages ordered on the IRIS code, without holding the connection.
+pub struct SqliteEntryIterator {
+    connection: Arc<Mutex<Connection>>,
+    page: VecDeque<Entry>,
+    last_iris_code: String,
+    done: bool,
+}
+
+impl SqliteEntryIterator {
+    pub fn new(connection: Arc<Mutex<Connection>>) -> Self {
+        SqliteEntryIterator {
+            connection,
+            page: VecDeque::new(),
+            last_iris_code: String::new(),
+            done: false,
+        }
+    }
+
+    fn load_next_page(&mut self) -> StorageResult<()> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = to_storage_result(connection.prepare_cached(&format!(
+            "{} WHERE iris_code > ?1 ORDER BY iris_code LIMIT ?2",
+            select_entries_sql()
+        )))?;
+        let rows = to_storage_result(
+            statement.query_map(params![self.last_iris_code, PAGE_SIZE], row_to_entry),
+        )?;
+        for row in rows {
+            self.page.push_back(to_storage_result(row)?);
+        }
+
+        match self.page.back().and_then(|entry| entry.iris_code.clone()) {
+            Some(iris_code) => self.last_iris_code = iris_code,
+            None => self.done = true,
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for SqliteEntryIterator {
+    type Item = StorageResult<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.page.is_empty() && !self.done {
+            if let Err(error) = self.load_next_page() {
+                self.done = true;
+                return Some(Err(error));
+            }
+        }
+        self.page.pop_front().map(Ok)
+    }
+}
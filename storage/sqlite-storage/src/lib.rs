@@ -0,0 +1,377 @@
+use domain::core::entry::{Entry, Iris};
+use domain::storage::error::*;
+use domain::storage::traits::{EntryIterator, EntryStorageTrait};
+use entry::*;
+use iterator::SqliteEntryIterator;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub mod entry;
+pub mod extended;
+pub mod index;
+pub mod iterator;
+
+//Stay under the SQLite limit of host parameters in a single query.
+const MAX_QUERY_PARAMETERS: usize = 500;
+
+const TERRITORIES_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS territories (
+    kind TEXT NOT NULL,
+    name TEXT NOT NULL,
+    member TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    PRIMARY KEY (kind, name, member)
+)";
+
+const POSTAL_CODES_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS postal_codes (
+    name TEXT PRIMARY KEY NOT NULL,
+    insee_code TEXT,
+    lat REAL,
+    long REAL
+)";
+
+//Entries, territories and postal codes stored in a single SQLite file.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStorage {
+    pub fn new(path: String) -> StorageResult<Self> {
+        let connection = to_storage_result(Connection::open(path))?;
+        to_storage_result(connection.execute_batch(&format!(
+            "{}; {}; {};",
+            create_entries_table_sql(),
+            TERRITORIES_TABLE_SQL,
+            POSTAL_CODES_TABLE_SQL
+        )))?;
+        to_storage_result(add_missing_columns(&connection))?;
+
+        Ok(SqliteStorage {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn get_connection(&self) -> MutexGuard<'_, Connection> {
+        self.connection.lock().unwrap()
+    }
+
+    //Write all the entries in a single transaction.
+    pub fn create_entries(&self, entries: &[Entry]) -> StorageResult<()> {
+        let mut connection = self.get_connection();
+        let transaction = to_storage_result(connection.transaction())?;
+        {
+            let mut statement = to_storage_result(transaction.prepare(&insert_entry_sql()))?;
+            for entry in entries {
+                let iris_code = match &entry.iris_code {
+                    Some(iris_code) => iris_code,
+                    None => return Err(StorageError::CreationImpossible),
+                };
+                to_storage_result(execute_insert(&mut statement, iris_code, entry))?;
+            }
+        }
+        to_storage_result(transaction.commit())
+    }
+
+    //Replace the whole content of a territory index (`regions`, `departments`...).
+    pub fn create_index(
+        &self,
+        name: &str,
+        index: &BTreeMap<String, Vec<String>>,
+    ) -> StorageResult<()> {
+        let mut connection = self.get_connection();
+        let transaction = to_storage_result(connection.transaction())?;
+        to_storage_result(
+            transaction.execute("DELETE FROM territories WHERE kind = ?1", params![name]),
+        )?;
+        {
+            let mut statement = to_storage_result(transaction.prepare(
+                "INSERT OR IGNORE INTO territories (kind, name, member, position) VALUES (?1, ?2, ?3, ?4)",
+            ))?;
+            for (key, members) in index {
+                for (position, member) in members.iter().enumerate() {
+                    to_storage_result(statement.execute(params![
+                        name,
+                        key,
+                        member,
+                        position as i64
+                    ]))?;
+                }
+            }
+        }
+        to_storage_result(transaction.commit())
+    }
+
+    //Replace the whole content of the postal codes index.
+    pub fn create_postal_index(&self, index: &BTreeMap<String, Iris>) -> StorageResult<()> {
+        let mut connection = self.get_connection();
+        let transaction = to_storage_result(connection.transaction())?;
+        to_storage_result(transaction.execute("DELETE FROM postal_codes", params![]))?;
+        {
+            let mut statement = to_storage_result(transaction.prepare(
+                "INSERT OR REPLACE INTO postal_codes (name, insee_code, lat, long) VALUES (?1, ?2, ?3, ?4)",
+            ))?;
+            for (key, iris) in index {
+                let (lat, long) = match &iris.geo_loc {
+                    Some(geo_loc) => (Some(geo_loc.lat), Some(geo_loc.long)),
+                    None => (None, None),
+                };
+                to_storage_result(statement.execute(params![key, iris.code, lat, long]))?;
+            }
+        }
+        to_storage_result(transaction.commit())
+    }
+
+    fn find_entry(&self, iris_code: &str) -> StorageResult<Option<Entry>> {
+        let connection = self.get_connection();
+        to_storage_result(
+            connection
+                .query_row(
+                    &format!("{} WHERE iris_code = ?1", select_entries_sql()),
+                    params![iris_code],
+                    row_to_entry,
+                )
+                .optional(),
+        )
+    }
+}
+
+impl EntryStorageTrait for SqliteStorage {
+    fn get_all(&self) -> StorageResult<Vec<Entry>> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(
+            connection.prepare(&format!("{} ORDER BY iris_code", select_entries_sql())),
+        )?;
+        let rows = to_storage_result(statement.query_map(params![], row_to_entry))?;
+        to_storage_result(rows.collect())
+    }
+
+    fn iter_entries(&self) -> StorageResult<EntryIterator<'_>> {
+        Ok(Box::new(SqliteEntryIterator::new(self.connection.clone())))
+    }
+
+    fn get_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        self.find_entry(&iris_code)
+    }
+
+    fn get_entries(&self, iris_codes: &[String]) -> StorageResult<HashMap<String, Entry>> {
+        let connection = self.get_connection();
+        let mut entries: HashMap<String, Entry> = HashMap::with_capacity(iris_codes.len());
+        for chunk in iris_codes.chunks(MAX_QUERY_PARAMETERS) {
+            let placeholders: Vec<&str> = chunk.iter().map(|_| "?").collect();
+            let mut statement = to_storage_result(connection.prepare(&format!(
+                "{} WHERE iris_code IN ({})",
+                select_entries_sql(),
+                placeholders.join(", ")
+            )))?;
+            let rows = to_storage_result(statement.query_map(chunk, row_to_entry))?;
+            for row in rows {
+                let entry = to_storage_result(row)?;
+                if let Some(iris_code) = entry.iris_code.clone() {
+                    entries.insert(iris_code, entry);
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn get_national_entry(&self) -> StorageResult<Option<Entry>> {
+        let connection = self.get_connection();
+        let first_entry = to_storage_result(
+            connection
+                .query_row(
+                    &format!("{} ORDER BY iris_code LIMIT 1", select_entries_sql()),
+                    params![],
+                    row_to_entry,
+                )
+                .optional(),
+        )?;
+        Ok(first_entry.map(|entry| entry.to_national_entry()))
+    }
+
+    fn get_region_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        Ok(self
+            .find_entry(&iris_code)?
+            .map(|entry| entry.to_regional_entry()))
+    }
+
+    fn get_department_entry(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        Ok(self
+            .find_entry(&iris_code)?
+            .map(|entry| entry.to_departmental_entry()))
+    }
+
+    fn create(&self, iris_code: String, entry: Entry) -> StorageResult<()> {
+        let connection = self.get_connection();
+        let mut statement = to_storage_result(connection.prepare_cached(&insert_entry_sql()))?;
+        to_storage_result(execute_insert(&mut statement, &iris_code, &entry))?;
+        Ok(())
+    }
+
+    fn update(&self, iris_code: String, entry: Entry) -> StorageResult<Option<Entry>> {
+        let previous = match self.find_entry(&iris_code)? {
+            Some(previous) => previous,
+            None => return Ok(None),
+        };
+        self.create(iris_code, entry)?;
+        Ok(Some(previous))
+    }
+
+    fn update_entries(&self, entries: &[Entry]) -> StorageResult<()> {
+        self.create_entries(entries)
+    }
+
+    fn delete(&self, iris_code: String) -> StorageResult<Option<Entry>> {
+        let deleted = match self.find_entry(&iris_code)? {
+            Some(deleted) => deleted,
+            None => return Ok(None),
+        };
+        let connection = self.get_connection();
+        to_storage_result(connection.execute(
+            "DELETE FROM entries WHERE iris_code = ?1",
+            params![iris_code],
+        ))?;
+        Ok(Some(deleted))
+    }
+}
+
+//Map the SQLite errors on the storage errors.
+pub fn to_storage_result<T>(result: rusqlite::Result<T>) -> StorageResult<T> {
+    result.map_err(|error| StorageError::Database(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extended::SqliteIndexStoragePostal;
+    use crate::index::SqliteIndexStorage;
+    use domain::core::entry::*;
+    use domain::core::flat_entry::get_scores;
+    use domain::storage::traits::{IndexStoragePostalTrait, IndexStorageTrait};
+
+    fn get_entry(iris_code: &str, territory: Option<Territory>) -> Entry {
+        let entry = Entry::new(
+            Some(101.5),
+            Some(100.0),
+            Some(99.0),
+            Some(98.0),
+            Some(iris_code.to_string()),
+            Some("Arras Centre".to_string()),
+            Some(
+                InformationAccess::new(
+                    Some(1.0),
+                    Some(2.0),
+                    Some(3.0),
+                    Some(4.0),
+                    Some(0.25),
+                    Some(0.5),
+                    Some(0.75),
+                    None,
+                )
+                .with_information_score(Some(5.0)),
+            ),
+            Some(NumericInterfacesAccess::new(
+                Some(6.0),
+                None,
+                None,
+                None,
+                Some(0.9),
+                Some(1.0),
+                Some(0.125),
+                Some(21000.0),
+            )),
+            Some(AdministrativeCompetencies::new(
+                Some(7.0),
+                None,
+                None,
+                None,
+                Some(0.5),
+                Some(0.25),
+            )),
+            Some(NumericCompetencies::new(
+                Some(8.0),
+                None,
+                None,
+                None,
+                Some(0.125),
+                Some(0.375),
+            )),
+        );
+        match territory {
+            Some(territory) => entry.with_territory(territory),
+            None => entry,
+        }
+    }
+
+    fn get_territory() -> Territory {
+        Territory {
+            region: "Hauts-de-France".to_string(),
+            department: "62 - Pas-de-Calais".to_string(),
+            epci: "200033579 - CU d'Arras".to_string(),
+            insee_com: "62041".to_string(),
+        }
+    }
+
+    #[test]
+    fn entries_are_read_back_with_their_scores_and_territory() {
+        let storage = SqliteStorage::new(":memory:".to_string()).unwrap();
+        let with_territory = get_entry("620410101", Some(get_territory()));
+        let without_territory = get_entry("620410102", None);
+        storage
+            .create_entries(&[with_territory.clone(), without_territory.clone()])
+            .unwrap();
+
+        let entries = storage.get_all().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(get_scores(&entries[0]), get_scores(&with_territory));
+        assert_eq!(
+            entries[0].iris_code_designation.as_deref(),
+            Some("Arras Centre")
+        );
+        assert_eq!(entries[0].territory, Some(get_territory()));
+        assert_eq!(get_scores(&entries[1]), get_scores(&without_territory));
+        assert_eq!(entries[1].territory, None);
+
+        assert!(storage.delete("620410102".to_string()).unwrap().is_some());
+        assert!(storage
+            .get_entry("620410102".to_string())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn territories_and_postal_codes_are_read_back() {
+        let storage = SqliteStorage::new(":memory:".to_string()).unwrap();
+        let mut regions = BTreeMap::new();
+        regions.insert(
+            "HAUTS-DE-FRANCE".to_string(),
+            vec!["620410102".to_string(), "620410101".to_string()],
+        );
+        storage.create_index("regions", &regions).unwrap();
+        let mut postal_codes = BTreeMap::new();
+        postal_codes.insert(
+            "ARRAS - 62000".to_string(),
+            Iris::new(Some("62041".to_string()), Some(GeoLoc::new(50.29, 2.78))),
+        );
+        storage.create_postal_index(&postal_codes).unwrap();
+
+        let index = SqliteIndexStorage::new(&storage, "regions".to_string());
+        //The members keep their order.
+        assert_eq!(
+            index.get_index("HAUTS-DE-FRANCE".to_string()).unwrap(),
+            Some(vec!["620410102".to_string(), "620410101".to_string()])
+        );
+        assert_eq!(
+            index.get_all_keys().unwrap(),
+            vec!["HAUTS-DE-FRANCE".to_string()]
+        );
+        let postal_index = SqliteIndexStoragePostal::new(&storage);
+        let iris = postal_index
+            .get_index("ARRAS - 62000".to_string())
+            .unwrap()
+            .unwrap();
+        assert_eq!(iris.code.as_deref(), Some("62041"));
+        let geo_loc = iris.geo_loc.unwrap();
+        assert_eq!((geo_loc.lat, geo_loc.long), (50.29, 2.78));
+    }
+}